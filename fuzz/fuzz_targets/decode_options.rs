@@ -0,0 +1,19 @@
+//! Decodes arbitrary TCP option areas, and checks that what decodes encodes back to the same options.
+//!
+//! Run with `cargo +nightly fuzz run decode_options`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tcp_test::tcp::packet::options::{decode_options, encode_options, MAX_OPTIONS_LENGTH};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() > MAX_OPTIONS_LENGTH {
+        return;
+    }
+
+    // Decoding drops NOPs and everything after an End of Option List, so the options fit again once encoded
+    if let Ok(options) = decode_options(data) {
+        let encoded = encode_options(&options).expect("decoded options do not fit into the header");
+        assert_eq!(decode_options(&encoded).as_ref(), Ok(&options));
+    }
+});
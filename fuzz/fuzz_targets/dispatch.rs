@@ -0,0 +1,87 @@
+//! Feeds arbitrary packets to a connection table with a listener, as the receive loop does.
+//!
+//! The first byte selects the IP version, odd for IPv6. The packets follow, each behind its length as two bytes
+//! in network byte order. Segments may open connections on `LISTEN_PORT` and are then handed to them.
+//! The table sends over a link that drops every packet, so nothing leaves the process, and processes segments
+//! whatever their checksum, which the fuzzer could hardly ever get right. Segments of no connection are answered
+//! with RSTs, so building those is fuzzed as well.
+//!
+//! Run with `cargo +nightly fuzz run dispatch`.
+#![no_main]
+
+use std::net::Ipv6Addr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use tcp_test::tcp::connection_table::{ChecksumPolicy, ConnectionTable, UnknownSegmentPolicy};
+use tcp_test::tcp::link::device::Link;
+use tcp_test::tcp::main_loop::parse_segment;
+use tcp_test::tcp::packet::data::RemoteSockaddr;
+use tcp_test::tcp::packet::ip_header::{IpHeader, IpVersion, Ipv6Header};
+use tcp_test::tcp::settings::Settings;
+
+const LISTEN_PORT: u16 = 7;
+
+/// Drops every packet sent and never receives one, segments are handed to the table directly.
+struct DiscardLink;
+
+impl Link for DiscardLink {
+    fn supports(&self, _version: IpVersion) -> bool {
+        true
+    }
+
+    fn send(&self, _ip_head: &IpHeader, packet: &[u8], _remote: &RemoteSockaddr) -> isize {
+        packet.len() as isize
+    }
+
+    fn receive(&self) -> Option<(Bytes, Option<IpHeader>)> {
+        None
+    }
+
+    fn set_receive_timeout(&self, _timeout: Duration) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, mut packets)) = data.split_first() else {
+        return;
+    };
+    let version = if selector & 1 == 0 { IpVersion::V4 } else { IpVersion::V6 };
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+    let _guard = runtime.enter();
+    let table = Arc::new(
+        ConnectionTable::new(Arc::new(DiscardLink))
+            .with_checksum_policy(ChecksumPolicy::Count)
+            .with_unknown_segment_policy(UnknownSegmentPolicy::Reset)
+    );
+    table.listen(LISTEN_PORT, Settings::default());
+
+    while packets.len() >= 2 {
+        let length = (u16::from_be_bytes([packets[0], packets[1]]) as usize).min(packets.len() - 2);
+        let packet = Bytes::copy_from_slice(&packets[2..2 + length]);
+        packets = &packets[2 + length..];
+
+        let ip_head = match version {
+            IpVersion::V4 => None,
+            IpVersion::V6 => Some(IpHeader::V6(Ipv6Header {
+                source: Ipv6Addr::LOCALHOST,
+                destination: Ipv6Addr::LOCALHOST,
+                payload_length: packet.len() as u16,
+            })),
+        };
+        if let Ok(receive_data) = parse_segment(packet, ip_head) {
+            table.dispatch(receive_data);
+        }
+
+        // Lets the workers of the connections see the segment
+        runtime.block_on(async {
+            for _ in 0..8 {
+                tokio::task::yield_now().await;
+            }
+        });
+    }
+});
@@ -0,0 +1,41 @@
+//! Pushes arbitrary bytes through the header views and the parser of the receive path.
+//!
+//! Run with `cargo +nightly fuzz run parse_packet`. A crashing input is written to `artifacts/parse_packet`, copying
+//! it to `corpus/parse_packet/seed-<name>` keeps it as a regression case.
+#![no_main]
+
+use std::net::Ipv6Addr;
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use tcp_test::tcp::main_loop::parse_segment;
+use tcp_test::tcp::packet::ip_header::{IpHeader, Ipv6Header};
+use tcp_test::tcp::packet::parser::{Ipv4Packet, TcpSegment};
+
+fn read_segment(segment: TcpSegment) {
+    assert_eq!(segment.header_length() + segment.payload().len(), segment.len());
+    let _ = (segment.source_port(), segment.destination_port(), segment.seq(), segment.ack());
+    let _ = (segment.flags(), segment.window(), segment.header());
+    let _ = segment.options();
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = Ipv4Packet::parse(data) {
+        assert!(packet.header_length() <= packet.total_length() && packet.total_length() <= data.len());
+        let _ = (packet.source(), packet.destination(), packet.header(), packet.is_checksum_valid());
+        if let Ok(segment) = packet.tcp() {
+            read_segment(segment);
+        }
+    }
+    if let Ok(segment) = TcpSegment::parse(data) {
+        read_segment(segment);
+    }
+
+    let _ = parse_segment(Bytes::copy_from_slice(data), None);
+    let ip_head = IpHeader::V6(Ipv6Header {
+        source: Ipv6Addr::LOCALHOST,
+        destination: Ipv6Addr::LOCALHOST,
+        payload_length: data.len() as u16,
+    });
+    let _ = parse_segment(Bytes::copy_from_slice(data), Some(ip_head));
+});
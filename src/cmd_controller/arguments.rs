@@ -0,0 +1,178 @@
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use tracing::Level;
+
+use tcp_test::tcp::congestion::congestion_control::CongestionAlgorithm;
+use tcp_test::tcp::connection_table::ChecksumPolicy;
+use tcp_test::tcp::packet::tcb::DEFAULT_RECEIVE_WINDOW;
+use tcp_test::tcp::settings::{Keepalive, Settings};
+
+/// A TCP stack over raw sockets or a TUN device.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Arguments {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// The address packets are sent from, the loopback address of the remote's IP version by default
+    #[arg(long, global = true, value_name = "ADDRESS")]
+    pub source_address: Option<IpAddr>,
+
+    /// Drives the connections over a TUN device of this name instead of raw sockets, the source address is then an
+    /// address of the device's network that only this stack owns
+    #[arg(long, global = true, value_name = "NAME", requires = "source_address")]
+    pub tun: Option<String>,
+
+    /// The kernel's address on the TUN device with the length of its network prefix, as `10.0.0.1/24`, once for
+    /// every IP version the device carries
+    #[arg(long, global = true, value_name = "ADDRESS/PREFIX", value_parser = parse_network, requires = "tun")]
+    pub tun_address: Vec<(IpAddr, u8)>,
+
+    /// The port packets are sent from, a random one by default
+    #[arg(long, global = true, value_name = "PORT")]
+    pub source_port: Option<u16>,
+
+    /// The most detailed level that is logged: error, warn, info, debug or trace
+    #[arg(long, global = true, value_name = "LEVEL", default_value_t = Level::INFO)]
+    pub log_level: Level,
+
+    /// The congestion control algorithm of the connections: newreno or cubic
+    #[arg(long, global = true, value_name = "ALGORITHM", default_value_t = CongestionAlgorithm::default())]
+    pub congestion: CongestionAlgorithm,
+
+    /// The receive window advertised to the remote, in bytes
+    #[arg(long, global = true, value_name = "BYTES", default_value_t = DEFAULT_RECEIVE_WINDOW)]
+    pub window: u16,
+
+    /// Writes every sent and received packet to a pcap file
+    #[arg(long, global = true, value_name = "FILE")]
+    pub capture: Option<PathBuf>,
+
+    /// Processes segments whose checksum does not match instead of dropping them, they are still counted
+    #[arg(long, global = true)]
+    pub accept_bad_checksums: bool,
+
+    /// Probes connections the remote was silent on for this many milliseconds, they are never probed by default
+    #[arg(long, global = true, value_name = "MILLISECONDS", value_parser = parse_milliseconds)]
+    pub keepalive: Option<Duration>,
+
+    /// How long to wait for the answer to a keepalive probe before the next is sent, in milliseconds
+    #[arg(long, global = true, value_name = "MILLISECONDS", default_value = "75000", value_parser = parse_milliseconds, requires = "keepalive")]
+    pub keepalive_interval: Duration,
+
+    /// How many keepalive probes may go unanswered before a connection is given up
+    #[arg(long, global = true, value_name = "COUNT", default_value_t = 9, requires = "keepalive")]
+    pub keepalive_count: u32,
+
+    /// What is appended to every line of the user input before it is sent
+    #[arg(long, global = true, value_enum, default_value_t = LineEnding::Lf)]
+    pub line_ending: LineEnding,
+}
+
+/// The line ending the user input is sent with, the line is otherwise sent exactly as typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`, as line based internet protocols expect
+    Crlf,
+    /// Nothing, for protocols that are not line based
+    None,
+}
+
+impl LineEnding {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+            LineEnding::None => b"",
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Connects to a remote and sends every line read from stdin
+    Connect {
+        /// The remote, as `ip:port` or `[ipv6]:port`
+        address: String,
+    },
+    /// Accepts connections on a port over IPv4 and IPv6
+    Listen {
+        port: u16,
+    },
+    /// Reports which ports of a host answer a SYN
+    Scan {
+        host: IpAddr,
+        /// The ports to probe, as `first-last` or a single port
+        #[arg(long, default_value = "1-1024", value_parser = parse_port_range)]
+        ports: RangeInclusive<u16>,
+        /// How long to wait for an answer to each SYN, in milliseconds
+        #[arg(long, value_name = "MILLISECONDS", default_value = "1000", value_parser = parse_milliseconds)]
+        timeout: Duration,
+    },
+    /// Plays our segments of a captured session against a server again and reports where its answers differ
+    Replay {
+        /// The pcap file of the session
+        recording: PathBuf,
+        /// The server, as `ip:port` or `[ipv6]:port`
+        address: SocketAddr,
+        /// How long to wait for the answers to each segment, in milliseconds
+        #[arg(long, value_name = "MILLISECONDS", default_value = "1000", value_parser = parse_milliseconds)]
+        timeout: Duration,
+    },
+}
+
+impl Arguments {
+    /// The settings every connection is created with.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            source_address: self.source_address,
+            receive_window: self.window,
+            congestion: self.congestion,
+            keepalive: self.keepalive.map(|idle| Keepalive {
+                idle,
+                interval: self.keepalive_interval,
+                count: self.keepalive_count,
+            }),
+        }
+    }
+
+    /// What the receive loops do with segments whose checksum does not match.
+    pub fn checksum_policy(&self) -> ChecksumPolicy {
+        if self.accept_bad_checksums {
+            ChecksumPolicy::Count
+        } else {
+            ChecksumPolicy::Drop
+        }
+    }
+}
+
+fn parse_port_range(range: &str) -> Result<RangeInclusive<u16>, String> {
+    let (first, last) = range.split_once('-').unwrap_or((range, range));
+    let first: u16 = first.parse().map_err(|_| format!("invalid port: {}", first))?;
+    let last: u16 = last.parse().map_err(|_| format!("invalid port: {}", last))?;
+    if first > last {
+        return Err(format!("{} is after {}", first, last));
+    }
+    Ok(first..=last)
+}
+
+fn parse_network(network: &str) -> Result<(IpAddr, u8), String> {
+    let (address, prefix_length) = network.split_once('/').ok_or_else(|| format!("{} has no prefix length", network))?;
+    let address: IpAddr = address.parse().map_err(|_| format!("invalid address: {}", address))?;
+    let prefix_length: u8 = prefix_length.parse().map_err(|_| format!("invalid prefix length: {}", prefix_length))?;
+    let maximum = if address.is_ipv4() { 32 } else { 128 };
+    if prefix_length > maximum {
+        return Err(format!("the prefix length of {} is above {}", address, maximum));
+    }
+    Ok((address, prefix_length))
+}
+
+fn parse_milliseconds(milliseconds: &str) -> Result<Duration, String> {
+    milliseconds.parse().map(Duration::from_millis).map_err(|_| format!("invalid number of milliseconds: {}", milliseconds))
+}
@@ -1,65 +1,69 @@
+use std::sync::Arc;
+
 use tokio::io;
 use tokio::io::{AsyncBufReadExt, BufReader, Stdin};
+use tracing::warn;
+
+use tcp_test::tcp::packet::data::Controller;
 
-use crate::tcp::packet::data::{Controller, SpacilProcessor};
+use crate::cmd_controller::arguments::LineEnding;
 
 // This function reads user input from the command line
-// It takes a mutable reference to a BufReader and a mutable reference to a String as parameters
-// It returns a Result with a String on success and an io::Error on failure
-async fn read_user_input(reader: &mut BufReader<Stdin>, buffer: &mut String) -> io::Result<String> {
-    reader.read_line(buffer).await?;
-    Ok(buffer.trim_end().to_string())
+// It takes a mutable reference to a BufReader and a mutable reference to a buffer as parameters
+// It returns a Result with the line, without its line ending, on success, None once stdin has reached its end,
+// and an io::Error on failure
+// The line is read as bytes, so anything that is not UTF-8 is sent as it is
+async fn read_user_input(reader: &mut BufReader<Stdin>, buffer: &mut Vec<u8>) -> io::Result<Option<Vec<u8>>> {
+    if reader.read_until(b'\n', buffer).await? == 0 {
+        return Ok(None);
+    }
+
+    let mut line = buffer.as_slice();
+    line = line.strip_suffix(b"\n").unwrap_or(line);
+    line = line.strip_suffix(b"\r").unwrap_or(line);
+    Ok(Some(line.to_vec()))
 }
 
 // This function listens for commands from the command line
-// It takes a Controller as a parameter
-// It does not return a value
-pub async fn commandline_listener(controller: Controller) {
+// It takes a function returning the connections the input goes to, which is a single one for the client
+// and every accepted connection for the listener, and the line ending every line is sent with
+// It returns once stdin has reached its end, which closes every connection like "exit" does
+pub async fn commandline_listener<F>(connections: F, line_ending: LineEnding)
+    where
+        F: Fn() -> Vec<Arc<Controller>>,
+{
     // Creating a new BufReader for stdin
     let mut reader = BufReader::new(io::stdin());
-    // Creating a new String to hold the user input
-    let mut buffer = String::new();
+    // Creating a new buffer to hold the user input
+    let mut buffer = Vec::new();
     // Looping indefinitely to continuously read user input
     loop {
         // Reading user input
-        let input = read_user_input(&mut reader, &mut buffer).await.unwrap();
+        let Some(input) = read_user_input(&mut reader, &mut buffer).await.unwrap() else {
+            connections().iter().for_each(|controller| controller.close());
+            return;
+        };
 
         // Matching the user input to perform different actions
-        match input.as_str() {
-            // If the user input is "exit", send a fin packet
-            "exit" => {
-                let mut packet = controller.make_packet_with_none().to_fin_packet(
-                    *controller.last_seq_number.read(),
-                    *controller.last_ack_seq_number.read()
-                );
-                let sent_size = controller.send_packet_spacial(&mut packet, SpacilProcessor::WaveHandshake);
-
-                tracing::info!("fin data send: {}, with size: {}", packet, sent_size);
+        match input.as_slice() {
+            // If the user input is "exit", close the connection once everything queued has been sent
+            b"exit" => {
+                connections().iter().for_each(|controller| controller.close());
             }
 
-            // If the user input is "close", send a data packet with "close" as the data
-            "close" => {
-                let mut packet = controller.make_packet_with_data("close").to_data_packet(
-                    *controller.last_seq_number.read(),
-                    *controller.last_ack_seq_number.read()
-                );
-
-                let sent_size = controller.send_packet_spacial(&mut packet, SpacilProcessor::WaveHandshake);
-                tracing::info!("input data send: {}, with size: {}", packet, sent_size);
-                buffer.clear();
-            }
-
-            // If the user input is anything else, send a data packet with the user input as the data
+            // Anything else, including "close", is written to the connection as a line
+            // The data goes out as soon as the remote's window allows it
             data => {
-                let mut packet = controller.make_packet_with_data(data.trim()).to_data_packet(
-                    *controller.last_seq_number.read(),
-                    *controller.last_ack_seq_number.read()
-                );
-
-                let sent_size = controller.send_packet(&mut packet);
-                tracing::info!("input data send: {}, with size: {}", packet, sent_size);
-                buffer.clear();
+                let mut line = data.to_vec();
+                line.extend_from_slice(line_ending.as_bytes());
+                for controller in connections() {
+                    if let Err(e) = controller.write(&line) {
+                        warn!("{}", e);
+                    }
+                }
             }
         }
+
+        buffer.clear();
     }
-}
\ No newline at end of file
+}
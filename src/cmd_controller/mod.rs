@@ -1 +1,3 @@
-pub mod cmd_controller;
\ No newline at end of file
+pub mod cmd_controller;
+pub mod arguments;
+pub mod scan;
\ No newline at end of file
@@ -0,0 +1,104 @@
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::Duration;
+
+use colored::Colorize;
+use rand::Rng;
+use tokio::task::JoinSet;
+use tracing::info;
+
+use tcp_test::tcp::connection_table::ConnectionTable;
+use tcp_test::tcp::main_loop::send_packet;
+use tcp_test::tcp::packet::data::{CloseReason, Controller};
+use tcp_test::tcp::settings::Settings;
+
+/// How many ports are probed at the same time.
+const SCAN_BATCH: usize = 128;
+
+/// What the answer to a SYN tells about a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    /// The port answered with a SYN-ACK
+    Open,
+    /// The connection was refused
+    Closed,
+    /// Nothing came back in time
+    Filtered,
+}
+
+// This function probes every port of a range with a SYN and reports the ones that answer
+// Open ports complete the handshake and are closed again right away, probes without an answer are dropped
+// once the timeout has passed
+// It returns the state of every probed port, in order
+pub async fn scan(
+    table: &Arc<ConnectionTable>,
+    host: IpAddr,
+    ports: RangeInclusive<u16>,
+    timeout: Duration,
+    source_port: Option<u16>,
+    settings: Settings,
+) -> Result<Vec<(u16, PortState)>, String> {
+    info!("{}", format!("Scanning ports {} to {} of {}", ports.start(), ports.end(), host).truecolor(200, 35, 55));
+
+    let ports: Vec<u16> = ports.collect();
+    let mut results = Vec::with_capacity(ports.len());
+
+    for batch in ports.chunks(SCAN_BATCH) {
+        let mut probes = JoinSet::new();
+        for &port in batch {
+            let controller = new_probe(table, host, port, source_port, settings)?;
+            probes.spawn(probe(controller, timeout));
+        }
+
+        while let Some(result) = probes.join_next().await {
+            let (port, state) = result.map_err(|e| e.to_string())?;
+            if state == PortState::Open {
+                info!("{}", format!("Port {} is open", port).truecolor(25, 160, 60));
+            }
+            results.push((port, state));
+        }
+    }
+
+    results.sort_unstable_by_key(|(port, _)| *port);
+    let open = results.iter().filter(|(_, state)| *state == PortState::Open).count();
+    info!("{}", format!("Scan of {} finished, {} of {} ports are open", host, open, results.len()).truecolor(200, 35, 55));
+
+    Ok(results)
+}
+
+// This function registers the connection a port is probed with
+// Without a source port every probe is sent from an unused port of the dynamic range
+fn new_probe(table: &Arc<ConnectionTable>, host: IpAddr, port: u16, source_port: Option<u16>, settings: Settings) -> Result<Arc<Controller>, String> {
+    loop {
+        let local_port = source_port.unwrap_or_else(|| rand::thread_rng().gen_range(49152..=u16::MAX));
+        let controller = Controller::new(table.link.clone(), local_port, &host.to_string(), port, settings)?;
+        if table.get(&controller.key()).is_none() {
+            return Ok(table.register(controller));
+        }
+        if source_port.is_some() {
+            return Err(format!("port {} is already probed from port {}", port, local_port));
+        }
+    }
+}
+
+// This function sends the SYN of a probe and waits for the handshake to complete
+async fn probe(controller: Arc<Controller>, timeout: Duration) -> (u16, PortState) {
+    let port = controller.sockaddr_to_remote.port();
+    send_packet(&controller).await;
+
+    let state = match tokio::time::timeout(timeout, controller.wait_synchronized()).await {
+        Ok(Ok(())) => {
+            controller.close();
+            PortState::Open
+        }
+        Ok(Err(_)) => PortState::Closed,
+        Err(_) => {
+            // Stops the SYN from being retransmitted and removes the probe from the table
+            controller.finish(CloseReason::Aborted("no answer to the SYN".to_string()));
+            PortState::Filtered
+        }
+    };
+
+    (port, state)
+}
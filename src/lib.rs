@@ -0,0 +1,34 @@
+//! A userspace TCP implementation over raw sockets or a TUN device.
+//!
+//! `RawTcpStream` implements tokio's `AsyncRead` and `AsyncWrite`, so existing async code and codecs can run on top
+//! of this stack:
+//!
+//! ```no_run
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! use tcp_test::RawTcpStream;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let mut stream = RawTcpStream::connect("127.0.0.1:65534").await?;
+//! stream.write_all(b"hello\n").await?;
+//!
+//! let mut buffer = [0; 1024];
+//! let size = stream.read(&mut buffer).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Raw sockets need root or the CAP_NET_RAW capability.
+
+// Module declarations
+// The bindings are mostly generated by bindgen and kept as they are
+#[allow(
+    clippy::let_and_return,
+    clippy::missing_safety_doc,
+    clippy::module_inception,
+    clippy::too_many_arguments,
+    clippy::useless_transmute
+)]
+pub mod raw_bindings;
+pub mod tcp;
+
+pub use tcp::stream::RawTcpStream;
@@ -1,33 +1,37 @@
-#![feature(cstr_count_bytes)]
 #![feature(let_chains)]
 // #![feature(lazy_cell)]
 #![cfg_attr(debug_assertions, allow(warnings))]
 
 // Importing necessary libraries and modules
-use std::ffi::{c_int, CString};
-use std::os::raw::c_void;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
+use clap::Parser;
 use colored::Colorize;
-use parking_lot::lock_api::RwLock;
 use rand::random;
-use tracing::{info, Level};
+use tracing::{error, info, warn};
 
+use tcp_test::tcp::capture::{PcapWriter, read_capture};
+use tcp_test::tcp::connection_table::{ChecksumPolicy, ConnectionTable, UnknownSegmentPolicy};
+use tcp_test::tcp::link::device::Link;
+use tcp_test::tcp::link::raw_socket::RawSocketLink;
+use tcp_test::tcp::link::tun::TunLink;
+use tcp_test::tcp::main_loop::{receive_packet, send_packet};
+use tcp_test::tcp::packet::data::{CloseReason, Controller};
+use tcp_test::tcp::packet::ip_header::IpVersion;
+use tcp_test::tcp::replay::replay;
+use tcp_test::tcp::settings::Settings;
+use tcp_test::tcp::util::ToAddress;
+
+use crate::cmd_controller::arguments::{Arguments, Command, LineEnding};
 use crate::cmd_controller::cmd_controller::commandline_listener;
-use crate::raw_bindings::raw_bindings::{AF_INET, in_addr, inet_pton, IP_HDRINCL, IPPROTO_IP, IPPROTO_TCP, setsockopt, SOCK_RAW, sockaddr_in, socket};
-use crate::tcp::main_loop::{receive_packet, send_packet};
-use crate::tcp::packet::data::{Controller, SpacilProcessor};
-use crate::tcp::util::ChangingOrderSizes;
+use crate::cmd_controller::scan::scan;
 
 // Module declarations
-mod raw_bindings;
-mod tcp;
 mod cmd_controller;
 
-// Constants for remote address and port
-const REMOTE_ADDRESS: &str = "127.0.0.1";
-const REMOTE_PORT: u16 = 65534;
-
 /*
 static GLOBAL_MAP: LazyLock<RwLock<parking_lot::RawRwLock, DashMap<&str, Box<dyn Any + Send + Sync>>>>  = LazyLock::new(|| {
     RwLock::new(DashMap::default())
@@ -35,79 +39,220 @@ static GLOBAL_MAP: LazyLock<RwLock<parking_lot::RawRwLock, DashMap<&str, Box<dyn
 */
 
 /// Main function for the application
-/// This function initializes the tracing subscriber, creates a socket, generates a random port, sets up the remote address,
-/// and initializes the Controller struct. It then spawns two coroutines for receiving packets and listening to user input.
-/// Finally, it sends a packet and awaits the completion of the two coroutines.
+/// This function parses the command line and initializes the tracing subscriber, then runs the subcommand:
+/// `connect` opens a connection and sends the user input to it, `listen` accepts IPv4 and IPv6 connections on a port,
+/// `scan` probes the ports of a host and `replay` plays a captured session again.
 /// For complete comments, please refer to the `cmd_controller.rs` file, `packet_factory.rs` file, `receive_processor.rs` file, and `main_loop.rs` file.
 /// For the test server, [see](https://github.com/Anivie/tcp-test-server).
 #[tokio::main]
 #[cfg(target_os = "linux")]
 async fn main() {
-    // Initialize tracing subscriber with max level set to INFO
+    let arguments = Arguments::parse();
+
+    // Initialize tracing subscriber with the requested max level
     tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
+        .with_max_level(arguments.log_level)
         .init();
 
-    // Create a socket
-    let socket = unsafe {
-        let socket = socket(AF_INET as c_int, SOCK_RAW, IPPROTO_TCP as c_int);
-        if socket == -1 {
-            panic!("Create socket failed, error: {}", socket);
+    let settings = arguments.settings();
+    let result = match create_tables(&arguments) {
+        Ok(tables) => match &arguments.command {
+            Command::Connect { address } => connect(address, arguments.source_port, settings, &tables, arguments.line_ending).await,
+            Command::Listen { port } => listen(*port, settings, &tables, arguments.line_ending).await,
+            Command::Scan { host, ports, timeout } => match tables.new_table(IpVersion::of(host)) {
+                Ok(table) => {
+                    tokio::spawn(receive_packet(table.clone()));
+                    scan(&table, *host, ports.clone(), *timeout, arguments.source_port, settings).await.map(|_| ())
+                }
+                Err(e) => Err(e),
+            },
+            Command::Replay { recording, address, timeout } => replay_session(recording, *address, *timeout, settings, &tables).await,
+        },
+        Err(e) => Err(e),
+    };
+
+    // The receive loops never return, so the process is ended rather than waiting for them
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            error!("{}", e.red());
+            std::process::exit(1);
         }
+    }
+}
+
+/// What every connection table of the process is created with.
+struct Tables {
+    /// The TUN device every table drives its connections over, each table opens a raw socket if not set
+    tun: Option<Arc<TunLink>>,
+    /// Where the packets of every table are recorded
+    capture: Option<Arc<PcapWriter>>,
+    /// What is done with segments whose checksum does not match
+    checksum_policy: ChecksumPolicy,
+}
 
-        let one = 1;
-        let opt = setsockopt(socket, IPPROTO_IP as c_int, IP_HDRINCL as c_int, &one as *const i32 as *const c_void, 4);
-        if opt == -1 {
-            panic!("Create socket failed, error: {}", opt);
-        } else {
-            info!("Create socket success, socket id: {}", socket);
-            info!("Create socket success, opt return: {}", opt);
+impl Tables {
+    /// The link connections of an IP version are driven over: the TUN device, or a new raw socket.
+    fn link(&self, version: IpVersion) -> Result<Arc<dyn Link>, String> {
+        match &self.tun {
+            Some(tun) => Ok(tun.clone()),
+            None => Ok(Arc::new(RawSocketLink::open(version)?)),
         }
-        socket
-    };
+    }
+
+    /// Creates a table for connections of an IP version.
+    ///
+    /// Tables on the TUN device reset segments of unknown connections themselves, the kernel does on raw sockets.
+    fn new_table(&self, version: IpVersion) -> Result<Arc<ConnectionTable>, String> {
+        let unknown_segment_policy = match self.tun {
+            Some(_) => UnknownSegmentPolicy::Reset,
+            None => UnknownSegmentPolicy::Drop,
+        };
+        let table = ConnectionTable::new(self.link(version)?)
+            .with_checksum_policy(self.checksum_policy)
+            .with_unknown_segment_policy(unknown_segment_policy);
+        Ok(Arc::new(match &self.capture {
+            Some(capture) => table.with_capture(capture.clone()),
+            None => table,
+        }))
+    }
+}
 
-    // Generate a random port
-    let port: u16 = {
-        let p: u16 = random();
-        info!("Start with port: {}", p.to_string().red());
-        p
+/// Opens the pcap file and the TUN device asked for on the command line.
+fn create_tables(arguments: &Arguments) -> Result<Tables, String> {
+    Ok(Tables {
+        tun: create_tun(arguments)?,
+        capture: create_capture(arguments)?,
+        checksum_policy: arguments.checksum_policy(),
+    })
+}
+
+/// Opens the pcap file packets are recorded in, if one was asked for.
+fn create_capture(arguments: &Arguments) -> Result<Option<Arc<PcapWriter>>, String> {
+    let Some(path) = &arguments.capture else {
+        return Ok(None);
     };
 
-    // Set up the remote address
-    let sockaddr_to = unsafe {
-        let mut addr = sockaddr_in {
-            sin_family: AF_INET as u16,
-            sin_port: REMOTE_PORT.to_network(),
-            ..Default::default()
-        };
+    let capture = PcapWriter::create(path).map_err(|e| format!("Can not create {}: {}", path.display(), e))?;
+    info!("Recording packets to {}", path.display().to_string().red());
+    Ok(Some(Arc::new(capture)))
+}
 
-        let ip = CString::new(REMOTE_ADDRESS).unwrap();
-        let res = inet_pton(AF_INET as c_int, ip.as_ptr(), &mut addr.sin_addr as *mut in_addr as *mut c_void);
-        if res != 1 {
-            panic!("error on inet_pton: {}", res)
-        }
-        addr
+/// Creates the TUN device connections are driven over, if one was asked for, and brings it up with the kernel's
+/// addresses.
+fn create_tun(arguments: &Arguments) -> Result<Option<Arc<TunLink>>, String> {
+    let Some(name) = &arguments.tun else {
+        return Ok(None);
     };
 
+    let tun = TunLink::open(name)?;
+    for (address, prefix_length) in &arguments.tun_address {
+        tun.add_address(*address, *prefix_length)?;
+    }
+    tun.up()?;
+    Ok(Some(Arc::new(tun)))
+}
+
+/// Connects to a remote and writes every line of the user input to it.
+///
+/// # Arguments
+///
+/// * `address` - The remote, as `ip:port` or `[ipv6]:port`
+/// * `source_port` - Our port, a random one if not set
+/// * `settings` - The settings of the connection
+/// * `tables` - What the table of the connection is created with
+/// * `line_ending` - What is appended to every line of the user input
+///
+/// # Remarks
+///
+/// The process exits once the connection is closed, with status 1 if it was aborted.
+async fn connect(address: &str, source_port: Option<u16>, settings: Settings, tables: &Tables, line_ending: LineEnding) -> Result<(), String> {
+    let (remote_port, remote_address) = address.to_address().ok_or_else(|| format!("Invalid address: {}", address))?;
+
+    // Create a table for the remote's IP version, every connection is driven over the same link
+    let version = if remote_address.contains(':') { IpVersion::V6 } else { IpVersion::V4 };
+    let table = tables.new_table(version)?;
+
+    // Use the requested port or generate a random one
+    let port = source_port.unwrap_or_else(random);
+    info!("Start with port: {}", port.to_string().red());
+
     // Initialize the Controller struct
-    let control = Controller {
-        socket,
-        local_port: port,
-        sockaddr_to_remote: sockaddr_to,
-        address_to_remote: format!("{}:{}", REMOTE_ADDRESS, REMOTE_PORT),
-        last_ack_seq_number: Arc::new(RwLock::new(0)),
-        last_seq_number: Arc::new(RwLock::new(0)),
-        spacil: Arc::new(RwLock::new(SpacilProcessor::None)),
-    };
+    let control = table.register(Controller::new(table.link.clone(), port, remote_address, remote_port, settings)?);
+
+    // Spawn the coroutine receiving packets and send the first handshake
+    tokio::spawn(receive_packet(table));
+    send_packet(&control).await;
 
-    // Spawn two coroutines for receiving packets and listening to user input
-    let receive_coroutine = tokio::spawn(receive_packet(control.clone()));
-    let user_input_coroutine = tokio::spawn(commandline_listener(control.clone()));
+    // The user input goes out once the connection is established
+    if let Err(reason) = control.wait_synchronized().await {
+        return Err(format!("Can not connect to {}: {}", address, reason));
+    }
+    let input_controller = control.clone();
+    tokio::spawn(commandline_listener(move || vec![input_controller.clone()], line_ending));
 
-    // Send a packet
-    send_packet(control).await;
+    // Exit once the connection is closed
+    match control.wait_closed().await {
+        CloseReason::Closed => std::process::exit(0),
+        CloseReason::Aborted(_) | CloseReason::Refused | CloseReason::Reset | CloseReason::KeepaliveTimeout => std::process::exit(1),
+    }
+}
+
+/// Replays a recorded session against a server and reports every difference in its answers.
+///
+/// # Arguments
+///
+/// * `recording` - The pcap file of the session
+/// * `target` - The server
+/// * `timeout` - How long to wait for the answers to each segment
+/// * `settings` - Where the segments are sent from
+/// * `tables` - The link and capture of the live session are taken from it
+///
+/// # Returns
+///
+/// * `Result<(), String>` - An error if the replay could not run or the answers differ
+async fn replay_session(recording: &Path, target: SocketAddr, timeout: Duration, settings: Settings, tables: &Tables) -> Result<(), String> {
+    let segments = read_capture(recording).map_err(|e| format!("Can not read {}: {}", recording.display(), e))?;
+    let link = tables.link(IpVersion::of(&target.ip()))?;
+    let report = replay(&segments, link, target, settings, timeout, tables.capture.clone()).await?;
+
+    if report.is_identical() {
+        info!("{}", "The server answered exactly as recorded".truecolor(25, 160, 60));
+        return Ok(());
+    }
+    for difference in &report.differences {
+        warn!("{}", difference.truecolor(230, 120, 30));
+    }
+    Err(format!("The server answered differently in {} places", report.differences.len()))
+}
+
+/// Accepts connections on a port and writes every line of the user input to all of them.
+///
+/// # Arguments
+///
+/// * `port` - The port to listen on
+/// * `settings` - The settings of accepted connections, with a source address only that address is listened on
+/// * `tables` - What the tables of the connections are created with
+/// * `line_ending` - What is appended to every line of the user input
+async fn listen(port: u16, settings: Settings, tables: &Tables, line_ending: LineEnding) -> Result<(), String> {
+    let versions = match settings.source_address {
+        Some(address) => vec![IpVersion::of(&address)],
+        None => vec![IpVersion::V4, IpVersion::V6],
+    };
+
+    // Every connection of an IP version is driven over the same link, a link carrying both needs a single table
+    let mut listening: Vec<Arc<ConnectionTable>> = Vec::with_capacity(versions.len());
+    for version in versions {
+        if listening.iter().any(|table| table.supports(version)) {
+            continue;
+        }
+        let table = tables.new_table(version)?;
+        table.listen(port, settings);
+        tokio::spawn(receive_packet(table.clone()));
+        listening.push(table);
+    }
 
-    // Await the completion of the two coroutines
-    receive_coroutine.await.unwrap();
-    user_input_coroutine.await.unwrap();
-}
\ No newline at end of file
+    // Connections are still accepted once the user input has ended
+    commandline_listener(move || listening.iter().flat_map(|table| table.controllers()).collect(), line_ending).await;
+    std::future::pending().await
+}
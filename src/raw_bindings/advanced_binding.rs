@@ -23,7 +23,7 @@ impl iphdr {
         let mut iphdr = unsafe {
             iphdr {
                 tos: 0,
-                tot_len: (size_of::<iphdr>() + size_of::<tcphdr>() + data_len) as u16,
+                tot_len: ((size_of::<iphdr>() + size_of::<tcphdr>() + data_len) as u16).to_network(),
                 id: random::<u16>().to_network(),
                 frag_off: 0,
                 ttl: 64,
@@ -170,7 +170,7 @@ impl Display for iphdr {
                 self.ihl(),
                 self.version(),
                 self.tos,
-                self.tot_len,
+                self.tot_len.to_host(),
                 self.id.to_host(),
                 self.frag_off,
                 self.ttl,
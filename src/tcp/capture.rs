@@ -0,0 +1,357 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::error;
+use parking_lot::Mutex;
+
+use crate::tcp::packet::data::ReceiveData;
+use crate::tcp::packet::ip_header::IpHeader;
+use crate::tcp::packet::options::TcpOption;
+use crate::tcp::packet::parser::{Ipv4Packet, Ipv6Packet, IPV6_HEADER_LENGTH};
+use crate::tcp::util::ChangingOrderSizes;
+
+/// Magic number of a pcap file with microsecond timestamps.
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+/// Packets start with their IPv4 or IPv6 header, there is no link layer header.
+pub const LINKTYPE_RAW: u32 = 101;
+/// The largest packet that is kept in full.
+const SNAPSHOT_LENGTH: u32 = 65535;
+
+/// Writes every packet sent or received over a raw socket to a libpcap file, for Wireshark or for diffing sessions.
+///
+/// The writer is shared by the connection tables of a process, each packet is written as a whole and flushed right
+/// away so the file is complete even when the process is killed.
+pub struct PcapWriter<W: Write = BufWriter<File>> {
+    file: Mutex<W>,
+}
+
+impl PcapWriter {
+    /// Creates the file, or truncates it, and writes the pcap global header.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Where the capture is written
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<PcapWriter>` - The writer, or the error that occurred creating the file
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<PcapWriter> {
+        PcapWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the pcap global header to `writer`, the packets follow it.
+    pub fn new(mut writer: W) -> io::Result<PcapWriter<W>> {
+        writer.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&4u16.to_le_bytes())?;
+        // Timestamps are in UTC, with no accuracy given
+        writer.write_all(&0i32.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&SNAPSHOT_LENGTH.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+        writer.flush()?;
+
+        Ok(PcapWriter {
+            file: Mutex::new(writer),
+        })
+    }
+
+    /// The writer the capture went to.
+    pub fn into_inner(self) -> W {
+        self.file.into_inner()
+    }
+
+    /// Records a packet, stamped with the current time.
+    ///
+    /// # Arguments
+    ///
+    /// * `ip_head` - The IP header of the packet
+    /// * `packet` - The packet as it went over the link: the whole IPv4 datagram, or only the TCP segment for IPv6
+    ///
+    /// # Remarks
+    ///
+    /// IPv6 raw sockets never see the IPv6 header, so one is put in front of the segment.
+    pub fn record(&self, ip_head: &IpHeader, packet: &[u8]) {
+        let packet = match ip_head {
+            IpHeader::V4(_) => packet.to_vec(),
+            IpHeader::V6(header) => {
+                let mut datagram = Vec::with_capacity(IPV6_HEADER_LENGTH + packet.len());
+                datagram.extend_from_slice(&header.to_bytes(packet.len()));
+                datagram.extend_from_slice(packet);
+                datagram
+            }
+        };
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let captured = packet.len().min(SNAPSHOT_LENGTH as usize);
+
+        let mut file = self.file.lock();
+        let result = file.write_all(&(timestamp.as_secs() as u32).to_le_bytes())
+            .and_then(|_| file.write_all(&timestamp.subsec_micros().to_le_bytes()))
+            .and_then(|_| file.write_all(&(captured as u32).to_le_bytes()))
+            .and_then(|_| file.write_all(&(packet.len() as u32).to_le_bytes()))
+            .and_then(|_| file.write_all(&packet[..captured]))
+            .and_then(|_| file.flush());
+
+        if let Err(e) = result {
+            error!("Can not write the packet to the capture: {}", e);
+        }
+    }
+}
+
+/// Magic number of a pcap file with nanosecond timestamps.
+const PCAP_MAGIC_NANOSECONDS: u32 = 0xa1b23c4d;
+/// Packets start with an IPv4 header.
+const LINKTYPE_IPV4: u32 = 228;
+/// Packets start with an IPv6 header.
+const LINKTYPE_IPV6: u32 = 229;
+
+/// The control flags in the 14th byte of the TCP header.
+pub const FIN: u8 = 0x01;
+pub const SYN: u8 = 0x02;
+pub const RST: u8 = 0x04;
+pub const PSH: u8 = 0x08;
+pub const ACK: u8 = 0x10;
+pub const URG: u8 = 0x20;
+
+/// A TCP segment read back from a capture, or received live to be compared with one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedSegment {
+    /// When the segment was captured, since the Unix epoch
+    pub timestamp: Duration,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub seq: u32,
+    pub ack: u32,
+    /// The control flags, see `SYN`, `ACK` and the others
+    pub flags: u8,
+    pub window: u16,
+    pub options: Vec<TcpOption>,
+    pub payload: Vec<u8>,
+}
+
+impl CapturedSegment {
+    #[inline]
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+impl From<&ReceiveData> for CapturedSegment {
+    fn from(receive: &ReceiveData) -> Self {
+        let head = &receive.tcphdr;
+        let flags = [(head.fin(), FIN), (head.syn(), SYN), (head.rst(), RST), (head.psh(), PSH), (head.ack(), ACK), (head.urg(), URG)]
+            .iter()
+            .filter(|(set, _)| *set == 1)
+            .fold(0, |flags, (_, flag)| flags | flag);
+
+        CapturedSegment {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+            source: SocketAddr::new(receive.ip_head.source(), head.source.to_host()),
+            destination: SocketAddr::new(receive.ip_head.destination(), head.dest.to_host()),
+            seq: head.seq.to_host(),
+            ack: head.ack_seq.to_host(),
+            flags,
+            window: head.window.to_host(),
+            options: receive.options.clone(),
+            payload: receive.data.as_deref().unwrap_or_default().to_vec(),
+        }
+    }
+}
+
+/// Names the control flags of a segment, as in `SYN|ACK`.
+pub fn flag_names(flags: u8) -> String {
+    let names: Vec<&str> = [(FIN, "FIN"), (SYN, "SYN"), (RST, "RST"), (PSH, "PSH"), (ACK, "ACK"), (URG, "URG")]
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+
+    if names.is_empty() { "none".to_string() } else { names.join("|") }
+}
+
+/// Reads the TCP segments of a pcap file whose packets start with their IP header.
+///
+/// # Arguments
+///
+/// * `path` - The capture, as written by `PcapWriter` or by tcpdump on a raw IP interface
+///
+/// # Returns
+///
+/// * `Result<Vec<CapturedSegment>, String>` - The TCP segments in capture order, or why the file can not be read.
+///   Packets that are not TCP are skipped.
+pub fn read_capture<P: AsRef<Path>>(path: P) -> Result<Vec<CapturedSegment>, String> {
+    let content = std::fs::read(path).map_err(|e| e.to_string())?;
+    parse_capture(&content)
+}
+
+/// Reads the TCP segments of a pcap file already in memory, see `read_capture`.
+pub fn parse_capture(content: &[u8]) -> Result<Vec<CapturedSegment>, String> {
+    if content.len() < 24 {
+        return Err("the file is too short for a pcap header".to_string());
+    }
+
+    // The magic number tells the byte order of the writer and the timestamp resolution
+    let magic = u32::from_le_bytes(content[0..4].try_into().unwrap());
+    let (little_endian, nanoseconds) = match magic {
+        PCAP_MAGIC => (true, false),
+        PCAP_MAGIC_NANOSECONDS => (true, true),
+        _ if magic.swap_bytes() == PCAP_MAGIC => (false, false),
+        _ if magic.swap_bytes() == PCAP_MAGIC_NANOSECONDS => (false, true),
+        _ => return Err(format!("not a pcap file, magic number {:#x}", magic)),
+    };
+    let read_u32 = |bytes: &[u8]| {
+        let bytes: [u8; 4] = bytes.try_into().unwrap();
+        if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    };
+
+    let link_type = read_u32(&content[20..24]);
+    if link_type != LINKTYPE_RAW && link_type != LINKTYPE_IPV4 && link_type != LINKTYPE_IPV6 {
+        return Err(format!("link type {} is not supported, packets have to start with the IP header", link_type));
+    }
+
+    let mut segments = Vec::new();
+    let mut offset = 24;
+    while offset + 16 <= content.len() {
+        let seconds = read_u32(&content[offset..offset + 4]);
+        let fraction = read_u32(&content[offset + 4..offset + 8]);
+        let captured = read_u32(&content[offset + 8..offset + 12]) as usize;
+        offset += 16;
+
+        let packet = content.get(offset..offset + captured).ok_or("the last packet is truncated")?;
+        offset += captured;
+
+        let timestamp = Duration::from_secs(seconds as u64) + if nanoseconds {
+            Duration::from_nanos(fraction as u64)
+        } else {
+            Duration::from_micros(fraction as u64)
+        };
+        if let Some(segment) = parse_packet(packet, timestamp) {
+            segments.push(segment);
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Parses an IP packet of a capture, None if it is not a complete TCP segment.
+fn parse_packet(packet: &[u8], timestamp: Duration) -> Option<CapturedSegment> {
+    let (source, destination, segment) = match packet.first()? >> 4 {
+        4 => {
+            let packet = Ipv4Packet::parse(packet).ok()?;
+            (IpAddr::V4(packet.source()), IpAddr::V4(packet.destination()), packet.tcp().ok()?)
+        }
+        6 => {
+            let packet = Ipv6Packet::parse(packet).ok()?;
+            (IpAddr::V6(packet.source()), IpAddr::V6(packet.destination()), packet.tcp().ok()?)
+        }
+        _ => return None,
+    };
+
+    Some(CapturedSegment {
+        timestamp,
+        source: SocketAddr::new(source, segment.source_port()),
+        destination: SocketAddr::new(destination, segment.destination_port()),
+        seq: segment.seq(),
+        ack: segment.ack(),
+        flags: segment.flags(),
+        window: segment.window(),
+        options: segment.options().ok()?,
+        payload: segment.payload().to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::tcp::packet::tcp_packet::TCPPacket;
+
+    use super::*;
+
+    /// Records a SYN|ACK with an MSS option and `data` from `source` to `destination`.
+    fn record<W: Write>(capture: &PcapWriter<W>, source: IpAddr, destination: SocketAddr, data: &'static [u8]) -> usize {
+        let mut packet = TCPPacket::default(source, destination.to_string(), Some(data), 4000).unwrap();
+        unsafe {
+            let tcp_head = &mut packet.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
+            tcp_head.seq = 1000u32.to_network();
+            tcp_head.ack_seq = 2000u32.to_network();
+            tcp_head.window = 512u16.to_network();
+            tcp_head.set_syn(1);
+            tcp_head.set_ack(1);
+        }
+        packet.set_options(&[TcpOption::MaximumSegmentSize(1460)]).unwrap();
+
+        packet.as_ptr();
+        let bytes = &packet.data_vec[..packet.len()];
+        capture.record(&packet.ip_head, bytes);
+        bytes.len()
+    }
+
+    #[test]
+    fn writes_the_global_and_record_headers() {
+        let capture = PcapWriter::new(Vec::new()).unwrap();
+        let length = record(&capture, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), "10.0.0.2:80".parse().unwrap(), b"hi");
+        let content = capture.into_inner();
+
+        let u32_at = |offset: usize| u32::from_le_bytes(content[offset..offset + 4].try_into().unwrap());
+        assert_eq!(u32_at(0), PCAP_MAGIC);
+        assert_eq!(&content[4..8], &[2, 0, 4, 0]);
+        assert_eq!(u32_at(16), SNAPSHOT_LENGTH);
+        assert_eq!(u32_at(20), LINKTYPE_RAW);
+
+        // Captured and original length, then the IPv4 datagram as it was sent
+        assert_eq!((u32_at(32) as usize, u32_at(36) as usize), (length, length));
+        assert_eq!(content.len(), 24 + 16 + length);
+        assert_eq!(content[40] >> 4, 4);
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let capture = PcapWriter::new(Vec::new()).unwrap();
+        let v4 = (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), "10.0.0.2:80".parse().unwrap());
+        let v6 = ("fd00::1".parse().unwrap(), "[fd00::2]:80".parse().unwrap());
+        record(&capture, v4.0, v4.1, b"over IPv4");
+        let length = record(&capture, v6.0, v6.1, b"over IPv6");
+        let content = capture.into_inner();
+
+        // The IPv6 header is put in front of the segment
+        let record_header = content.len() - length - IPV6_HEADER_LENGTH - 16;
+        let captured = u32::from_le_bytes(content[record_header + 8..record_header + 12].try_into().unwrap());
+        assert_eq!(captured as usize, IPV6_HEADER_LENGTH + length);
+
+        let segments = parse_capture(&content).unwrap();
+        assert_eq!(segments.len(), 2);
+        for (segment, (source, destination), payload) in [(&segments[0], v4, b"over IPv4"), (&segments[1], v6, b"over IPv6")] {
+            assert_eq!(segment.source, SocketAddr::new(source, 4000));
+            assert_eq!(segment.destination, destination);
+            assert_eq!((segment.seq, segment.ack, segment.flags, segment.window), (1000, 2000, SYN | ACK, 512));
+            assert_eq!(segment.options, vec![TcpOption::MaximumSegmentSize(1460)]);
+            assert_eq!(segment.payload, payload);
+        }
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_complete_capture() {
+        let capture = PcapWriter::new(Vec::new()).unwrap();
+        record(&capture, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), "10.0.0.2:80".parse().unwrap(), b"hi");
+        let content = capture.into_inner();
+
+        assert!(parse_capture(&content[..20]).is_err());
+        assert!(parse_capture(&content[..content.len() - 1]).is_err());
+
+        let mut other_magic = content.clone();
+        other_magic[0] ^= 0xff;
+        assert!(parse_capture(&other_magic).is_err());
+
+        let mut ethernet = content;
+        ethernet[20..24].copy_from_slice(&1u32.to_le_bytes());
+        assert!(parse_capture(&ethernet).is_err());
+    }
+}
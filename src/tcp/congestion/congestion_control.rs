@@ -0,0 +1,298 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use log::debug;
+
+use crate::tcp::congestion::cubic::Cubic;
+use crate::tcp::congestion::new_reno::NewReno;
+use crate::tcp::packet::tcb::seq_le;
+
+/// Number of duplicate ACKs that trigger a fast retransmit, RFC 5681 section 3.2.
+pub const DUPLICATE_ACK_THRESHOLD: u32 = 3;
+
+/// How a loss was detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LossEvent {
+    /// Three duplicate ACKs arrived, the oldest segment is sent again right away
+    FastRetransmit,
+    /// The retransmission timer expired
+    Timeout,
+}
+
+/// A congestion control algorithm, consulted by the send path before every segment.
+///
+/// All sizes are in bytes.
+pub trait CongestionControl: Send + Sync {
+    /// Short name of the algorithm, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// The congestion window: how many bytes may be in flight.
+    fn cwnd(&self) -> u32;
+
+    /// The slow start threshold.
+    fn ssthresh(&self) -> u32;
+
+    /// Called when an ACK acknowledges new data outside of loss recovery.
+    ///
+    /// # Arguments
+    ///
+    /// * `acked` - The number of newly acknowledged bytes
+    /// * `now` - When the ACK arrived
+    /// * `rtt` - The smoothed round-trip time, `None` before the first sample
+    fn on_ack(&mut self, acked: u32, now: Instant, rtt: Option<Duration>);
+
+    /// Called when a loss is detected.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - How the loss was detected
+    /// * `flight_size` - The number of bytes that were in flight
+    /// * `now` - When the loss was detected
+    fn on_loss(&mut self, event: LossEvent, flight_size: u32, now: Instant);
+}
+
+/// The congestion control algorithms a connection can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CongestionAlgorithm {
+    NewReno,
+    #[default]
+    Cubic,
+}
+
+impl CongestionAlgorithm {
+    /// Creates a fresh instance of the algorithm.
+    ///
+    /// # Arguments
+    ///
+    /// * `mss` - The sender maximum segment size
+    pub fn build(self, mss: u32) -> Box<dyn CongestionControl> {
+        match self {
+            CongestionAlgorithm::NewReno => Box::new(NewReno::new(mss)),
+            CongestionAlgorithm::Cubic => Box::new(Cubic::new(mss)),
+        }
+    }
+}
+
+impl FromStr for CongestionAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "reno" | "newreno" | "new-reno" => Ok(CongestionAlgorithm::NewReno),
+            "cubic" => Ok(CongestionAlgorithm::Cubic),
+            _ => Err(format!("Unknown congestion control algorithm: {}", s)),
+        }
+    }
+}
+
+impl Display for CongestionAlgorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CongestionAlgorithm::NewReno => write!(f, "newreno"),
+            CongestionAlgorithm::Cubic => write!(f, "cubic"),
+        }
+    }
+}
+
+/// RFC 5681 initial window: min(4 * MSS, max(2 * MSS, 4380)).
+#[inline]
+pub fn initial_window(mss: u32) -> u32 {
+    (4 * mss).min((2 * mss).max(4380))
+}
+
+/// What the send path has to do after an ACK went through `Congestion::on_ack`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckOutcome {
+    Nothing,
+    /// The oldest unacknowledged segment has to be sent again (fast retransmit or a partial ACK in recovery)
+    Retransmit,
+}
+
+/// State of an ongoing fast recovery.
+#[derive(Debug, Clone, Copy)]
+struct Recovery {
+    /// SND.NXT when fast recovery started, recovery ends once it is acknowledged
+    recover: u32,
+    /// The window while recovering, inflated by duplicate ACKs and deflated by partial ACKs
+    cwnd: u32,
+}
+
+/// Per-connection congestion state: the selected algorithm plus duplicate ACK counting and the NewReno
+/// fast recovery of RFC 6582, which are the same for every algorithm.
+///
+/// The algorithm only sees the loss that starts a recovery. Until the recovery ends the window is the one of
+/// RFC 6582 section 3.2: ssthresh plus three MSS, one more MSS for every further duplicate ACK, and less
+/// whatever a partial ACK acknowledges. A full ACK deflates it to ssthresh, which is where the algorithm left it.
+pub struct Congestion {
+    selected: CongestionAlgorithm,
+    algorithm: Box<dyn CongestionControl>,
+    mss: u32,
+    duplicate_acks: u32,
+    recovery: Option<Recovery>,
+}
+
+impl Congestion {
+    pub fn new(algorithm: CongestionAlgorithm, mss: u32) -> Self {
+        Congestion {
+            selected: algorithm,
+            algorithm: algorithm.build(mss),
+            mss,
+            duplicate_acks: 0,
+            recovery: None,
+        }
+    }
+
+    /// Starts the selected algorithm over with a new MSS, once it has been negotiated.
+    pub fn set_mss(&mut self, mss: u32) {
+        *self = Congestion::new(self.selected, mss);
+    }
+
+    #[inline]
+    pub fn cwnd(&self) -> u32 {
+        self.recovery.map_or(self.algorithm.cwnd(), |recovery| recovery.cwnd)
+    }
+
+    /// Processes an ACK.
+    ///
+    /// # Arguments
+    ///
+    /// * `acked` - Newly acknowledged bytes, zero for a duplicate ACK
+    /// * `ack` - The acknowledgement number
+    /// * `snd_nxt` - SND.NXT when the ACK arrived
+    /// * `flight_size` - Bytes in flight before the ACK
+    /// * `rtt` - The smoothed round-trip time, `None` before the first sample
+    pub fn on_ack(&mut self, acked: u32, ack: u32, snd_nxt: u32, flight_size: u32, rtt: Option<Duration>) -> AckOutcome {
+        let now = Instant::now();
+
+        if acked == 0 {
+            self.duplicate_acks += 1;
+            if let Some(recovery) = &mut self.recovery {
+                // Every duplicate ACK means a segment left the network
+                recovery.cwnd = recovery.cwnd.saturating_add(self.mss);
+                return AckOutcome::Nothing;
+            }
+            if self.duplicate_acks == DUPLICATE_ACK_THRESHOLD {
+                self.algorithm.on_loss(LossEvent::FastRetransmit, flight_size, now);
+                self.recovery = Some(Recovery {
+                    recover: snd_nxt,
+                    cwnd: self.algorithm.ssthresh().saturating_add(DUPLICATE_ACK_THRESHOLD * self.mss),
+                });
+                self.log_loss(LossEvent::FastRetransmit);
+                return AckOutcome::Retransmit;
+            }
+            return AckOutcome::Nothing;
+        }
+
+        self.duplicate_acks = 0;
+        match &mut self.recovery {
+            Some(recovery) if seq_le(recovery.recover, ack) => {
+                self.recovery = None;
+                AckOutcome::Nothing
+            }
+            // A partial ACK: the next hole is lost as well
+            Some(recovery) => {
+                let deflated = recovery.cwnd.saturating_sub(acked);
+                recovery.cwnd = if acked >= self.mss { deflated + self.mss } else { deflated }.max(self.mss);
+                AckOutcome::Retransmit
+            }
+            None => {
+                self.algorithm.on_ack(acked, now, rtt);
+                AckOutcome::Nothing
+            }
+        }
+    }
+
+    /// Processes a retransmission timeout, which also ends any fast recovery.
+    pub fn on_timeout(&mut self, flight_size: u32) {
+        self.duplicate_acks = 0;
+        self.recovery = None;
+        self.algorithm.on_loss(LossEvent::Timeout, flight_size, Instant::now());
+        self.log_loss(LossEvent::Timeout);
+    }
+
+    /// Logs the window a loss left the algorithm with.
+    fn log_loss(&self, event: LossEvent) {
+        debug!(
+            "{}",
+            format!("{} reacted to {:?}: cwnd {} bytes, ssthresh {} bytes", self.algorithm.name(), event, self.cwnd(), self.algorithm.ssthresh())
+                .truecolor(230, 120, 30)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSS: u32 = 1000;
+    const ALGORITHMS: [CongestionAlgorithm; 2] = [CongestionAlgorithm::NewReno, CongestionAlgorithm::Cubic];
+
+    #[test]
+    fn slow_start_outside_of_recovery() {
+        for algorithm in ALGORITHMS {
+            let mut congestion = Congestion::new(algorithm, MSS);
+            assert_eq!(congestion.cwnd(), initial_window(MSS));
+
+            assert_eq!(congestion.on_ack(MSS, 2000, 5000, 4000, None), AckOutcome::Nothing);
+            assert_eq!(congestion.cwnd(), initial_window(MSS) + MSS, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn fast_recovery_inflates_and_deflates_the_window() {
+        for algorithm in ALGORITHMS {
+            let mut congestion = Congestion::new(algorithm, MSS);
+            let (snd_una, snd_nxt) = (1000, 11000);
+            let flight_size = snd_nxt - snd_una;
+
+            for _ in 1..DUPLICATE_ACK_THRESHOLD {
+                assert_eq!(congestion.on_ack(0, snd_una, snd_nxt, flight_size, None), AckOutcome::Nothing);
+            }
+            assert_eq!(congestion.cwnd(), initial_window(MSS), "{}", algorithm);
+
+            assert_eq!(congestion.on_ack(0, snd_una, snd_nxt, flight_size, None), AckOutcome::Retransmit);
+            let ssthresh = congestion.algorithm.ssthresh();
+            assert_eq!(congestion.cwnd(), ssthresh + 3 * MSS, "{}", algorithm);
+
+            // Further duplicate ACKs inflate the window without another reduction
+            for _ in 0..2 {
+                assert_eq!(congestion.on_ack(0, snd_una, snd_nxt, flight_size, None), AckOutcome::Nothing);
+            }
+            assert_eq!(congestion.cwnd(), ssthresh + 5 * MSS, "{}", algorithm);
+
+            // A partial ACK deflates by what it acknowledges, and adds back one MSS if that was at least one
+            assert_eq!(congestion.on_ack(2 * MSS, 3000, snd_nxt, flight_size, None), AckOutcome::Retransmit);
+            assert_eq!(congestion.cwnd(), ssthresh + 4 * MSS, "{}", algorithm);
+            assert_eq!(congestion.on_ack(500, 3500, snd_nxt, flight_size, None), AckOutcome::Retransmit);
+            assert_eq!(congestion.cwnd(), ssthresh + 3500, "{}", algorithm);
+
+            // The full ACK ends the recovery with the window at ssthresh
+            assert_eq!(congestion.on_ack(7500, snd_nxt, snd_nxt, 7500, None), AckOutcome::Nothing);
+            assert_eq!(congestion.cwnd(), ssthresh, "{}", algorithm);
+            assert_eq!(congestion.algorithm.ssthresh(), ssthresh, "{}", algorithm);
+        }
+    }
+
+    #[test]
+    fn a_timeout_ends_fast_recovery() {
+        for algorithm in ALGORITHMS {
+            let mut congestion = Congestion::new(algorithm, MSS);
+            for _ in 0..DUPLICATE_ACK_THRESHOLD {
+                congestion.on_ack(0, 1000, 11000, 10000, None);
+            }
+            assert!(congestion.recovery.is_some());
+
+            congestion.on_timeout(10000);
+            assert!(congestion.recovery.is_none());
+            assert_eq!(congestion.cwnd(), MSS, "{}", algorithm);
+
+            // A new loss starts a new recovery
+            for _ in 0..DUPLICATE_ACK_THRESHOLD - 1 {
+                congestion.on_ack(0, 1000, 11000, 10000, None);
+            }
+            assert_eq!(congestion.on_ack(0, 1000, 11000, 10000, None), AckOutcome::Retransmit, "{}", algorithm);
+        }
+    }
+}
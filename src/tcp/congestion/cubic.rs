@@ -0,0 +1,184 @@
+use std::time::{Duration, Instant};
+
+use crate::tcp::congestion::congestion_control::{CongestionControl, initial_window, LossEvent};
+
+/// Scaling constant C of RFC 9438, in segments per second cubed.
+const C: f64 = 0.4;
+/// Multiplicative decrease factor of RFC 9438.
+const BETA: f64 = 0.7;
+
+/// CUBIC congestion control, RFC 9438.
+///
+/// The window follows W(t) = C * (t - K)^3 + W_max after a loss, but never grows slower than the Reno-friendly
+/// estimate. Each ACK moves the window towards W(t + RTT), the size it should have one round trip later.
+/// Window arithmetic is done in segments.
+pub struct Cubic {
+    mss: u32,
+    cwnd: u32,
+    ssthresh: u32,
+    /// Window just before the last reduction, in segments
+    w_max: f64,
+    /// Reno-friendly window estimate, in segments
+    w_est: f64,
+    /// Time the window needs to grow back to `w_max`, in seconds
+    k: f64,
+    epoch_start: Option<Instant>,
+}
+
+impl Cubic {
+    pub fn new(mss: u32) -> Self {
+        Cubic {
+            mss,
+            cwnd: initial_window(mss),
+            ssthresh: u32::MAX,
+            w_max: 0.0,
+            w_est: 0.0,
+            k: 0.0,
+            epoch_start: None,
+        }
+    }
+
+    #[inline]
+    fn segments(&self, bytes: u32) -> f64 {
+        bytes as f64 / self.mss as f64
+    }
+}
+
+impl CongestionControl for Cubic {
+    fn name(&self) -> &'static str {
+        "cubic"
+    }
+
+    fn cwnd(&self) -> u32 {
+        self.cwnd
+    }
+
+    fn ssthresh(&self) -> u32 {
+        self.ssthresh
+    }
+
+    fn on_ack(&mut self, acked: u32, now: Instant, rtt: Option<Duration>) {
+        if self.cwnd < self.ssthresh {
+            self.cwnd = self.cwnd.saturating_add(acked.min(self.mss));
+            return;
+        }
+
+        let cwnd = self.segments(self.cwnd);
+        let epoch_start = match self.epoch_start {
+            Some(epoch_start) => epoch_start,
+            None => {
+                // A new congestion avoidance epoch, RFC 9438 section 4.2
+                self.w_est = cwnd;
+                self.k = if self.w_max > cwnd { ((self.w_max - cwnd) / C).cbrt() } else { 0.0 };
+                if self.w_max < cwnd {
+                    self.w_max = cwnd;
+                }
+                self.epoch_start = Some(now);
+                now
+            }
+        };
+
+        let t = (now.duration_since(epoch_start) + rtt.unwrap_or_default()).as_secs_f64();
+        // The target is kept between the current window and one and a half times of it, RFC 9438 section 4.2
+        let target = (C * (t - self.k).powi(3) + self.w_max).clamp(cwnd, 1.5 * cwnd);
+
+        // Reno-friendly region, RFC 9438 section 4.3
+        let alpha = 3.0 * (1.0 - BETA) / (1.0 + BETA);
+        self.w_est += alpha * self.segments(acked) / cwnd;
+
+        let increment = (target - cwnd) / cwnd * self.segments(acked);
+        let next = (cwnd + increment).max(self.w_est);
+
+        self.cwnd = ((next * self.mss as f64) as u32).max(self.cwnd);
+    }
+
+    fn on_loss(&mut self, event: LossEvent, _flight_size: u32, _now: Instant) {
+        let cwnd = self.segments(self.cwnd);
+
+        // Fast convergence, RFC 9438 section 4.7
+        self.w_max = if cwnd < self.w_max { cwnd * (1.0 + BETA) / 2.0 } else { cwnd };
+        self.epoch_start = None;
+
+        self.ssthresh = ((self.cwnd as f64 * BETA) as u32).max(2 * self.mss);
+        self.cwnd = match event {
+            LossEvent::FastRetransmit => self.ssthresh,
+            LossEvent::Timeout => self.mss,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSS: u32 = 1000;
+
+    /// A CUBIC instance that just reduced its window after a loss at 100 segments.
+    fn after_loss() -> Cubic {
+        let mut cubic = Cubic::new(MSS);
+        cubic.cwnd = 100 * MSS;
+        cubic.on_loss(LossEvent::FastRetransmit, 100 * MSS, Instant::now());
+        cubic
+    }
+
+    #[test]
+    fn slow_start_grows_by_at_most_one_mss_per_ack() {
+        let mut cubic = Cubic::new(MSS);
+        assert_eq!(cubic.cwnd(), 4000);
+
+        cubic.on_ack(MSS, Instant::now(), None);
+        assert_eq!(cubic.cwnd(), 5000);
+        cubic.on_ack(3 * MSS, Instant::now(), None);
+        assert_eq!(cubic.cwnd(), 6000);
+    }
+
+    #[test]
+    fn a_loss_reduces_the_window_by_beta() {
+        let mut cubic = after_loss();
+        assert_eq!((cubic.cwnd(), cubic.ssthresh()), (70 * MSS, 70 * MSS));
+        assert_eq!(cubic.w_max, 100.0);
+
+        // Fast convergence: a loss below the last maximum lowers it further
+        cubic.on_loss(LossEvent::Timeout, 70 * MSS, Instant::now());
+        assert_eq!(cubic.w_max, 59.5);
+        assert_eq!((cubic.cwnd(), cubic.ssthresh()), (MSS, 49 * MSS));
+    }
+
+    #[test]
+    fn grows_towards_the_window_one_rtt_ahead() {
+        let start = Instant::now();
+        let mut now = after_loss();
+        let mut ahead = after_loss();
+        now.on_ack(MSS, start, None);
+        ahead.on_ack(MSS, start, None);
+
+        let later = start + Duration::from_secs(1);
+        now.on_ack(MSS, later, None);
+        ahead.on_ack(MSS, later, Some(Duration::from_secs(1)));
+        assert!(ahead.cwnd() > now.cwnd(), "{} <= {}", ahead.cwnd(), now.cwnd());
+    }
+
+    #[test]
+    fn congestion_avoidance_returns_to_the_last_maximum_after_k() {
+        let mut cubic = after_loss();
+        let rtt = Duration::from_millis(100);
+        let start = Instant::now();
+
+        for round in 0..80 {
+            let elapsed = rtt * round;
+            for _ in 0..cubic.cwnd() / MSS {
+                cubic.on_ack(MSS, start + elapsed, Some(rtt));
+            }
+
+            let t = (elapsed + rtt).as_secs_f64();
+            if t <= cubic.k / 2.0 {
+                assert!(cubic.cwnd() < 100 * MSS, "{} bytes after {}s", cubic.cwnd(), t);
+            }
+            if elapsed.as_secs_f64() >= cubic.k {
+                assert!(cubic.cwnd() >= 99 * MSS, "{} bytes after {}s", cubic.cwnd(), t);
+            }
+        }
+        // Convex growth past the last maximum
+        assert!(cubic.cwnd() > 110 * MSS, "{} bytes", cubic.cwnd());
+    }
+}
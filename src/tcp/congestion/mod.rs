@@ -0,0 +1,3 @@
+pub mod congestion_control;
+pub mod new_reno;
+pub mod cubic;
\ No newline at end of file
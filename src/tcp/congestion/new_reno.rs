@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use crate::tcp::congestion::congestion_control::{CongestionControl, initial_window, LossEvent};
+
+/// NewReno congestion control, RFC 5681 and RFC 6582.
+///
+/// Slow start grows the window by at most one MSS per ACK, congestion avoidance by one MSS per window
+/// using appropriate byte counting. Fast recovery itself is handled by `Congestion`.
+pub struct NewReno {
+    mss: u32,
+    cwnd: u32,
+    ssthresh: u32,
+    bytes_acked: u32,
+}
+
+impl NewReno {
+    pub fn new(mss: u32) -> Self {
+        NewReno {
+            mss,
+            cwnd: initial_window(mss),
+            ssthresh: u32::MAX,
+            bytes_acked: 0,
+        }
+    }
+}
+
+impl CongestionControl for NewReno {
+    fn name(&self) -> &'static str {
+        "newreno"
+    }
+
+    fn cwnd(&self) -> u32 {
+        self.cwnd
+    }
+
+    fn ssthresh(&self) -> u32 {
+        self.ssthresh
+    }
+
+    fn on_ack(&mut self, acked: u32, _now: Instant, _rtt: Option<Duration>) {
+        if self.cwnd < self.ssthresh {
+            self.cwnd = self.cwnd.saturating_add(acked.min(self.mss));
+            return;
+        }
+
+        self.bytes_acked += acked;
+        if self.bytes_acked >= self.cwnd {
+            self.bytes_acked -= self.cwnd;
+            self.cwnd = self.cwnd.saturating_add(self.mss);
+        }
+    }
+
+    fn on_loss(&mut self, event: LossEvent, flight_size: u32, _now: Instant) {
+        self.ssthresh = (flight_size / 2).max(2 * self.mss);
+        self.bytes_acked = 0;
+        self.cwnd = match event {
+            LossEvent::FastRetransmit => self.ssthresh,
+            LossEvent::Timeout => self.mss,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSS: u32 = 1000;
+
+    #[test]
+    fn slow_start_grows_by_at_most_one_mss_per_ack() {
+        let mut reno = NewReno::new(MSS);
+        assert_eq!(reno.cwnd(), 4000);
+
+        reno.on_ack(MSS, Instant::now(), None);
+        assert_eq!(reno.cwnd(), 5000);
+        reno.on_ack(3 * MSS, Instant::now(), None);
+        assert_eq!(reno.cwnd(), 6000);
+    }
+
+    #[test]
+    fn congestion_avoidance_grows_by_one_mss_per_window() {
+        let mut reno = NewReno::new(MSS);
+        reno.on_loss(LossEvent::FastRetransmit, 20 * MSS, Instant::now());
+        assert_eq!((reno.cwnd(), reno.ssthresh()), (10 * MSS, 10 * MSS));
+
+        for _ in 0..9 {
+            reno.on_ack(MSS, Instant::now(), None);
+        }
+        assert_eq!(reno.cwnd(), 10 * MSS);
+        reno.on_ack(MSS, Instant::now(), None);
+        assert_eq!(reno.cwnd(), 11 * MSS);
+    }
+
+    #[test]
+    fn a_timeout_starts_over_from_one_mss() {
+        let mut reno = NewReno::new(MSS);
+        reno.on_loss(LossEvent::Timeout, 20 * MSS, Instant::now());
+        assert_eq!((reno.cwnd(), reno.ssthresh()), (MSS, 10 * MSS));
+
+        // ssthresh never drops below two segments
+        reno.on_loss(LossEvent::Timeout, MSS, Instant::now());
+        assert_eq!(reno.ssthresh(), 2 * MSS);
+    }
+}
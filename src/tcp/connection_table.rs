@@ -0,0 +1,190 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+
+use crate::tcp::capture::PcapWriter;
+use crate::tcp::link::device::Link;
+use crate::tcp::packet::data::{Controller, ReceiveData};
+use crate::tcp::packet::ip_header::IpVersion;
+use crate::tcp::processor::ProcessorRegistry;
+use crate::tcp::settings::Settings;
+use crate::tcp::util::ChangingOrderSizes;
+use crate::tcp::worker::bus::PacketBus;
+
+/// Identifies a connection by the addresses and ports of its inbound segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionKey {
+    pub source_address: IpAddr,
+    pub source_port: u16,
+    pub destination_address: IpAddr,
+    pub destination_port: u16,
+}
+
+impl ConnectionKey {
+    /// The key of the connection an inbound segment belongs to.
+    pub fn of(receive: &ReceiveData) -> Self {
+        ConnectionKey {
+            source_address: receive.ip_head.source(),
+            source_port: receive.tcphdr.source.to_host(),
+            destination_address: receive.ip_head.destination(),
+            destination_port: receive.tcphdr.dest.to_host(),
+        }
+    }
+}
+
+/// What the receive loop does with a segment whose checksum does not match.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumPolicy {
+    /// The segment is counted and dropped, as RFC 9293 section 3.1 asks
+    #[default]
+    Drop,
+    /// The segment is counted and processed anyway
+    Count,
+}
+
+/// What the receive loop does with a segment that belongs to no connection and opens none.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownSegmentPolicy {
+    /// The segment is dropped silently. A raw socket sees the segments of every connection of the host, the
+    /// kernel answers those of its own connections and of ports nobody listens on
+    #[default]
+    Drop,
+    /// The segment is answered with a RST, as RFC 9293 section 3.10.7.1 asks, for links whose addresses are ours
+    /// alone
+    Reset,
+}
+
+/// A connection in the table, with the bus its segments are published to.
+pub type Connection = (Arc<Controller>, Arc<PacketBus>);
+
+/// Every connection driven over one link, and the ports that accept new ones.
+pub struct ConnectionTable {
+    /// Where the packets of every connection in the table go out and come in
+    pub link: Arc<dyn Link>,
+    pub(crate) connections: DashMap<ConnectionKey, Connection>,
+    /// Listening ports, with the settings of the connections they accept
+    pub(crate) listeners: DashMap<u16, Settings>,
+    /// Where the packets of every connection in the table are recorded
+    pub capture: Option<Arc<PcapWriter>>,
+    pub checksum_policy: ChecksumPolicy,
+    pub unknown_segment_policy: UnknownSegmentPolicy,
+    /// Segments received with a wrong checksum
+    pub(crate) checksum_errors: AtomicU64,
+    /// Processors every connection of the table gets on top of the built-in ones, replacing those of the same name
+    pub processors: ProcessorRegistry,
+}
+
+impl ConnectionTable {
+    pub fn new(link: Arc<dyn Link>) -> Self {
+        ConnectionTable {
+            link,
+            connections: DashMap::default(),
+            listeners: DashMap::default(),
+            capture: None,
+            checksum_policy: ChecksumPolicy::default(),
+            unknown_segment_policy: UnknownSegmentPolicy::default(),
+            checksum_errors: AtomicU64::new(0),
+            processors: ProcessorRegistry::default(),
+        }
+    }
+
+    /// Records every packet sent or received by the connections of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `capture` - The pcap file the packets are written to, it may be shared with other tables
+    pub fn with_capture(mut self, capture: Arc<PcapWriter>) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Sets what is done with segments whose checksum does not match.
+    pub fn with_checksum_policy(mut self, checksum_policy: ChecksumPolicy) -> Self {
+        self.checksum_policy = checksum_policy;
+        self
+    }
+
+    /// Sets what is done with segments that belong to no connection and open none.
+    pub fn with_unknown_segment_policy(mut self, unknown_segment_policy: UnknownSegmentPolicy) -> Self {
+        self.unknown_segment_policy = unknown_segment_policy;
+        self
+    }
+
+    /// Whether connections of an IP version can be driven in the table.
+    #[inline]
+    pub fn supports(&self, version: IpVersion) -> bool {
+        self.link.supports(version)
+    }
+
+    /// How many segments were received with a wrong checksum.
+    #[inline]
+    pub fn checksum_errors(&self) -> u64 {
+        self.checksum_errors.load(Ordering::Relaxed)
+    }
+
+    /// Adds a connection to the table and spawns its workers.
+    ///
+    /// # Arguments
+    ///
+    /// * `controller` - The connection
+    ///
+    /// # Returns
+    ///
+    /// * `Arc<Controller>` - The connection, shared with the table
+    ///
+    /// # Remarks
+    ///
+    /// The connection is removed from the table once it reaches CLOSED, which also stops its processors.
+    pub fn register(self: &Arc<Self>, mut controller: Controller) -> Arc<Controller> {
+        controller.capture = self.capture.clone();
+        controller.processors.extend(&self.processors);
+        let controller = Arc::new(controller);
+        let bus = Arc::new(controller.spawn_workers());
+        let key = controller.key();
+        self.connections.insert(key, (controller.clone(), bus));
+
+        let table = self.clone();
+        let closing = controller.clone();
+        tokio::spawn(async move {
+            closing.wait_closed().await;
+            table.connections.remove_if(&key, |_, (controller, _)| Arc::ptr_eq(controller, &closing));
+        });
+
+        controller
+    }
+
+    /// Looks up the connection an inbound segment belongs to.
+    pub fn get(&self, key: &ConnectionKey) -> Option<Connection> {
+        self.connections.get(key).map(|connection| connection.value().clone())
+    }
+
+    /// Every connection in the table.
+    pub fn controllers(&self) -> Vec<Arc<Controller>> {
+        self.connections.iter().map(|connection| connection.value().0.clone()).collect()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+}
+
+/// Controller struct implementation
+impl Controller {
+    /// The key inbound segments of this connection carry.
+    pub fn key(&self) -> ConnectionKey {
+        ConnectionKey {
+            source_address: self.sockaddr_to_remote.address(),
+            source_port: self.sockaddr_to_remote.port(),
+            destination_address: self.local_address,
+            destination_port: self.local_port,
+        }
+    }
+}
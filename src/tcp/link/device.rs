@@ -0,0 +1,67 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::tcp::packet::data::RemoteSockaddr;
+use crate::tcp::packet::ip_header::{IpHeader, IpVersion};
+use crate::tcp::packet::parser::IPV6_HEADER_LENGTH;
+
+/// The largest packet a link reads, anything behind it is cut off.
+pub const RECEIVE_BUFFER_SIZE: usize = 4096;
+
+/// Where the IP packets of a connection table go out and come in.
+///
+/// A link is shared by the receive loop of its table, which blocks in `receive`, and by every connection of the
+/// table sending through it, so both have to be callable from several threads at once.
+pub trait Link: Send + Sync {
+    /// Whether packets of an IP version can go over the link.
+    fn supports(&self, version: IpVersion) -> bool;
+
+    /// Sends a packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `ip_head` - The IP header of the packet
+    /// * `packet` - The packet as `TCPPacket` builds it: the whole IPv4 datagram, or only the TCP segment for IPv6
+    /// * `remote` - The remote's socket address
+    ///
+    /// # Returns
+    ///
+    /// * `isize` - The number of bytes sent, negative if the packet could not be sent
+    fn send(&self, ip_head: &IpHeader, packet: &[u8], remote: &RemoteSockaddr) -> isize;
+
+    /// Waits for the next packet.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(Bytes, Option<IpHeader>)>` - The packet, and its IP header if the packet does not start with it;
+    ///   None if nothing could be read before the receive timeout
+    fn receive(&self) -> Option<(Bytes, Option<IpHeader>)>;
+
+    /// Makes `receive` give up after a while, so a loop reading the link can notice it is no longer needed.
+    fn set_receive_timeout(&self, timeout: Duration) -> Result<(), String>;
+
+    /// Whether the link was shut down, the receive loop of its table stops then.
+    fn is_closed(&self) -> bool {
+        false
+    }
+}
+
+/// The whole IP datagram of a packet, for links that carry the IP header of IPv6 packets as well.
+///
+/// # Arguments
+///
+/// * `ip_head` - The IP header of the packet
+/// * `packet` - The packet as `TCPPacket` builds it: the whole IPv4 datagram, or only the TCP segment for IPv6
+pub fn datagram<'a>(ip_head: &IpHeader, packet: &'a [u8]) -> Cow<'a, [u8]> {
+    match ip_head {
+        IpHeader::V4(_) => Cow::Borrowed(packet),
+        IpHeader::V6(header) => {
+            let mut datagram = Vec::with_capacity(IPV6_HEADER_LENGTH + packet.len());
+            datagram.extend_from_slice(&header.to_bytes(packet.len()));
+            datagram.extend_from_slice(packet);
+            Cow::Owned(datagram)
+        }
+    }
+}
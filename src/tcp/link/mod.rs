@@ -0,0 +1,4 @@
+pub mod device;
+pub mod raw_socket;
+pub mod simulated;
+pub mod tun;
@@ -0,0 +1,178 @@
+use std::ffi::c_int;
+use std::mem::{size_of, size_of_val};
+use std::net::Ipv6Addr;
+use std::os::raw::c_void;
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use colored::Colorize;
+use log::trace;
+
+use crate::raw_bindings::raw_bindings::{cmsghdr, iovec, IPPROTO_IPV6, IPV6_PKTINFO, msghdr, recvfrom, recvmsg, sendto, sockaddr, sockaddr_in, sockaddr_in6};
+use crate::tcp::link::device::{Link, RECEIVE_BUFFER_SIZE};
+use crate::tcp::packet::data::RemoteSockaddr;
+use crate::tcp::packet::ip_header::{IpHeader, IpVersion, Ipv6Header};
+use crate::tcp::socket::{raw_socket, set_receive_timeout};
+
+/// A raw socket of the kernel, packets go wherever the kernel routes them.
+///
+/// The kernel also sees every segment we receive, and answers those of connections it does not know with a RST.
+/// They have to be filtered out, with iptables for example, for connections to get past the handshake.
+pub struct RawSocketLink {
+    pub socket: c_int,
+    /// The IP version of the socket, every packet sent or received over it uses it
+    pub version: IpVersion,
+}
+
+impl RawSocketLink {
+    /// Opens a raw socket of an IP version.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The IP version of the socket
+    ///
+    /// # Returns
+    ///
+    /// * `Result<RawSocketLink, String>` - The link, or an error if the socket can not be created
+    pub fn open(version: IpVersion) -> Result<RawSocketLink, String> {
+        Ok(RawSocketLink {
+            socket: raw_socket(version)?,
+            version,
+        })
+    }
+}
+
+impl Drop for RawSocketLink {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.socket);
+        }
+    }
+}
+
+impl Link for RawSocketLink {
+    fn supports(&self, version: IpVersion) -> bool {
+        self.version == version
+    }
+
+    fn send(&self, _ip_head: &IpHeader, packet: &[u8], remote: &RemoteSockaddr) -> isize {
+        let (sockaddr, sockaddr_len) = remote.as_sockaddr();
+        unsafe {
+            sendto(
+                self.socket,
+                packet.as_ptr() as *const c_void,
+                packet.len(),
+                0,
+                sockaddr,
+                sockaddr_len
+            )
+        }
+    }
+
+    fn receive(&self) -> Option<(Bytes, Option<IpHeader>)> {
+        let mut buffer = {
+            let mut buffer = BytesMut::with_capacity(RECEIVE_BUFFER_SIZE);
+            buffer.resize(RECEIVE_BUFFER_SIZE, 0);
+            buffer
+        };
+
+        // IPv4 raw sockets hand out the IP header in front of the segment, IPv6 raw sockets only the segment
+        let (ip_head, receive_size) = match self.version {
+            IpVersion::V4 => {
+                let mut sockaddr_in = sockaddr_in::default();
+                let mut addr_len = size_of::<sockaddr>() as u32;
+
+                let receive_size = unsafe {
+                    recvfrom(
+                        self.socket,
+                        buffer.as_mut_ptr() as *mut c_void,
+                        buffer.len(),
+                        0,
+                        &mut sockaddr_in as *mut sockaddr_in as *mut sockaddr,
+                        &mut addr_len as *mut u32,
+                    )
+                };
+                if receive_size < 0 {
+                    return None;
+                }
+                (None, receive_size as usize)
+            }
+            IpVersion::V6 => {
+                let (ip_head, receive_size) = receive_v6(self.socket, &mut buffer)?;
+                (Some(IpHeader::V6(ip_head)), receive_size)
+            }
+        };
+        buffer.truncate(receive_size);
+
+        Some((buffer.freeze(), ip_head))
+    }
+
+    fn set_receive_timeout(&self, timeout: Duration) -> Result<(), String> {
+        set_receive_timeout(self.socket, timeout)
+    }
+}
+
+/// Receives a segment from an IPv6 raw socket, with the addresses it was sent from and to.
+///
+/// # Arguments
+///
+/// * `socket` - The raw socket, with IPV6_RECVPKTINFO enabled
+/// * `buffer` - The buffer the segment is written to
+///
+/// # Returns
+///
+/// * `Option<(Ipv6Header, usize)>` - The addresses of the segment and its length
+fn receive_v6(socket: c_int, buffer: &mut BytesMut) -> Option<(Ipv6Header, usize)> {
+    let mut source = sockaddr_in6::default();
+    // Aligned for the cmsghdr structures written into it
+    let mut control = [0u64; 16];
+    let mut iov = iovec {
+        iov_base: buffer.as_mut_ptr() as *mut c_void,
+        iov_len: buffer.len(),
+    };
+    let mut message = msghdr {
+        msg_name: &mut source as *mut sockaddr_in6 as *mut c_void,
+        msg_namelen: size_of::<sockaddr_in6>() as u32,
+        msg_iov: &mut iov as *mut iovec,
+        msg_iovlen: 1,
+        msg_control: control.as_mut_ptr() as *mut c_void,
+        msg_controllen: size_of_val(&control),
+        msg_flags: 0,
+    };
+
+    let receive_size = unsafe { recvmsg(socket, &mut message as *mut msghdr, 0) };
+    if receive_size < 0 {
+        return None;
+    }
+
+    // The destination address comes with the IPV6_PKTINFO control message
+    let mut destination = None;
+    let control = unsafe { std::slice::from_raw_parts(control.as_ptr() as *const u8, message.msg_controllen) };
+    let mut offset = 0;
+    while offset + size_of::<cmsghdr>() <= control.len() {
+        let header = unsafe { std::ptr::read_unaligned(control.as_ptr().add(offset) as *const cmsghdr) };
+        if header.cmsg_len < size_of::<cmsghdr>() {
+            break;
+        }
+
+        let data = offset + size_of::<cmsghdr>();
+        if header.cmsg_level == IPPROTO_IPV6 as c_int && header.cmsg_type == IPV6_PKTINFO as c_int && data + 16 <= control.len() {
+            let mut address = [0u8; 16];
+            address.copy_from_slice(&control[data..data + 16]);
+            destination = Some(Ipv6Addr::from(address));
+        }
+        offset += (header.cmsg_len + size_of::<usize>() - 1) & !(size_of::<usize>() - 1);
+    }
+
+    let Some(destination) = destination else {
+        trace!("{}", "Received IPv6 packet without its destination address, thrown.".truecolor(25, 160, 60));
+        return None;
+    };
+
+    let header = Ipv6Header {
+        source: Ipv6Addr::from(unsafe { source.sin6_addr.__in6_u.__u6_addr8 }),
+        destination,
+        payload_length: receive_size as u16,
+    };
+    Some((header, receive_size as usize))
+}
@@ -0,0 +1,287 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use parking_lot::{Condvar, Mutex};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::tcp::link::device::{datagram, Link};
+use crate::tcp::packet::data::RemoteSockaddr;
+use crate::tcp::packet::ip_header::{IpHeader, IpVersion};
+
+/// How a simulated channel treats the packets sent over it.
+///
+/// Chances are between 0 and 1, every packet is decided on by itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulationSettings {
+    /// Chance that a packet is lost
+    pub loss: f64,
+    /// Chance that a packet is held back for `reorder_delay`, so the packets sent after it overtake it
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// Chance that a packet is delivered twice
+    pub duplicate: f64,
+    /// Chance that a bit of a packet is flipped
+    pub corrupt: f64,
+    /// How long every packet takes to arrive
+    pub latency: Duration,
+    /// Up to how much longer a packet may take, chosen at random for each one
+    pub jitter: Duration,
+    /// Seed of the random decisions, the same seed makes the same decisions for the same packets
+    pub seed: u64,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        SimulationSettings {
+            loss: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(20),
+            duplicate: 0.0,
+            corrupt: 0.0,
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            seed: 0,
+        }
+    }
+}
+
+/// What a simulated channel did to the packets sent over it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SimulationStatistics {
+    pub sent: u64,
+    pub lost: u64,
+    pub reordered: u64,
+    pub duplicated: u64,
+    pub corrupted: u64,
+}
+
+/// A packet on its way, ordered by the time it arrives and then by the order it was sent in.
+struct InFlight {
+    arrival: Instant,
+    order: u64,
+    packet: Bytes,
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.arrival, self.order).cmp(&(other.arrival, other.order))
+    }
+}
+
+/// One direction of a simulated channel.
+struct Channel {
+    settings: SimulationSettings,
+    random: StdRng,
+    in_flight: BinaryHeap<Reverse<InFlight>>,
+    /// Order of the next packet put on its way
+    next_order: u64,
+    statistics: SimulationStatistics,
+    closed: bool,
+}
+
+impl Channel {
+    fn new(settings: SimulationSettings, seed: u64) -> Self {
+        Channel {
+            settings,
+            random: StdRng::seed_from_u64(seed),
+            in_flight: BinaryHeap::new(),
+            next_order: 0,
+            statistics: SimulationStatistics::default(),
+            closed: false,
+        }
+    }
+
+    /// Decides what happens to a packet and puts whatever is left of it on its way.
+    fn send(&mut self, packet: Bytes) {
+        let now = Instant::now();
+        self.statistics.sent += 1;
+
+        if self.random.gen::<f64>() < self.settings.loss {
+            self.statistics.lost += 1;
+            return;
+        }
+
+        let copies = if self.random.gen::<f64>() < self.settings.duplicate {
+            self.statistics.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let mut packet = packet.clone();
+            if !packet.is_empty() && self.random.gen::<f64>() < self.settings.corrupt {
+                self.statistics.corrupted += 1;
+                let mut corrupted = BytesMut::from(&packet[..]);
+                let index = self.random.gen_range(0..corrupted.len());
+                corrupted[index] ^= 1 << self.random.gen_range(0..8);
+                packet = corrupted.freeze();
+            }
+
+            let mut delay = self.settings.latency + self.settings.jitter.mul_f64(self.random.gen::<f64>());
+            if self.random.gen::<f64>() < self.settings.reorder {
+                self.statistics.reordered += 1;
+                delay += self.settings.reorder_delay;
+            }
+
+            self.in_flight.push(Reverse(InFlight { arrival: now + delay, order: self.next_order, packet }));
+            self.next_order += 1;
+        }
+    }
+}
+
+/// A direction of a channel, with the condition its receiver waits on.
+struct Direction {
+    channel: Mutex<Channel>,
+    arrived: Condvar,
+}
+
+/// One end of an in-process channel between two connection tables, for tests that need neither root nor a peer.
+///
+/// Packets are delivered whole, with their IP header, after the channel decided from its seeded random numbers
+/// whether to lose, hold back, duplicate or corrupt them. Each direction has its own random numbers, so a run sending
+/// the same packets in each direction meets the same fate.
+///
+/// The checksums of segments from loopback addresses are not checked, the ends should use other addresses for
+/// corrupted packets to be noticed.
+pub struct SimulatedLink {
+    outbound: Arc<Direction>,
+    inbound: Arc<Direction>,
+    /// How long `receive` waits for a packet, it waits forever if not set
+    receive_timeout: Mutex<Option<Duration>>,
+}
+
+impl SimulatedLink {
+    /// Creates both ends of a channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - How both directions of the channel treat their packets
+    ///
+    /// # Returns
+    ///
+    /// * `(Arc<SimulatedLink>, Arc<SimulatedLink>)` - The ends, what one sends the other receives
+    pub fn pair(settings: SimulationSettings) -> (Arc<SimulatedLink>, Arc<SimulatedLink>) {
+        let forward = Arc::new(Direction {
+            channel: Mutex::new(Channel::new(settings, settings.seed)),
+            arrived: Condvar::new(),
+        });
+        let backward = Arc::new(Direction {
+            channel: Mutex::new(Channel::new(settings, settings.seed.wrapping_add(1))),
+            arrived: Condvar::new(),
+        });
+
+        let first = SimulatedLink {
+            outbound: forward.clone(),
+            inbound: backward.clone(),
+            receive_timeout: Mutex::new(None),
+        };
+        let second = SimulatedLink {
+            outbound: backward,
+            inbound: forward,
+            receive_timeout: Mutex::new(None),
+        };
+        (Arc::new(first), Arc::new(second))
+    }
+
+    /// What the channel did to the packets this end sent.
+    pub fn statistics(&self) -> SimulationStatistics {
+        self.outbound.channel.lock().statistics
+    }
+
+    /// Changes how the channel treats the packets this end sends from now on, packets on their way are left alone.
+    ///
+    /// # Remarks
+    ///
+    /// The seed is not used again, the direction keeps drawing from the random numbers it was seeded with.
+    pub fn set_settings(&self, settings: SimulationSettings) {
+        self.outbound.channel.lock().settings = settings;
+    }
+
+    /// Shuts the channel down for both ends, packets still on their way are lost.
+    ///
+    /// # Remarks
+    ///
+    /// The receive loops of the tables on both ends return, which a runtime waits for before it shuts down.
+    pub fn close(&self) {
+        for direction in [&self.outbound, &self.inbound] {
+            let mut channel = direction.channel.lock();
+            channel.closed = true;
+            channel.in_flight.clear();
+            drop(channel);
+            direction.arrived.notify_all();
+        }
+    }
+}
+
+impl Link for SimulatedLink {
+    fn supports(&self, _version: IpVersion) -> bool {
+        true
+    }
+
+    fn send(&self, ip_head: &IpHeader, packet: &[u8], _remote: &RemoteSockaddr) -> isize {
+        let mut channel = self.outbound.channel.lock();
+        if channel.closed {
+            return -1;
+        }
+
+        channel.send(Bytes::copy_from_slice(&datagram(ip_head, packet)));
+        self.outbound.arrived.notify_all();
+        packet.len() as isize
+    }
+
+    fn receive(&self) -> Option<(Bytes, Option<IpHeader>)> {
+        let deadline = self.receive_timeout.lock().map(|timeout| Instant::now() + timeout);
+
+        let mut channel = self.inbound.channel.lock();
+        loop {
+            if channel.closed {
+                return None;
+            }
+
+            let now = Instant::now();
+            let arrival = channel.in_flight.peek().map(|Reverse(next)| next.arrival);
+            if arrival.is_some_and(|arrival| arrival <= now) {
+                return channel.in_flight.pop().map(|Reverse(next)| (next.packet, None));
+            }
+            if deadline.is_some_and(|deadline| deadline <= now) {
+                return None;
+            }
+
+            // Sleeps until the next packet arrives, the deadline passes or a packet is sent, whichever comes first
+            match arrival.into_iter().chain(deadline).min() {
+                Some(wake) => {
+                    self.inbound.arrived.wait_until(&mut channel, wake);
+                }
+                None => self.inbound.arrived.wait(&mut channel),
+            }
+        }
+    }
+
+    fn set_receive_timeout(&self, timeout: Duration) -> Result<(), String> {
+        *self.receive_timeout.lock() = Some(timeout);
+        Ok(())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inbound.channel.lock().closed
+    }
+}
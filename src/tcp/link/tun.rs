@@ -0,0 +1,216 @@
+use std::ffi::{c_int, c_ulong, CStr};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use colored::Colorize;
+use parking_lot::Mutex;
+use tracing::info;
+
+use crate::tcp::link::device::{datagram, Link, RECEIVE_BUFFER_SIZE};
+use crate::tcp::packet::data::RemoteSockaddr;
+use crate::tcp::packet::ip_header::{IpHeader, IpVersion};
+
+/// The ioctl attaching a file of `/dev/net/tun` to a device, `_IOW('T', 202, int)`.
+const TUNSETIFF: c_ulong = 0x400454ca;
+
+/// A TUN device, every IP packet the kernel routes to it is read by us and every packet we write to it is received
+/// by the kernel as if it came in over a network.
+///
+/// The addresses of our connections are not addresses of the kernel, so it never answers our segments with RSTs.
+/// The device gets an address of its own for the kernel, packets to the rest of its network are routed to us.
+pub struct TunLink {
+    file: File,
+    /// The name of the device, as the kernel chose it if none was asked for
+    pub name: String,
+    /// How long `receive` waits for a packet, it waits forever if not set
+    receive_timeout: Mutex<Option<Duration>>,
+}
+
+impl TunLink {
+    /// Creates a TUN device, or attaches to an existing one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the device, a pattern like `tun%d` lets the kernel number it
+    ///
+    /// # Returns
+    ///
+    /// * `Result<TunLink, String>` - The device, or an error if it can not be created
+    ///
+    /// # Remarks
+    ///
+    /// Creating a TUN device needs root or the CAP_NET_ADMIN capability. The device is removed once the link is
+    /// dropped, unless it was made persistent.
+    pub fn open(name: &str) -> Result<TunLink, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")
+            .map_err(|e| format!("Can not open /dev/net/tun: {}", e))?;
+
+        let mut request = interface_request(name)?;
+        request.ifr_ifru.ifru_flags = (libc::IFF_TUN | libc::IFF_NO_PI) as i16;
+        if unsafe { libc::ioctl(file.as_raw_fd(), TUNSETIFF, &mut request as *mut libc::ifreq) } == -1 {
+            return Err(format!("Can not create TUN device {}: {}", name, io::Error::last_os_error()));
+        }
+
+        let name = unsafe { CStr::from_ptr(request.ifr_name.as_ptr()) }.to_string_lossy().into_owned();
+        info!("Create TUN device success, name: {}", name.red());
+
+        Ok(TunLink {
+            file,
+            name,
+            receive_timeout: Mutex::new(None),
+        })
+    }
+
+    /// Gives the kernel's end of the device an address, and routes the rest of its network to the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The kernel's address, our connections use other addresses of the network
+    /// * `prefix_length` - The length of the network prefix
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if the address can not be set
+    pub fn add_address(&self, address: IpAddr, prefix_length: u8) -> Result<(), String> {
+        match address {
+            IpAddr::V4(address) => {
+                if prefix_length > 32 {
+                    return Err(format!("Invalid prefix length {} for {}", prefix_length, address));
+                }
+                let socket = control_socket(libc::AF_INET)?;
+
+                let mut request = interface_request(&self.name)?;
+                request.ifr_ifru.ifru_addr = sockaddr(u32::from(address));
+                self.control(&socket, libc::SIOCSIFADDR, &mut request as *mut libc::ifreq as *mut _)?;
+
+                let netmask = u32::MAX.checked_shl(32 - prefix_length as u32).unwrap_or(0);
+                request.ifr_ifru.ifru_netmask = sockaddr(netmask);
+                self.control(&socket, libc::SIOCSIFNETMASK, &mut request as *mut libc::ifreq as *mut _)
+            }
+            IpAddr::V6(address) => {
+                if prefix_length > 128 {
+                    return Err(format!("Invalid prefix length {} for {}", prefix_length, address));
+                }
+                let socket = control_socket(libc::AF_INET6)?;
+
+                let mut request = interface_request(&self.name)?;
+                self.control(&socket, libc::SIOCGIFINDEX, &mut request as *mut libc::ifreq as *mut _)?;
+
+                let mut address_request = libc::in6_ifreq {
+                    ifr6_addr: libc::in6_addr { s6_addr: address.octets() },
+                    ifr6_prefixlen: prefix_length as u32,
+                    ifr6_ifindex: unsafe { request.ifr_ifru.ifru_ifindex },
+                };
+                self.control(&socket, libc::SIOCSIFADDR, &mut address_request as *mut libc::in6_ifreq as *mut _)
+            }
+        }
+    }
+
+    /// Brings the device up, the kernel routes nothing to it before.
+    pub fn up(&self) -> Result<(), String> {
+        let socket = control_socket(libc::AF_INET)?;
+        let mut request = interface_request(&self.name)?;
+        self.control(&socket, libc::SIOCGIFFLAGS, &mut request as *mut libc::ifreq as *mut _)?;
+        unsafe {
+            request.ifr_ifru.ifru_flags |= libc::IFF_UP as i16;
+        }
+        self.control(&socket, libc::SIOCSIFFLAGS, &mut request as *mut libc::ifreq as *mut _)
+    }
+
+    /// Runs an interface ioctl on a control socket.
+    fn control(&self, socket: &OwnedFd, request: c_ulong, argument: *mut libc::c_void) -> Result<(), String> {
+        if unsafe { libc::ioctl(socket.as_raw_fd(), request, argument) } == -1 {
+            return Err(format!("Can not configure {}: {}", self.name, io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// Waits for the device to become readable, false if the receive timeout passed first.
+    fn wait_readable(&self) -> bool {
+        let Some(timeout) = *self.receive_timeout.lock() else {
+            return true;
+        };
+
+        let mut poll = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut poll as *mut libc::pollfd, 1, timeout.as_millis() as c_int) > 0 }
+    }
+}
+
+impl Link for TunLink {
+    fn supports(&self, _version: IpVersion) -> bool {
+        true
+    }
+
+    fn send(&self, ip_head: &IpHeader, packet: &[u8], _remote: &RemoteSockaddr) -> isize {
+        // The device takes whole datagrams, an IPv6 segment gets the header the kernel would otherwise add
+        match (&self.file).write(&datagram(ip_head, packet)) {
+            Ok(size) => size as isize,
+            Err(_) => -1,
+        }
+    }
+
+    fn receive(&self) -> Option<(Bytes, Option<IpHeader>)> {
+        if !self.wait_readable() {
+            return None;
+        }
+
+        let mut buffer = {
+            let mut buffer = BytesMut::with_capacity(RECEIVE_BUFFER_SIZE);
+            buffer.resize(RECEIVE_BUFFER_SIZE, 0);
+            buffer
+        };
+        let receive_size = (&self.file).read(&mut buffer).ok()?;
+        buffer.truncate(receive_size);
+
+        Some((buffer.freeze(), None))
+    }
+
+    fn set_receive_timeout(&self, timeout: Duration) -> Result<(), String> {
+        *self.receive_timeout.lock() = Some(timeout);
+        Ok(())
+    }
+}
+
+/// An interface request for a device, with nothing but its name set.
+fn interface_request(name: &str) -> Result<libc::ifreq, String> {
+    let mut request: libc::ifreq = unsafe { std::mem::zeroed() };
+    if name.len() >= request.ifr_name.len() || name.contains('\0') {
+        return Err(format!("Invalid device name: {}", name));
+    }
+    for (target, byte) in request.ifr_name.iter_mut().zip(name.bytes()) {
+        *target = byte as libc::c_char;
+    }
+    Ok(request)
+}
+
+/// A socket interface ioctls are run on, closed when dropped.
+fn control_socket(domain: c_int) -> Result<OwnedFd, String> {
+    let socket = unsafe { libc::socket(domain, libc::SOCK_DGRAM, 0) };
+    if socket == -1 {
+        return Err(format!("Create socket failed, error: {}", io::Error::last_os_error()));
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(socket) })
+}
+
+/// An IPv4 address in the `sockaddr` form interface requests take.
+fn sockaddr(address: u32) -> libc::sockaddr {
+    let address = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: libc::in_addr { s_addr: address.to_be() },
+        sin_zero: [0; 8],
+    };
+    unsafe { std::mem::transmute(address) }
+}
@@ -0,0 +1,74 @@
+use colored::Colorize;
+use log::trace;
+use tracing::info;
+
+use crate::tcp::connection_table::ConnectionTable;
+use crate::tcp::packet::data::{Controller, ReceiveData, TcpState};
+use crate::tcp::packet::options::TcpOption;
+use crate::tcp::settings::Settings;
+use crate::tcp::util::ChangingOrderSizes;
+
+impl ConnectionTable {
+    /// Accepts inbound connections on a port.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The port to listen on
+    /// * `settings` - The settings of accepted connections
+    ///
+    /// # Remarks
+    ///
+    /// A SYN to the port from an unknown remote creates a new control block in LISTEN, which answers with a SYN-ACK
+    /// and moves to SYN-RECEIVED. Every accepted connection gets its own entry in the table.
+    /// With a source address in the settings only SYNs to that address are accepted, otherwise the connection
+    /// answers from whatever address the SYN was sent to.
+    pub fn listen(&self, port: u16, settings: Settings) {
+        info!("{}", format!("Listening on port {}", port).truecolor(200, 35, 55));
+        self.listeners.insert(port, settings);
+    }
+
+    /// Answers a SYN to a listening port with a SYN-ACK.
+    ///
+    /// # Arguments
+    ///
+    /// * `receive_data` - The segment that belongs to no connection
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Controller>` - The new connection, or None if the segment is not a SYN to a listening port
+    pub(crate) fn accept(&self, receive_data: &mut ReceiveData) -> Option<Controller> {
+        let head = receive_data.tcphdr;
+        let port = head.dest.to_host();
+        let settings = *self.listeners.get(&port)?;
+        if head.syn() == 0 || head.ack() == 1 || head.rst() == 1 {
+            trace!("{}", "Received packet does not belong to any connection, thrown.".truecolor(25, 160, 60));
+            return None;
+        }
+
+        let local_address = receive_data.ip_head.destination();
+        if settings.source_address.is_some_and(|address| address != local_address) {
+            trace!("{}", format!("SYN to {} is not for the listening address, thrown.", local_address).truecolor(25, 160, 60));
+            return None;
+        }
+        let settings = Settings { source_address: Some(local_address), ..settings };
+
+        let remote_address = receive_data.ip_head.source().to_string();
+        let controller = match Controller::new(self.link.clone(), port, &remote_address, head.source.to_host(), settings) {
+            Ok(controller) => controller,
+            Err(e) => {
+                trace!("{}", format!("Can not accept connection from {}: {}", remote_address, e).truecolor(25, 160, 60));
+                return None;
+            }
+        };
+
+        controller.transition(TcpState::Listen);
+        controller.update_tcb(receive_data);
+
+        let window_scale = receive_data.options.iter().any(|option| matches!(option, TcpOption::WindowScale(_)));
+        let mut packet = controller.make_packet_with_none().to_second_handshake(window_scale);
+        let sent_size = controller.send_packet_with_state(&mut packet, TcpState::SynReceived);
+        info!("Send second hand-shake to {}: {}, with size: {}", controller.address_to_remote, packet, sent_size);
+
+        Some(controller)
+    }
+}
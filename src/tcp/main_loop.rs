@@ -1,120 +1,302 @@
-use std::mem::size_of;
-use std::os::raw::c_void;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
 
-use bytes::BytesMut;
+use bytes::Bytes;
 use colored::Colorize;
 use log::trace;
-use tokio::sync::watch;
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::raw_bindings::raw_bindings::{iphdr, recvfrom, sockaddr, sockaddr_in, tcphdr};
-use crate::REMOTE_PORT;
-use crate::tcp::packet::data::{Controller, ReceiveData, SpacilProcessor};
-use crate::tcp::util::ChangingOrderSizes;
+use crate::tcp::connection_table::{ChecksumPolicy, ConnectionKey, ConnectionTable, UnknownSegmentPolicy};
+use crate::tcp::link::device::Link;
+use crate::tcp::packet::data::{Controller, ReceiveData, RemoteSockaddr, TcpState};
+use crate::tcp::packet::ip_header::IpHeader;
+use crate::tcp::packet::parser::{Ipv4Packet, Ipv6Packet, ParseError, TcpSegment};
+use crate::tcp::packet::tcp_packet::TCPPacket;
+use crate::tcp::worker::bus::PacketBus;
+use crate::tcp::worker::state_machine::SegmentCheck;
 
 /// This function is used to receive packets from a remote source.
-/// It creates a listener for different types of packets and spawns a new task to handle the packet reception.
-/// The received packets are then processed and the relevant data is extracted.
-/// The function is asynchronous and returns when the task handling the packet reception is complete.
+/// Every received segment is handed to the connection of its (source address, source port, destination address,
+/// destination port) in the table, segments of no connection may open a new one on a listening port.
+/// The function is asynchronous and returns once the link is closed.
+/// Reading the link blocks, so the loop runs on tokio's blocking pool and never holds up a worker thread.
 ///
 /// # Arguments
 ///
-/// * `controller` - A Controller object that manages the packet reception.
+/// * `table` - The connections driven over the link.
 ///
 /// # Examples
 ///
+/// ```ignore
+/// let table = Arc::new(ConnectionTable::new(Arc::new(RawSocketLink::open(IpVersion::V4)?)));
+/// table.register(controller);
+/// receive_packet(table).await;
 /// ```
-/// let controller = Controller::new();
-/// receive_packet(controller).await;
-/// ```
-pub async fn receive_packet(controller: Controller) {
-    let mut sockaddr_in = sockaddr_in::default();
-
-    let (sender, receiver) = watch::channel(None);
-    let controller = Arc::new(controller);
-
-    spawn_listener!(controller, receiver, [
-        third_handshake_listener,
-        packet_printer,
-        data_listener,
-        wave_handshake_listener
-    ]);
-
-    tokio::spawn(async move {
-        let mut addr_len = size_of::<sockaddr>() as u32;
-        loop {
-            let buffer = {
-                let mut buffer = BytesMut::with_capacity(4096);
-                buffer.resize(4096, 0);
-                buffer
-            };
-
-            let receive_size = unsafe {
-                recvfrom(
-                    controller.socket,
-                    buffer.as_ptr() as *mut u8 as *mut c_void,
-                    buffer.len(),
-                    0,
-                    &mut sockaddr_in as *mut sockaddr_in as *mut sockaddr,
-                    &mut addr_len as *mut u32,
-                )
-            };
-
-            let (ip_head, tcp_head) = unsafe {
-                let ip_head = *(buffer.as_ptr() as *const iphdr);
-                let tcp_head = *(buffer.as_ptr().offset(size_of::<iphdr>() as isize) as *const tcphdr);
-                if ip_head.protocol != 6 {
-                    trace!("{}", "Received packet is not a TCP packet, thrown.".truecolor(25, 160, 60));
-                    continue;
-                }
+pub async fn receive_packet(table: Arc<ConnectionTable>) {
+    tokio::task::spawn_blocking(move || {
+        while !table.link.is_closed() {
+            if let Some(receive_data) = read_segment(table.link.as_ref()) {
+                table.dispatch(receive_data);
+            }
+        }
+    }).await.unwrap();
+}
 
-                let source_port = tcp_head.__bindgen_anon_1.__bindgen_anon_2.source.to_host();
-                let destination_port = tcp_head.__bindgen_anon_1.__bindgen_anon_2.dest.to_host();
-                if source_port == controller.local_port {
-                    trace!("{}", format!("Received packet from me({}), thrown.", source_port).truecolor(25, 160, 60));
-                    continue;
-                }
+impl ConnectionTable {
+    /// Hands an inbound segment to its connection, or to a listener if it belongs to none.
+    ///
+    /// # Arguments
+    ///
+    /// * `receive_data` - The segment, as parsed by `parse_segment`
+    ///
+    /// # Remarks
+    ///
+    /// Segments with a wrong checksum are counted, and dropped unless the table's policy is `ChecksumPolicy::Count`.
+    /// Accepting a connection spawns its workers, so this has to run within a tokio runtime, and handing a segment to
+    /// the processors of its connection may wait for them, so not from within an asynchronous task.
+    pub fn dispatch(self: &Arc<Self>, mut receive_data: ReceiveData) {
+        let key = ConnectionKey::of(&receive_data);
+        if !receive_data.checksum_valid {
+            let errors = self.checksum_errors.fetch_add(1, Ordering::Relaxed) + 1;
+            warn!(
+                "{}",
+                format!(
+                    "Received packet from {}:{} has a wrong checksum, {} so far{}",
+                    key.source_address,
+                    key.source_port,
+                    errors,
+                    if self.checksum_policy == ChecksumPolicy::Drop { ", thrown." } else { "." }
+                ).truecolor(230, 120, 30)
+            );
+            if self.checksum_policy == ChecksumPolicy::Drop {
+                return;
+            }
+        }
 
-                if !(source_port == controller.local_port && destination_port == REMOTE_PORT) &&
-                    !(source_port == REMOTE_PORT && destination_port == controller.local_port) {
+        match self.get(&key) {
+            Some((controller, bus)) => {
+                if let Some(capture) = &self.capture {
+                    capture.record(&receive_data.ip_head, &receive_data.raw);
+                }
+                controller.handle_segment(&bus, receive_data)
+            }
+            None => match self.accept(&mut receive_data) {
+                Some(controller) => {
+                    if let Some(capture) = &self.capture {
+                        capture.record(&receive_data.ip_head, &receive_data.raw);
+                    }
+                    self.register(controller);
+                }
+                None => {
                     trace!(
                         "{}",
                         format!(
-                            "Received packet does not match the required ports({} to {}), thrown.",
-                            source_port,
-                            destination_port
+                            "Received packet does not match any connection({}:{} to {}:{}), thrown.",
+                            key.source_address,
+                            key.source_port,
+                            key.destination_address,
+                            key.destination_port
                         ).truecolor(25, 160, 60)
                     );
-                    continue;
+                    if self.unknown_segment_policy == UnknownSegmentPolicy::Reset && receive_data.tcphdr.rst() == 0 {
+                        self.send_reset(&receive_data);
+                    }
                 }
+            },
+        }
+    }
 
-                (ip_head, tcp_head)
-            };
+    /// Answers a segment that belongs to no connection with a RST.
+    ///
+    /// # Arguments
+    ///
+    /// * `receive_data` - The segment, it must not be a RST itself
+    ///
+    /// # Returns
+    ///
+    /// * `isize` - The size of the sent packet, negative if it could not be sent
+    pub fn send_reset(&self, receive_data: &ReceiveData) -> isize {
+        let key = ConnectionKey::of(receive_data);
+        let remote = SocketAddr::new(key.source_address, key.source_port);
+        let packet = TCPPacket::default::<_, Bytes>(key.destination_address, remote.to_string(), None, key.destination_port);
+        let remote_sockaddr = RemoteSockaddr::new(&key.source_address.to_string(), key.source_port);
+        let (Ok(packet), Ok(remote_sockaddr)) = (packet, remote_sockaddr) else {
+            return -1;
+        };
 
-            unsafe {
-                *controller.last_ack_seq_number.write() = tcp_head.__bindgen_anon_1.__bindgen_anon_2.ack_seq;
-                *controller.last_seq_number.write() = tcp_head.__bindgen_anon_1.__bindgen_anon_2.seq;
+        let mut packet = packet.to_reset_packet(receive_data);
+        packet.as_ptr();
+        let bytes = &packet.data_vec[..packet.len()];
+        if let Some(capture) = &self.capture {
+            capture.record(&packet.ip_head, bytes);
+        }
+        let sent_size = self.link.send(&packet.ip_head, bytes, &remote_sockaddr);
+
+        info!("reset send to {}: {}, with size: {}", remote, packet, sent_size);
+        sent_size
+    }
+}
+
+/// Reads one TCP segment from a link.
+///
+/// # Arguments
+///
+/// * `link` - The link to read from
+///
+/// # Returns
+///
+/// * `Option<ReceiveData>` - The parsed segment, or None if the packet is not TCP or can not be parsed
+pub fn read_segment(link: &dyn Link) -> Option<ReceiveData> {
+    let (packet, ip_head) = link.receive()?;
+
+    match parse_segment(packet, ip_head) {
+        Ok(receive_data) => Some(receive_data),
+        Err(e) => {
+            trace!("{}", format!("Received packet can not be parsed({}), thrown.", e).truecolor(25, 160, 60));
+            None
+        }
+    }
+}
+
+/// Parses a packet read from a link.
+///
+/// # Arguments
+///
+/// * `packet` - The packet, starting with its IPv4 or IPv6 header, or only the TCP segment of an IPv6 raw socket
+/// * `ip_head` - The header of a segment read without it, taken from the socket address and the control messages;
+///   None if the packet starts with its IP header
+///
+/// # Returns
+///
+/// * `Result<ReceiveData, ParseError>` - The segment, or why the packet is not a valid TCP segment
+///
+/// # Remarks
+///
+/// A segment with a wrong checksum is still returned, marked as such, so the table can count it. The `raw` bytes of
+/// an IPv6 segment never include the IPv6 header, as if it was read from a raw socket.
+pub fn parse_segment(packet: Bytes, ip_head: Option<IpHeader>) -> Result<ReceiveData, ParseError> {
+    let (ip_head, segment, ip_checksum_valid, raw) = match ip_head {
+        None => match packet.first().map(|byte| byte >> 4) {
+            Some(6) => {
+                let ip_packet = Ipv6Packet::parse(&packet)?;
+                let segment = ip_packet.tcp()?;
+                (IpHeader::V6(ip_packet.header()), segment, true, packet.slice_ref(segment.as_bytes()))
+            }
+            _ => {
+                let ip_packet = Ipv4Packet::parse(&packet)?;
+                (IpHeader::V4(ip_packet.header()), ip_packet.tcp()?, ip_packet.is_checksum_valid(), packet.clone())
             }
+        },
+        Some(ip_head) => (ip_head, TcpSegment::parse(&packet)?, true, packet.clone()),
+    };
 
-            // Send the received packet to the sender channel
-            sender.send(Some(ReceiveData {
-                iphdr: ip_head,
-                tcphdr: unsafe {
-                    tcp_head.__bindgen_anon_1.__bindgen_anon_2
-                },
-                packet_size: receive_size as usize,
-                data: unsafe {
-                    let data_size = receive_size - 20 - (tcp_head.__bindgen_anon_1.__bindgen_anon_2.doff() * 4)as isize;
-                    if data_size > 0 {
-                        Some(buffer[(20 + (tcp_head.__bindgen_anon_1.__bindgen_anon_2.doff() * 4)) as usize .. receive_size as usize].to_vec())
-                    }else {
-                        None
-                    }
-                },
-            })).unwrap();
+    // The kernel leaves the TCP checksum of segments sent over loopback for the hardware to fill in, and never
+    // checks them either
+    let checksum_valid = ip_checksum_valid
+        && (ip_head.source().is_loopback() || segment.is_checksum_valid(&ip_head.pseudo_header(segment.len())));
+
+    let options = segment.options()?;
+    let data = if segment.payload().is_empty() {
+        None
+    } else {
+        Some(packet.slice_ref(segment.payload()))
+    };
+    let tcphdr = segment.header();
+
+    Ok(ReceiveData {
+        ip_head,
+        tcphdr,
+        packet_size: packet.len(),
+        options,
+        data,
+        raw,
+        checksum_valid,
+        ..Default::default()
+    })
+}
+
+/// Controller struct implementation
+impl Controller {
+    /// Spawns the processors, the retransmission and persist timers and, if it is set, the keepalive timer of the
+    /// connection.
+    ///
+    /// # Returns
+    ///
+    /// * `PacketBus` - The bus accepted segments are published to, the processors are subscribed before they start
+    pub fn spawn_workers(self: &Arc<Self>) -> PacketBus {
+        let bus = PacketBus::default();
+
+        let segments = bus.subscribe();
+        let events = self.events.subscribe();
+        let processor_controller = self.clone();
+        tokio::spawn(async move {
+            processor_controller.run_processors(segments, events).await;
+        });
+
+        let timer_controller = self.clone();
+        tokio::spawn(async move {
+            timer_controller.retransmission_timer().await;
+        });
+
+        let persist_controller = self.clone();
+        tokio::spawn(async move {
+            persist_controller.persist_timer().await;
+        });
+
+        if let Some(keepalive) = self.settings.keepalive {
+            let keepalive_controller = self.clone();
+            tokio::spawn(async move {
+                keepalive_controller.keepalive_timer(keepalive).await;
+            });
         }
-    }).await.unwrap();
+
+        bus
+    }
+
+    /// Runs an inbound segment of this connection through the state machine and hands it to the processors.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - The bus returned by `spawn_workers`
+    /// * `receive_data` - The segment
+    ///
+    /// # Remarks
+    ///
+    /// Publishing waits while the processors are too far behind, so this has to run outside of asynchronous tasks.
+    pub fn handle_segment(&self, bus: &PacketBus, mut receive_data: ReceiveData) {
+        // Whatever the segment is, the remote is still there
+        *self.last_received.lock() = Instant::now();
+
+        // Check the segment against the connection state before any processor sees it
+        match self.check_segment(&receive_data) {
+            SegmentCheck::Accept => {}
+            SegmentCheck::Acknowledge => {
+                let mut packet = self.make_packet_with_none().to_ack_packet();
+                let sent_size = self.send_packet(&mut packet);
+                info!("duplicate segment ack send: {}, with size: {}", packet, sent_size);
+                return;
+            }
+            SegmentCheck::Drop => return,
+            SegmentCheck::Reset => {
+                self.send_reset(&receive_data);
+                return;
+            }
+            SegmentCheck::Abort => {
+                self.reset_by_remote();
+                return;
+            }
+        }
+
+        self.update_tcb(&mut receive_data);
+
+        // An ACK may have opened the send window
+        self.flush_send_buffer();
+
+        // The processors see every segment, in the order the segments were accepted
+        bus.publish(receive_data);
+    }
 }
 
 /// This function is used to print the packet received from the remote.
@@ -126,13 +308,13 @@ pub async fn receive_packet(controller: Controller) {
 ///
 /// # Examples
 ///
+/// ```ignore
+/// let controller = table.register(controller);
+/// send_packet(&controller).await;
 /// ```
-/// let controller = Controller::new();
-/// send_packet(controller).await;
-/// ```
-pub async fn send_packet(controller: Controller) {
+pub async fn send_packet(controller: &Controller) {
     let mut packet = controller.make_packet_with_none().to_first_handshake();
-    let sent_size = controller.send_packet_spacial(&mut packet, SpacilProcessor::InitHandshake);
+    let sent_size = controller.send_packet_with_state(&mut packet, TcpState::SynSent);
 
     info!("Send first hand-shake: {}, with size: {}", packet, sent_size);
 }
@@ -1,5 +1,15 @@
 #[macro_use]
 pub mod util;
 pub mod main_loop;
+pub mod listener;
+pub mod connection_table;
+pub mod socket;
+pub mod link;
+pub mod capture;
+pub mod replay;
+pub mod processor;
+pub mod settings;
+pub mod stream;
 mod worker;
-pub(super) mod packet;
\ No newline at end of file
+pub mod packet;
+pub mod congestion;
\ No newline at end of file
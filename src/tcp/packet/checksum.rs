@@ -0,0 +1,168 @@
+/// The Internet checksum of RFC 1071, summed over data that may come in several pieces.
+///
+/// Words are read in network byte order, a piece of odd length is continued by the first byte of the next one and
+/// the last odd byte is padded with a zero. The checksum is returned in host byte order.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Checksum {
+    sum: u64,
+    /// The first byte of a word whose second byte is in the next piece
+    pending: Option<u8>,
+}
+
+impl Checksum {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a piece of the data to the sum.
+    pub fn add(&mut self, mut bytes: &[u8]) -> &mut Self {
+        if let Some(high) = self.pending.take() {
+            match bytes.split_first() {
+                Some((&low, rest)) => {
+                    self.sum += u16::from_be_bytes([high, low]) as u64;
+                    bytes = rest;
+                }
+                None => {
+                    self.pending = Some(high);
+                    return self;
+                }
+            }
+        }
+
+        let mut words = bytes.chunks_exact(2);
+        for word in &mut words {
+            self.sum += u16::from_be_bytes([word[0], word[1]]) as u64;
+        }
+        self.pending = words.remainder().first().copied();
+        self
+    }
+
+    /// The one's complement of the one's complement sum.
+    pub fn finish(&self) -> u16 {
+        let mut sum = self.sum + self.pending.map_or(0, |high| u16::from_be_bytes([high, 0]) as u64);
+        while (sum >> 16) != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+
+    /// Whether the data, with its checksum field filled in, sums up to all ones.
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.finish() == 0
+    }
+}
+
+/// The checksum of a single piece of data, in host byte order.
+pub fn checksum(bytes: &[u8]) -> u16 {
+    Checksum::new().add(bytes).finish()
+}
+
+/// Updates a checksum after a 16-bit field of the data changed, RFC 1624 equation 3.
+///
+/// # Arguments
+///
+/// * `checksum` - The checksum before the change, in host byte order
+/// * `old` - The field before the change, in host byte order
+/// * `new` - The field after the change, in host byte order
+///
+/// # Returns
+///
+/// * `u16` - The checksum after the change, in host byte order
+pub fn update(checksum: u16, old: u16, new: u16) -> u16 {
+    // HC' = ~(~HC + ~m + m')
+    let mut sum = (!checksum) as u32 + (!old) as u32 + new as u32;
+    while (sum >> 16) != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Updates a checksum after a 32-bit field of the data changed, one 16-bit half at a time.
+pub fn update_u32(checksum: u16, old: u32, new: u32) -> u16 {
+    let checksum = update(checksum, (old >> 16) as u16, (new >> 16) as u16);
+    update(checksum, old as u16, new as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Twenty bytes of a header-like buffer with a checksum field at offset 16.
+    fn header() -> Vec<u8> {
+        (0..20u8).map(|i| i.wrapping_mul(37).wrapping_add(0xc5)).collect()
+    }
+
+    #[test]
+    fn matches_the_rfc_1071_example() {
+        // RFC 1071 section 3: the sum of these bytes is 0xddf2
+        assert_eq!(checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]), !0xddf2);
+    }
+
+    #[test]
+    fn pads_an_odd_length_with_a_zero() {
+        assert_eq!(checksum(&[0x01, 0x02, 0x03]), !0x0402);
+        assert_eq!(checksum(&[0x01, 0x02, 0x03]), checksum(&[0x01, 0x02, 0x03, 0x00]));
+        assert_eq!(checksum(&[]), 0xffff);
+    }
+
+    #[test]
+    fn sums_data_split_across_pieces() {
+        let data = header();
+        let whole = checksum(&data);
+
+        for first in 0..data.len() {
+            for second in first..data.len() {
+                let sum = Checksum::new()
+                    .add(&data[..first])
+                    .add(&[])
+                    .add(&data[first..second])
+                    .add(&data[second..])
+                    .finish();
+                assert_eq!(sum, whole, "split at {} and {}", first, second);
+            }
+        }
+    }
+
+    #[test]
+    fn data_with_its_checksum_is_valid() {
+        let mut data = header();
+        data[16..18].fill(0);
+        let sum = checksum(&data);
+        data[16..18].copy_from_slice(&sum.to_be_bytes());
+
+        assert!(Checksum::new().add(&data[..7]).add(&data[7..]).is_valid());
+        data[3] ^= 0x10;
+        assert!(!Checksum::new().add(&data).is_valid());
+    }
+
+    #[test]
+    fn incremental_updates_match_a_full_recomputation() {
+        let values = [0x0000u16, 0x0001, 0x7fff, 0x8000, 0xfffe, 0xffff, 0x1234, 0xedcb];
+        for &old in &values {
+            for &new in &values {
+                let mut data = header();
+                data[2..4].copy_from_slice(&old.to_be_bytes());
+                let before = checksum(&data);
+                data[2..4].copy_from_slice(&new.to_be_bytes());
+
+                assert_eq!(update(before, old, new), checksum(&data), "{:#06x} -> {:#06x}", old, new);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_u32_updates_match_a_full_recomputation() {
+        let values = [0x0000_0000u32, 0xffff_ffff, 0x0000_ffff, 0xffff_0000, 0x8000_0001, 0xdead_beef];
+        for &old in &values {
+            for &new in &values {
+                let mut data = header();
+                data[4..8].copy_from_slice(&old.to_be_bytes());
+                let before = checksum(&data);
+                data[4..8].copy_from_slice(&new.to_be_bytes());
+
+                assert_eq!(update_u32(before, old, new), checksum(&data), "{:#010x} -> {:#010x}", old, new);
+            }
+        }
+    }
+}
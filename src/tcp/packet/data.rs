@@ -1,9 +1,30 @@
-use std::ffi::c_int;
+use std::ffi::{c_int, c_void, CString};
+use std::mem::size_of;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
+use std::time::Instant;
 
-use parking_lot::RwLock;
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+use rand::random;
+use tokio::sync::{broadcast, Notify, watch};
 
-use crate::raw_bindings::raw_bindings::{iphdr, sockaddr_in, tcphdr__bindgen_ty_1__bindgen_ty_2};
+use crate::raw_bindings::raw_bindings::{AF_INET, AF_INET6, in6_addr, in_addr, inet_pton, sockaddr, sockaddr_in, sockaddr_in6, tcphdr__bindgen_ty_1__bindgen_ty_2};
+use crate::tcp::capture::PcapWriter;
+use crate::tcp::congestion::congestion_control::Congestion;
+use crate::tcp::link::device::Link;
+use crate::tcp::packet::ip_header::{IpHeader, IpVersion};
+use crate::tcp::packet::options::{DEFAULT_REMOTE_MSS, DEFAULT_REMOTE_MSS_V6, TcpOption};
+use crate::tcp::packet::persist::PersistTimer;
+use crate::tcp::packet::reassembly::{Delivery, ReassemblyBuffer};
+use crate::tcp::packet::receive_buffer::ReceiveBuffer;
+use crate::tcp::packet::retransmission::RetransmissionQueue;
+use crate::tcp::packet::send_buffer::SendBuffer;
+use crate::tcp::packet::tcb::TransmissionControlBlock;
+use crate::tcp::processor::{ConnectionEvent, ProcessorRegistry};
+use crate::tcp::settings::Settings;
+use crate::tcp::util::ChangingOrderSizes;
+use crate::tcp::worker::util::EVENT_CAPACITY;
 
 #[derive(Debug)]
 pub struct PseudoHeader {
@@ -14,34 +35,230 @@ pub struct PseudoHeader {
     pub tcp_length: u16,
 }
 
-#[derive(PartialEq)]
-pub enum SpacilProcessor {
-    InitHandshake,
-    WaveHandshake,
-    None
+/// The IPv6 pseudo-header of RFC 8200, section 8.1.
+#[derive(Debug)]
+pub struct PseudoHeaderV6 {
+    pub source_address: [u8; 16],
+    pub dest_address: [u8; 16],
+    pub tcp_length: u32,
+    pub zero: [u8; 3],
+    pub next_header: u8,
 }
 
-impl Default for SpacilProcessor {
-    fn default() -> Self {
-        SpacilProcessor::None
-    }
+/// Connection states from RFC 793, section 3.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TcpState {
+    #[default]
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    LastAck,
+    Closing,
+    TimeWait,
+}
+
+/// Why a connection reached CLOSED.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloseReason {
+    /// Both sides finished the wave handshake
+    Closed,
+    /// The connection was torn down, with the reason
+    Aborted(String),
+    /// The remote answered our SYN with a RST
+    Refused,
+    /// The remote reset the connection
+    Reset,
+    /// The remote answered none of our keepalive probes
+    KeepaliveTimeout,
 }
 
 #[derive(Default)]
 pub struct ReceiveData {
-    pub(crate) iphdr: iphdr,
+    pub(crate) ip_head: IpHeader,
     pub(crate) tcphdr: tcphdr__bindgen_ty_1__bindgen_ty_2,
     pub(crate) packet_size: usize,
-    pub(crate) data: Option<Vec<u8>>,
+    pub(crate) options: Vec<TcpOption>,
+    /// The payload, a slice of `raw`
+    pub(crate) data: Option<Bytes>,
+    /// The packet as read from the link, with the IP header for IPv4
+    pub(crate) raw: Bytes,
+    /// Whether the IP and TCP checksums match the packet
+    pub(crate) checksum_valid: bool,
+    /// What the segment made contiguous in the receive stream, filled in by the receive loop
+    pub(crate) delivered: Delivery,
+}
+
+impl ReceiveData {
+    /// The sequence space the segment occupies: its data plus one for each of SYN and FIN.
+    pub fn sequence_length(&self) -> u32 {
+        self.data.as_ref().map_or(0, |data| data.len() as u32) + (self.tcphdr.syn() + self.tcphdr.fin()) as u32
+    }
+
+    /// What the segment made contiguous in the receive stream, for processors outside the crate.
+    #[inline]
+    pub fn delivered(&self) -> &Delivery {
+        &self.delivered
+    }
+}
+
+/// The remote's socket address, in the form `sendto` takes it.
+///
+/// IPv6 raw sockets take the protocol rather than the port in `sin6_port`, so the port is kept beside it.
+#[derive(Clone, Copy)]
+pub enum RemoteSockaddr {
+    V4(sockaddr_in),
+    V6(sockaddr_in6, u16),
+}
+
+impl RemoteSockaddr {
+    /// Parses the address of a remote.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - An IPv4 address in dotted notation or an IPv6 address
+    /// * `port` - The remote port
+    ///
+    /// # Returns
+    ///
+    /// * `Result<RemoteSockaddr, String>` - The socket address, or an error if the address can not be parsed
+    pub fn new(address: &str, port: u16) -> Result<RemoteSockaddr, String> {
+        let ip = CString::new(address).map_err(|e| e.to_string())?;
+
+        unsafe {
+            if address.contains(':') {
+                let mut addr = sockaddr_in6 {
+                    sin6_family: AF_INET6 as u16,
+                    ..Default::default()
+                };
+
+                let res = inet_pton(AF_INET6 as c_int, ip.as_ptr(), &mut addr.sin6_addr as *mut in6_addr as *mut c_void);
+                if res != 1 {
+                    return Err(format!("error on inet_pton: {}", res));
+                }
+                Ok(RemoteSockaddr::V6(addr, port))
+            } else {
+                let mut addr = sockaddr_in {
+                    sin_family: AF_INET as u16,
+                    sin_port: port.to_network(),
+                    ..Default::default()
+                };
+
+                let res = inet_pton(AF_INET as c_int, ip.as_ptr(), &mut addr.sin_addr as *mut in_addr as *mut c_void);
+                if res != 1 {
+                    return Err(format!("error on inet_pton: {}", res));
+                }
+                Ok(RemoteSockaddr::V4(addr))
+            }
+        }
+    }
+
+    pub fn address(&self) -> IpAddr {
+        match self {
+            RemoteSockaddr::V4(addr) => IpAddr::V4(Ipv4Addr::from(addr.sin_addr.s_addr.to_host())),
+            RemoteSockaddr::V6(addr, _) => IpAddr::V6(Ipv6Addr::from(unsafe { addr.sin6_addr.__in6_u.__u6_addr8 })),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        match self {
+            RemoteSockaddr::V4(addr) => addr.sin_port.to_host(),
+            RemoteSockaddr::V6(_, port) => *port,
+        }
+    }
+
+    /// The pointer and length to hand to `sendto`.
+    pub fn as_sockaddr(&self) -> (*const sockaddr, u32) {
+        match self {
+            RemoteSockaddr::V4(addr) => (addr as *const sockaddr_in as *const sockaddr, size_of::<sockaddr_in>() as u32),
+            RemoteSockaddr::V6(addr, _) => (addr as *const sockaddr_in6 as *const sockaddr, size_of::<sockaddr_in6>() as u32),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Controller {
-    pub socket: c_int,
+    /// The link of the table the connection is driven in
+    pub link: Arc<dyn Link>,
+    pub local_address: IpAddr,
     pub local_port: u16,
-    pub sockaddr_to_remote: sockaddr_in,
+    pub sockaddr_to_remote: RemoteSockaddr,
     pub address_to_remote: String,
-    pub last_ack_seq_number: Arc<RwLock<u32>>,
-    pub last_seq_number: Arc<RwLock<u32>>,
-    pub spacil: Arc<RwLock<SpacilProcessor>>,
+    pub settings: Settings,
+    pub tcb: Arc<RwLock<TransmissionControlBlock>>,
+    pub retransmission: Arc<Mutex<RetransmissionQueue>>,
+    /// Runs while the remote advertises a zero window and we have data for it
+    pub persist: Arc<Mutex<PersistTimer>>,
+    pub reassembly: Arc<Mutex<ReassemblyBuffer>>,
+    pub send_buffer: Arc<Mutex<SendBuffer>>,
+    pub receive_buffer: Arc<Mutex<ReceiveBuffer>>,
+    pub congestion: Arc<Mutex<Congestion>>,
+    pub state: Arc<RwLock<TcpState>>,
+    /// Notified on every state transition
+    pub state_changed: Arc<Notify>,
+    /// When the last segment from the remote arrived, or the connection was created
+    pub last_received: Arc<Mutex<Instant>>,
+    pub closed: Arc<watch::Sender<Option<CloseReason>>>,
+    /// Where the events of the connection are published to its processors
+    pub events: Arc<broadcast::Sender<ConnectionEvent>>,
+    /// What runs for every segment the connection accepts and every event it sees
+    pub processors: Arc<ProcessorRegistry>,
+    /// Where sent packets are recorded, set by the connection table
+    pub capture: Option<Arc<PcapWriter>>,
+}
+
+impl Controller {
+    /// Creates the control block of a new connection in CLOSED, with a random initial sequence number.
+    ///
+    /// # Arguments
+    ///
+    /// * `link` - The link packets are sent through
+    /// * `local_port` - Our port
+    /// * `remote_address` - The remote IPv4 address in dotted notation, or IPv6 address
+    /// * `remote_port` - The remote port
+    /// * `settings` - The source address, receive window and congestion control algorithm of the connection
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Controller, String>` - The controller, or an error if the addresses can not be used
+    pub fn new(link: Arc<dyn Link>, local_port: u16, remote_address: &str, remote_port: u16, settings: Settings) -> Result<Controller, String> {
+        let sockaddr_to_remote = RemoteSockaddr::new(remote_address, remote_port)?;
+        let version = IpVersion::of(&sockaddr_to_remote.address());
+        let local_address = settings.source_address_for(version)?;
+        let remote_mss = match version {
+            IpVersion::V4 => DEFAULT_REMOTE_MSS,
+            IpVersion::V6 => DEFAULT_REMOTE_MSS_V6,
+        };
+
+        let mut tcb = TransmissionControlBlock::new(random());
+        tcb.snd_mss = remote_mss;
+        tcb.rcv_wnd = settings.receive_window;
+
+        Ok(Controller {
+            link,
+            local_address,
+            local_port,
+            sockaddr_to_remote,
+            address_to_remote: SocketAddr::new(sockaddr_to_remote.address(), remote_port).to_string(),
+            settings,
+            tcb: Arc::new(RwLock::new(tcb)),
+            retransmission: Arc::new(Mutex::new(RetransmissionQueue::default())),
+            persist: Arc::new(Mutex::new(PersistTimer::default())),
+            reassembly: Arc::new(Mutex::new(ReassemblyBuffer::default())),
+            send_buffer: Arc::new(Mutex::new(SendBuffer::default())),
+            receive_buffer: Arc::new(Mutex::new(ReceiveBuffer::new(settings.receive_window))),
+            congestion: Arc::new(Mutex::new(Congestion::new(settings.congestion, remote_mss as u32))),
+            state: Arc::new(RwLock::new(TcpState::Closed)),
+            state_changed: Arc::new(Notify::new()),
+            last_received: Arc::new(Mutex::new(Instant::now())),
+            closed: Arc::new(watch::channel(None).0),
+            events: Arc::new(broadcast::channel(EVENT_CAPACITY).0),
+            processors: Arc::new(ProcessorRegistry::builtin()),
+            capture: None,
+        })
+    }
 }
\ No newline at end of file
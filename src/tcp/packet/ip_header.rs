@@ -0,0 +1,199 @@
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use colored::Colorize;
+
+use crate::raw_bindings::raw_bindings::{iphdr, IPPROTO_TCP, tcphdr};
+use crate::tcp::packet::data::{PseudoHeader, PseudoHeaderV6};
+use crate::tcp::packet::parser::IPV6_HEADER_LENGTH;
+use crate::tcp::util::ChangingOrderSizes;
+
+/// The IP version a raw socket and its connections use.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpVersion {
+    #[default]
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    pub fn of(address: &IpAddr) -> Self {
+        match address {
+            IpAddr::V4(_) => IpVersion::V4,
+            IpAddr::V6(_) => IpVersion::V6,
+        }
+    }
+}
+
+/// What we know of an IPv6 header.
+///
+/// IPv6 raw sockets never hand out or take the IPv6 header, the kernel builds it from the socket address we send to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv6Header {
+    pub source: Ipv6Addr,
+    pub destination: Ipv6Addr,
+    pub payload_length: u16,
+}
+
+impl Ipv6Header {
+    /// The fixed header as the kernel puts it in front of a segment, RFC 8200 section 3.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload_length` - The length of the segment behind the header
+    pub fn to_bytes(&self, payload_length: usize) -> [u8; IPV6_HEADER_LENGTH] {
+        let mut bytes = [0u8; IPV6_HEADER_LENGTH];
+        bytes[0] = 6 << 4;
+        bytes[4..6].copy_from_slice(&(payload_length as u16).to_be_bytes());
+        bytes[6] = IPPROTO_TCP as u8;
+        bytes[7] = 64;
+        bytes[8..24].copy_from_slice(&self.source.octets());
+        bytes[24..40].copy_from_slice(&self.destination.octets());
+        bytes
+    }
+}
+
+/// The IP header of a sent or received segment.
+#[derive(Clone, Copy)]
+pub enum IpHeader {
+    V4(iphdr),
+    V6(Ipv6Header),
+}
+
+impl Default for IpHeader {
+    fn default() -> Self {
+        IpHeader::V4(<iphdr as Default>::default())
+    }
+}
+
+impl IpHeader {
+    /// Creates the header of a segment we send.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Our address
+    /// * `destination` - The remote's address, of the same version
+    /// * `tcp_len` - The length of the TCP segment
+    ///
+    /// # Returns
+    ///
+    /// * `Result<IpHeader, String>` - The header, or an error if the addresses are of different versions
+    pub fn new(source: IpAddr, destination: IpAddr, tcp_len: usize) -> Result<IpHeader, String> {
+        match (source, destination) {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                let data_len = tcp_len - size_of::<tcphdr>();
+                Ok(IpHeader::V4(iphdr::default(data_len, &source.to_string(), &destination.to_string())))
+            }
+            (IpAddr::V6(source), IpAddr::V6(destination)) => Ok(IpHeader::V6(Ipv6Header {
+                source,
+                destination,
+                payload_length: tcp_len as u16,
+            })),
+            (source, destination) => Err(format!("Can not send from {} to {}", source, destination)),
+        }
+    }
+
+    #[inline]
+    pub fn version(&self) -> IpVersion {
+        match self {
+            IpHeader::V4(_) => IpVersion::V4,
+            IpHeader::V6(_) => IpVersion::V6,
+        }
+    }
+
+    pub fn source(&self) -> IpAddr {
+        match self {
+            IpHeader::V4(header) => IpAddr::V4(Ipv4Addr::from(header.saddr.to_host())),
+            IpHeader::V6(header) => IpAddr::V6(header.source),
+        }
+    }
+
+    pub fn destination(&self) -> IpAddr {
+        match self {
+            IpHeader::V4(header) => IpAddr::V4(Ipv4Addr::from(header.daddr.to_host())),
+            IpHeader::V6(header) => IpAddr::V6(header.destination),
+        }
+    }
+
+    /// Length of the header in the packets we send, the IPv6 header is added by the link.
+    #[inline]
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        match self {
+            IpHeader::V4(_) => size_of::<iphdr>(),
+            IpHeader::V6(_) => 0,
+        }
+    }
+
+    /// Updates the length fields after the TCP segment changed.
+    pub fn set_tcp_len(&mut self, tcp_len: usize) {
+        match self {
+            IpHeader::V4(header) => header.tot_len = ((size_of::<iphdr>() + tcp_len) as u16).to_network(),
+            IpHeader::V6(header) => header.payload_length = tcp_len as u16,
+        }
+    }
+
+    /// The pseudo-header the TCP checksum covers, RFC 793 section 3.1 and RFC 8200 section 8.1.
+    ///
+    /// # Arguments
+    ///
+    /// * `tcp_len` - The length of the TCP segment
+    pub fn pseudo_header(&self, tcp_len: usize) -> Vec<u8> {
+        match self {
+            IpHeader::V4(header) => {
+                let pseudo_header = PseudoHeader {
+                    source_address: header.saddr,
+                    dest_address: header.daddr,
+                    placeholder: 0,
+                    protocol: header.protocol,
+                    tcp_length: (tcp_len as u16).to_network(),
+                };
+
+                let mut vec = Vec::with_capacity(12);
+                vec.extend_from_slice(&pseudo_header.source_address.to_ne_bytes());
+                vec.extend_from_slice(&pseudo_header.dest_address.to_ne_bytes());
+                vec.push(pseudo_header.placeholder);
+                vec.push(pseudo_header.protocol);
+                vec.extend_from_slice(&pseudo_header.tcp_length.to_ne_bytes());
+                vec
+            }
+            IpHeader::V6(header) => {
+                let pseudo_header = PseudoHeaderV6 {
+                    source_address: header.source.octets(),
+                    dest_address: header.destination.octets(),
+                    tcp_length: (tcp_len as u32).to_network(),
+                    zero: [0; 3],
+                    next_header: IPPROTO_TCP as u8,
+                };
+
+                let mut vec = Vec::with_capacity(40);
+                vec.extend_from_slice(&pseudo_header.source_address);
+                vec.extend_from_slice(&pseudo_header.dest_address);
+                vec.extend_from_slice(&pseudo_header.tcp_length.to_ne_bytes());
+                vec.extend_from_slice(&pseudo_header.zero);
+                vec.push(pseudo_header.next_header);
+                vec
+            }
+        }
+    }
+}
+
+impl Display for IpHeader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpHeader::V4(header) => write!(f, "{}", header),
+            IpHeader::V6(header) => write!(
+                f,
+                "{}",
+                format!(
+                    "IPv6 head: {{payload_len: {}, next_header: {}, saddr: {}, daddr: {}}}",
+                    header.payload_length,
+                    IPPROTO_TCP,
+                    header.source,
+                    header.destination
+                ).truecolor(47, 122, 215)
+            ),
+        }
+    }
+}
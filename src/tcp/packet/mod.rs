@@ -1,3 +1,13 @@
 pub mod data;
 pub mod tcp_packet;
+pub mod ip_header;
+pub mod tcb;
+pub mod retransmission;
+pub mod persist;
+pub mod reassembly;
+pub mod options;
+pub mod checksum;
+pub mod parser;
+pub mod send_buffer;
+pub mod receive_buffer;
 mod packet_factory;
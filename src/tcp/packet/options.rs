@@ -0,0 +1,232 @@
+/// The MSS we announce in our SYN.
+pub const DEFAULT_MSS: u16 = 1460;
+/// The MSS we announce in our SYN over IPv6, whose header is 20 bytes longer.
+pub const DEFAULT_MSS_V6: u16 = 1440;
+/// The MSS assumed when the remote announces none, RFC 879.
+pub const DEFAULT_REMOTE_MSS: u16 = 536;
+/// The MSS assumed when the remote announces none over IPv6, the minimum MTU of RFC 8200 minus both headers.
+pub const DEFAULT_REMOTE_MSS_V6: u16 = 1220;
+/// The largest window shift allowed by RFC 7323.
+pub const MAX_WINDOW_SCALE: u8 = 14;
+/// The options area of a TCP header is at most 40 bytes long.
+pub const MAX_OPTIONS_LENGTH: usize = 40;
+
+const KIND_END_OF_LIST: u8 = 0;
+const KIND_NO_OPERATION: u8 = 1;
+const KIND_MAXIMUM_SEGMENT_SIZE: u8 = 2;
+const KIND_WINDOW_SCALE: u8 = 3;
+const KIND_SACK_PERMITTED: u8 = 4;
+const KIND_TIMESTAMPS: u8 = 8;
+
+/// A TCP option as carried after the fixed 20-byte header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TcpOption {
+    EndOfList,
+    NoOperation,
+    /// RFC 793, only valid in SYN segments
+    MaximumSegmentSize(u16),
+    /// RFC 7323, the shift count, only valid in SYN segments
+    WindowScale(u8),
+    /// RFC 2018, only valid in SYN segments
+    SackPermitted,
+    /// RFC 7323
+    Timestamps { value: u32, echo_reply: u32 },
+    /// Any option we do not understand, kept as it was received
+    Unknown { kind: u8, data: Vec<u8> },
+}
+
+impl TcpOption {
+    /// Appends the wire format of the option to `buffer`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if the option is too long for its length byte
+    pub fn encode(&self, buffer: &mut Vec<u8>) -> Result<(), String> {
+        match self {
+            TcpOption::EndOfList => buffer.push(KIND_END_OF_LIST),
+            TcpOption::NoOperation => buffer.push(KIND_NO_OPERATION),
+            TcpOption::MaximumSegmentSize(mss) => {
+                buffer.extend_from_slice(&[KIND_MAXIMUM_SEGMENT_SIZE, 4]);
+                buffer.extend_from_slice(&mss.to_be_bytes());
+            }
+            TcpOption::WindowScale(shift) => buffer.extend_from_slice(&[KIND_WINDOW_SCALE, 3, *shift]),
+            TcpOption::SackPermitted => buffer.extend_from_slice(&[KIND_SACK_PERMITTED, 2]),
+            TcpOption::Timestamps { value, echo_reply } => {
+                buffer.extend_from_slice(&[KIND_TIMESTAMPS, 10]);
+                buffer.extend_from_slice(&value.to_be_bytes());
+                buffer.extend_from_slice(&echo_reply.to_be_bytes());
+            }
+            TcpOption::Unknown { kind, data } => {
+                let length = u8::try_from(data.len() + 2)
+                    .map_err(|_| format!("TCP option {} carries {} bytes, its length byte allows at most 253", kind, data.len()))?;
+                buffer.extend_from_slice(&[*kind, length]);
+                buffer.extend_from_slice(data);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encodes a list of options and pads it with NOPs to a multiple of four bytes.
+///
+/// # Arguments
+///
+/// * `options` - The options to encode
+///
+/// # Returns
+///
+/// * `Result<Vec<u8>, String>` - The options area, or an error if an option or all of them do not fit into the header
+pub fn encode_options(options: &[TcpOption]) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::with_capacity(MAX_OPTIONS_LENGTH);
+    for option in options {
+        option.encode(&mut buffer)?;
+    }
+
+    while buffer.len() % 4 != 0 {
+        buffer.push(KIND_NO_OPERATION);
+    }
+
+    if buffer.len() > MAX_OPTIONS_LENGTH {
+        return Err(format!("TCP options take {} bytes, at most {} fit into the header", buffer.len(), MAX_OPTIONS_LENGTH));
+    }
+
+    Ok(buffer)
+}
+
+/// Decodes the options area of a TCP header.
+///
+/// Decoding stops at an End of Option List. NOPs are skipped.
+///
+/// # Arguments
+///
+/// * `buffer` - The bytes between the fixed header and the data
+///
+/// # Returns
+///
+/// * `Result<Vec<TcpOption>, String>` - The options, or an error if an option is truncated or has a bad length
+pub fn decode_options(buffer: &[u8]) -> Result<Vec<TcpOption>, String> {
+    let mut options = Vec::new();
+    let mut offset = 0;
+
+    while offset < buffer.len() {
+        let kind = buffer[offset];
+        match kind {
+            KIND_END_OF_LIST => break,
+            KIND_NO_OPERATION => {
+                offset += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let length = *buffer.get(offset + 1).ok_or(format!("TCP option {} has no length", kind))? as usize;
+        if length < 2 || offset + length > buffer.len() {
+            return Err(format!("TCP option {} has an invalid length {}", kind, length));
+        }
+
+        let data = &buffer[offset + 2..offset + length];
+        let option = match (kind, data.len()) {
+            (KIND_MAXIMUM_SEGMENT_SIZE, 2) => TcpOption::MaximumSegmentSize(u16::from_be_bytes([data[0], data[1]])),
+            (KIND_WINDOW_SCALE, 1) => TcpOption::WindowScale(data[0]),
+            (KIND_SACK_PERMITTED, 0) => TcpOption::SackPermitted,
+            (KIND_TIMESTAMPS, 8) => TcpOption::Timestamps {
+                value: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+                echo_reply: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            },
+            (KIND_MAXIMUM_SEGMENT_SIZE | KIND_WINDOW_SCALE | KIND_SACK_PERMITTED | KIND_TIMESTAMPS, _) => {
+                return Err(format!("TCP option {} has an invalid length {}", kind, length));
+            }
+            _ => TcpOption::Unknown { kind, data: data.to_vec() },
+        };
+
+        options.push(option);
+        offset += length;
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(options: &[TcpOption]) -> Vec<TcpOption> {
+        let encoded = encode_options(options).unwrap();
+        assert_eq!(encoded.len() % 4, 0);
+        decode_options(&encoded).unwrap()
+    }
+
+    #[test]
+    fn round_trips_each_option() {
+        for option in [
+            TcpOption::MaximumSegmentSize(1460),
+            TcpOption::WindowScale(MAX_WINDOW_SCALE),
+            TcpOption::SackPermitted,
+            TcpOption::Timestamps { value: 0xdead_beef, echo_reply: 1 },
+            TcpOption::Unknown { kind: 30, data: vec![1, 2, 3] },
+        ] {
+            assert_eq!(round_trip(std::slice::from_ref(&option)), vec![option]);
+        }
+    }
+
+    #[test]
+    fn round_trips_a_syn_option_list() {
+        let options = vec![
+            TcpOption::MaximumSegmentSize(DEFAULT_MSS),
+            TcpOption::SackPermitted,
+            TcpOption::Timestamps { value: 7, echo_reply: 0 },
+            TcpOption::WindowScale(7),
+        ];
+        assert_eq!(round_trip(&options), options);
+    }
+
+    #[test]
+    fn encodes_the_wire_format() {
+        let encoded = encode_options(&[TcpOption::MaximumSegmentSize(1460), TcpOption::WindowScale(7)]).unwrap();
+        assert_eq!(encoded, vec![2, 4, 0x05, 0xb4, 3, 3, 7, 1]);
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_list() {
+        assert_eq!(decode_options(&[1, 4, 2, 0, 2, 4, 0x05, 0xb4]).unwrap(), vec![TcpOption::SackPermitted]);
+    }
+
+    #[test]
+    fn rejects_options_too_long_for_the_header() {
+        let options = vec![TcpOption::Timestamps { value: 0, echo_reply: 0 }; 4];
+        assert_eq!(encode_options(&options).unwrap().len(), MAX_OPTIONS_LENGTH);
+        assert!(encode_options(&[options.as_slice(), &[TcpOption::NoOperation]].concat()).is_err());
+    }
+
+    #[test]
+    fn rejects_an_unknown_option_too_long_for_its_length_byte() {
+        let mut buffer = Vec::new();
+        assert!(TcpOption::Unknown { kind: 30, data: vec![0; 253] }.encode(&mut buffer).is_ok());
+        assert_eq!(buffer[1], 255);
+        assert!(TcpOption::Unknown { kind: 30, data: vec![0; 254] }.encode(&mut Vec::new()).is_err());
+        assert!(encode_options(&[TcpOption::Unknown { kind: 30, data: vec![0; 300] }]).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_lengths() {
+        for buffer in [
+            &[2, 3, 0x05][..],
+            &[2, 5, 0x05, 0xb4, 0],
+            &[3, 2],
+            &[4, 3, 0],
+            &[8, 9, 0, 0, 0, 0, 0, 0, 0],
+            // Shorter than its own kind and length
+            &[30, 1],
+            &[30, 0],
+        ] {
+            assert!(decode_options(buffer).is_err(), "{:?}", buffer);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_options() {
+        for buffer in [&[2][..], &[2, 4, 0x05], &[8, 10, 0, 0, 0, 1], &[1, 1, 3, 3], &[30, 4, 1]] {
+            assert!(decode_options(buffer).is_err(), "{:?}", buffer);
+        }
+    }
+}
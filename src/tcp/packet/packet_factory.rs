@@ -1,7 +1,9 @@
-use std::mem::size_of;
+use bytes::Bytes;
 
-use crate::raw_bindings::raw_bindings::{sendto, sockaddr, sockaddr_in};
-use crate::tcp::packet::data::{Controller, SpacilProcessor};
+use crate::tcp::packet::checksum;
+use crate::tcp::packet::data::{Controller, ReceiveData, TcpState};
+use crate::tcp::packet::ip_header::IpHeader;
+use crate::tcp::packet::options::{DEFAULT_MSS, DEFAULT_MSS_V6, TcpOption};
 use crate::tcp::packet::tcp_packet::TCPPacket;
 use crate::tcp::util::ChangingOrderSizes;
 
@@ -11,14 +13,14 @@ impl Controller {
     ///
     /// # Arguments
     ///
-    /// * `data` - A generic type that can be converted into a Vec<u8>
+    /// * `data` - The payload, sent exactly as given
     ///
     /// # Returns
     ///
     /// * `TCPPacket` - The created TCP packet
     #[inline]
-    pub fn make_packet_with_data<T: Into<Vec<u8>>>(&self, data: T) -> TCPPacket {
-        TCPPacket::default(&self.address_to_remote, Some(data), self.local_port).unwrap()
+    pub fn make_packet_with_data<T: Into<Bytes>>(&self, data: T) -> TCPPacket {
+        TCPPacket::default(self.local_address, &self.address_to_remote, Some(data), self.local_port).unwrap()
     }
 
     /// Creates a TCP packet without data
@@ -28,27 +30,31 @@ impl Controller {
     /// * `TCPPacket` - The created TCP packet
     #[inline]
     pub fn make_packet_with_none(&self) -> TCPPacket {
-        TCPPacket::default::<_, String>(&self.address_to_remote, None, self.local_port).unwrap()
+        TCPPacket::default::<_, Bytes>(self.local_address, &self.address_to_remote, None, self.local_port).unwrap()
     }
 
-    /// Sends a TCP packet with a SpacilProcessor
+    /// Sends a TCP packet and moves the connection to a new state
     ///
     /// # Arguments
     ///
     /// * `tcppacket` - A mutable reference to the TCP packet to be sent
-    /// * `spacial` - The SpacilProcessor to be used
+    /// * `state` - The state the connection enters once the packet is sent
     ///
     /// # Returns
     ///
     /// * `isize` - The size of the sent packet
     #[inline]
-    pub fn send_packet_spacial(&self, tcppacket: &mut TCPPacket, spacial: SpacilProcessor) -> isize {
-        *self.spacil.write() = spacial;
+    pub fn send_packet_with_state(&self, tcppacket: &mut TCPPacket, state: TcpState) -> isize {
+        self.transition(state);
         self.send_packet(tcppacket)
     }
 
     /// Sends a TCP packet
     ///
+    /// The packet is stamped with SND.NXT as its sequence number and, if it carries an ACK, with RCV.NXT as its
+    /// acknowledgement number. SND.NXT is then advanced by the sequence space the packet occupies, and packets that
+    /// occupy sequence space are kept in the retransmission queue until they are acknowledged.
+    ///
     /// # Arguments
     ///
     /// * `tcppacket` - A mutable reference to the TCP packet to be sent
@@ -57,25 +63,149 @@ impl Controller {
     ///
     /// * `isize` - The size of the sent packet
     pub fn send_packet(&self, tcppacket: &mut TCPPacket) -> isize {
-        let sent_size = unsafe {
-            sendto(
-                self.socket,
-                tcppacket.as_ptr(),
-                tcppacket.len(),
-                0,
-                &self.sockaddr_to_remote as *const sockaddr_in as *const sockaddr,
-                size_of::<sockaddr>() as u32
-            )
-        };
+        let mut tcb = self.tcb.write();
+        unsafe {
+            let tcp_head = &mut tcppacket.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
+            tcp_head.seq = tcb.snd_nxt.to_network();
+            if tcp_head.ack() == 1 {
+                tcp_head.ack_seq = tcb.rcv_nxt.to_network();
+            }
+            tcp_head.window = tcb.rcv_wnd.to_network();
+        }
+        tcppacket.checksum_valid = false;
+
+        let sequence_length = tcppacket.sequence_length();
+        tcb.snd_nxt = tcb.snd_nxt.wrapping_add(sequence_length);
+
+        // Anything that occupies sequence space has to be acknowledged, keep it until it is
+        if sequence_length > 0 {
+            self.retransmission.lock().push(tcb.snd_nxt, tcppacket.clone());
+        }
+        drop(tcb);
+
+        self.transmit(tcppacket)
+    }
+
+    /// Answers a segment with a RST, without touching the TCB
+    ///
+    /// # Arguments
+    ///
+    /// * `receive` - The segment, it must not be a RST itself
+    ///
+    /// # Returns
+    ///
+    /// * `isize` - The size of the sent packet
+    pub fn send_reset(&self, receive: &ReceiveData) -> isize {
+        let mut packet = self.make_packet_with_none().to_reset_packet(receive);
+        let sent_size = self.transmit(&mut packet);
 
+        tracing::info!("reset send: {}, with size: {}", packet, sent_size);
         sent_size
     }
+
+    /// Sends a keepalive probe, without touching the TCB
+    ///
+    /// # Returns
+    ///
+    /// * `isize` - The size of the sent packet
+    pub fn send_keepalive(&self) -> isize {
+        let mut packet = self.make_probe_packet();
+        let sent_size = self.transmit(&mut packet);
+
+        tracing::info!("keepalive probe send: {}, with size: {}", packet, sent_size);
+        sent_size
+    }
+
+    /// Sends a probe for the remote's zero window, without touching the TCB
+    ///
+    /// # Returns
+    ///
+    /// * `isize` - The size of the sent packet
+    pub fn send_window_probe(&self) -> isize {
+        let mut packet = self.make_probe_packet();
+        let sent_size = self.transmit(&mut packet);
+
+        tracing::info!("window probe send: {}, with size: {}", packet, sent_size);
+        sent_size
+    }
+
+    /// Makes a keepalive or window probe from the current TCB
+    fn make_probe_packet(&self) -> TCPPacket {
+        let (snd_una, rcv_nxt, rcv_wnd) = {
+            let tcb = self.tcb.read();
+            (tcb.snd_una, tcb.rcv_nxt, tcb.rcv_wnd)
+        };
+        self.make_packet_with_none().to_probe_packet(snd_una, rcv_nxt, rcv_wnd)
+    }
+
+    /// Writes a TCP packet to the link exactly as it is, without touching the TCB
+    ///
+    /// Every packet that leaves the connection goes through here, so this is where sent packets are captured.
+    ///
+    /// # Arguments
+    ///
+    /// * `tcppacket` - A mutable reference to the TCP packet to be sent
+    ///
+    /// # Returns
+    ///
+    /// * `isize` - The size of the sent packet
+    pub fn transmit(&self, tcppacket: &mut TCPPacket) -> isize {
+        tcppacket.as_ptr();
+        let packet = &tcppacket.data_vec[..tcppacket.len()];
+
+        // Recorded before it is sent, an answer over loopback may be received before the send returns
+        if let Some(capture) = &self.capture {
+            capture.record(&tcppacket.ip_head, packet);
+        }
+
+        self.link.send(&tcppacket.ip_head, packet, &self.sockaddr_to_remote)
+    }
 }
 
 /// TCPPacket struct implementation
+///
+/// These functions only set the control flags, the sequence and acknowledgement numbers are filled in from the
+/// TCB by `Controller::send_packet`.
 impl TCPPacket {
+    /// Refreshes the acknowledgement number and window of a packet that is sent again
+    ///
+    /// The checksum is updated for the changed fields as in RFC 1624, rather than computed over the whole packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `rcv_nxt` - The current RCV.NXT
+    /// * `rcv_wnd` - The current receive window
+    pub fn restamp(&mut self, rcv_nxt: u32, rcv_wnd: u16) {
+        unsafe {
+            let tcp_head = &mut self.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
+            let mut check = tcp_head.check.to_host();
+            if tcp_head.ack() == 1 {
+                check = checksum::update_u32(check, tcp_head.ack_seq.to_host(), rcv_nxt);
+                tcp_head.ack_seq = rcv_nxt.to_network();
+            }
+            check = checksum::update(check, tcp_head.window.to_host(), rcv_wnd);
+            tcp_head.window = rcv_wnd.to_network();
+
+            // A checksum that was not computed yet is computed in full when the packet is sent
+            if self.checksum_valid {
+                tcp_head.check = check.to_network();
+            }
+        }
+    }
+
+    /// The MSS announced in our SYN, which depends on the size of the IP header
+    #[inline]
+    fn announced_mss(&self) -> u16 {
+        match self.ip_head {
+            IpHeader::V4(_) => DEFAULT_MSS,
+            IpHeader::V6(_) => DEFAULT_MSS_V6,
+        }
+    }
+
     /// Converts the packet to a first handshake packet
     ///
+    /// The SYN announces our MSS and a window scale of zero, so the remote may scale the windows it sends us.
+    ///
     /// # Returns
     ///
     /// * `TCPPacket` - The converted TCP packet
@@ -83,122 +213,146 @@ impl TCPPacket {
         unsafe {
             self.tcp_head.__bindgen_anon_1.__bindgen_anon_2.set_syn(1);
         }
+        self.set_options(&[
+            TcpOption::MaximumSegmentSize(self.announced_mss()),
+            TcpOption::WindowScale(0),
+        ]).unwrap();
         self
     }
 
-    /// Converts the packet to a third handshake packet
+    /// Converts the packet to a second handshake packet, the answer to a remote's SYN
     ///
     /// # Arguments
     ///
-    /// * `response_ack_seq` - The response acknowledgement sequence
-    /// * `response_seq` - The response sequence
+    /// * `window_scale` - Whether the remote's SYN offered window scaling, the option is only echoed if it did
     ///
     /// # Returns
     ///
     /// * `TCPPacket` - The converted TCP packet
-    pub fn to_third_handshake(mut self, response_ack_seq: u32, response_seq: u32) -> TCPPacket {
+    pub fn to_second_handshake(mut self, window_scale: bool) -> TCPPacket {
         unsafe {
-            let tcp_head = &mut self.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
-            tcp_head.set_ack(1);
-            tcp_head.seq = response_ack_seq;
-            tcp_head.ack_seq = (response_seq.to_host() + 1).to_network();
+            self.tcp_head.__bindgen_anon_1.__bindgen_anon_2.set_syn(1);
+            self.tcp_head.__bindgen_anon_1.__bindgen_anon_2.set_ack(1);
         }
 
+        let mut options = vec![TcpOption::MaximumSegmentSize(self.announced_mss())];
+        if window_scale {
+            options.push(TcpOption::WindowScale(0));
+        }
+        self.set_options(&options).unwrap();
         self
     }
 
-    /// Converts the packet to a fourth handshake packet
+    /// Converts the packet to a third handshake packet
     ///
-    /// # Arguments
+    /// # Returns
     ///
-    /// * `response_ack_seq` - The response acknowledgement sequence
-    /// * `response_seq` - The response sequence
+    /// * `TCPPacket` - The converted TCP packet
+    pub fn to_third_handshake(self) -> TCPPacket {
+        self.to_ack_packet()
+    }
+
+    /// Converts the packet to a bare acknowledgement packet
     ///
     /// # Returns
     ///
     /// * `TCPPacket` - The converted TCP packet
-    pub fn to_fourth_handshake(mut self, response_ack_seq: u32, response_seq: u32) -> TCPPacket {
+    pub fn to_ack_packet(mut self) -> TCPPacket {
+        unsafe {
+            self.tcp_head.__bindgen_anon_1.__bindgen_anon_2.set_ack(1);
+        }
+
+        self
+    }
+
+    /// Converts the packet to a data packet
+    ///
+    /// # Returns
+    ///
+    /// * `TCPPacket` - The converted TCP packet
+    pub fn to_data_packet(mut self) -> TCPPacket {
         unsafe {
             let tcp_head = &mut self.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
-            tcp_head.set_ack(1);
 
-            tcp_head.seq = (response_ack_seq.to_host() + 1).to_network();
-            tcp_head.ack_seq = (response_seq.to_host() + 1).to_network();
+            tcp_head.set_psh(1);
+            tcp_head.set_ack(1);
         }
 
         self
     }
 
-    /// Converts the packet to a data acknowledgement packet
+    /// Converts the packet to the RST answering a segment, formed as RFC 9293 section 3.10.7.1 asks
+    ///
+    /// The RST takes its sequence number from the segment's acknowledgement number, so the remote finds it in its
+    /// window. A segment without an acknowledgement number is acknowledged instead, from sequence number zero.
     ///
     /// # Arguments
     ///
-    /// * `response_seq` - The response sequence
-    /// * `response_ack` - The response acknowledgement
-    /// * `data_size` - The size of the data
+    /// * `receive` - The segment answered
     ///
     /// # Returns
     ///
     /// * `TCPPacket` - The converted TCP packet
-    pub fn to_data_ack_packet(mut self, response_seq: u32, response_ack: u32, data_size: u32) -> TCPPacket {
-        let response_seq = response_seq.to_host();
-
+    pub fn to_reset_packet(mut self, receive: &ReceiveData) -> TCPPacket {
         unsafe {
             let tcp_head = &mut self.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
 
-            tcp_head.set_ack(1);
-            tcp_head.seq = response_ack;
-            tcp_head.ack_seq = (response_seq + data_size).to_network();
+            tcp_head.set_rst(1);
+            if receive.tcphdr.ack() == 1 {
+                tcp_head.seq = receive.tcphdr.ack_seq;
+            } else {
+                tcp_head.seq = 0;
+                tcp_head.ack_seq = receive.tcphdr.seq.to_host().wrapping_add(receive.sequence_length()).to_network();
+                tcp_head.set_ack(1);
+            }
+            tcp_head.window = 0;
         }
+        self.checksum_valid = false;
 
         self
     }
 
-    /// Converts the packet to a data packet
+    /// Converts the packet to a keepalive or window probe
+    ///
+    /// The probe carries SND.UNA - 1, a sequence number the remote has already acknowledged, which it answers with an
+    /// ACK of its own whatever state its end is in and however small its window is.
     ///
     /// # Arguments
     ///
-    /// * `response_seq` - The response sequence
-    /// * `response_ack` - The response acknowledgement
+    /// * `snd_una` - The current SND.UNA
+    /// * `rcv_nxt` - The current RCV.NXT
+    /// * `rcv_wnd` - The current receive window
     ///
     /// # Returns
     ///
     /// * `TCPPacket` - The converted TCP packet
-    pub fn to_data_packet(mut self, response_seq: u32, response_ack: u32) -> TCPPacket {
+    pub fn to_probe_packet(mut self, snd_una: u32, rcv_nxt: u32, rcv_wnd: u16) -> TCPPacket {
         unsafe {
             let tcp_head = &mut self.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
 
-            tcp_head.set_psh(1);
             tcp_head.set_ack(1);
-
-            tcp_head.seq = response_ack;
-            tcp_head.ack_seq = response_seq;
+            tcp_head.seq = snd_una.wrapping_sub(1).to_network();
+            tcp_head.ack_seq = rcv_nxt.to_network();
+            tcp_head.window = rcv_wnd.to_network();
         }
+        self.checksum_valid = false;
 
         self
     }
 
     /// Converts the packet to a FIN packet
     ///
-    /// # Arguments
-    ///
-    /// * `response_seq` - The response sequence
-    /// * `response_ack` - The response acknowledgement
-    ///
     /// # Returns
     ///
     /// * `TCPPacket` - The converted TCP packet
-    pub fn to_fin_packet(mut self, response_seq: u32, response_ack: u32) -> TCPPacket {
+    pub fn to_fin_packet(mut self) -> TCPPacket {
         unsafe {
             let tcp_head = &mut self.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
 
             tcp_head.set_fin(1);
             tcp_head.set_ack(1);
-
-            tcp_head.ack_seq = response_seq;
-            tcp_head.seq = response_ack;
         }
 
         self
     }
-}
\ No newline at end of file
+}
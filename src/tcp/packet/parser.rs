@@ -0,0 +1,529 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::raw_bindings::raw_bindings::{__BindgenBitfieldUnit, iphdr, IPPROTO_TCP, tcphdr__bindgen_ty_1__bindgen_ty_2};
+use crate::tcp::packet::checksum::Checksum;
+use crate::tcp::packet::ip_header::Ipv6Header;
+use crate::tcp::packet::options::{decode_options, TcpOption};
+
+/// Length of an IPv4 header without options.
+pub const IPV4_MIN_HEADER_LENGTH: usize = 20;
+/// Length of the fixed IPv6 header.
+pub const IPV6_HEADER_LENGTH: usize = 40;
+/// Length of a TCP header without options.
+pub const TCP_MIN_HEADER_LENGTH: usize = 20;
+
+/// Why a received packet could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The buffer ends before a header that has to be there
+    Truncated { needed: usize, available: usize },
+    /// The IP version field is neither 4 nor 6, or not the one expected
+    UnsupportedVersion(u8),
+    /// The IHL is below the five words of the fixed header, or the header runs past the buffer
+    HeaderLength(u8),
+    /// The total length is shorter than the header or longer than the buffer, for IPv6 the payload length
+    /// runs past the buffer
+    TotalLength { total_length: usize, header_length: usize, available: usize },
+    /// The packet carries another protocol than TCP
+    NotTcp(u8),
+    /// The data offset is below the five words of the fixed header, or the header runs past the segment
+    DataOffset(u8),
+    /// The options area of the TCP header is malformed
+    Options(String),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Truncated { needed, available } => {
+                write!(f, "truncated packet: {} bytes needed, {} available", needed, available)
+            }
+            ParseError::UnsupportedVersion(version) => write!(f, "unsupported IP version {}", version),
+            ParseError::HeaderLength(ihl) => write!(f, "invalid IP header length of {} words", ihl),
+            ParseError::TotalLength { total_length, header_length, available } => write!(
+                f,
+                "invalid IP total length {} for a {} byte header and {} bytes received",
+                total_length,
+                header_length,
+                available
+            ),
+            ParseError::NotTcp(protocol) => write!(f, "protocol {} is not TCP", protocol),
+            ParseError::DataOffset(doff) => write!(f, "invalid TCP data offset of {} words", doff),
+            ParseError::Options(e) => write!(f, "malformed TCP options: {}", e),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// A validated view of an IPv4 packet, borrowing the buffer it was read into.
+///
+/// Every accessor only reads bytes `parse` has checked to be there, whatever the packet claims about itself.
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4Packet<'a> {
+    buffer: &'a [u8],
+    header_length: usize,
+    total_length: usize,
+}
+
+impl<'a> Ipv4Packet<'a> {
+    /// Checks the version, IHL and total length of a packet against the bytes received.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The packet as read from the socket, bytes behind the total length are ignored
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Ipv4Packet, ParseError>` - The view, or why the packet can not be read
+    pub fn parse(buffer: &'a [u8]) -> Result<Self, ParseError> {
+        if buffer.len() < IPV4_MIN_HEADER_LENGTH {
+            return Err(ParseError::Truncated { needed: IPV4_MIN_HEADER_LENGTH, available: buffer.len() });
+        }
+
+        let version = buffer[0] >> 4;
+        if version != 4 {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+
+        let ihl = buffer[0] & 0x0f;
+        let header_length = ihl as usize * 4;
+        if header_length < IPV4_MIN_HEADER_LENGTH || header_length > buffer.len() {
+            return Err(ParseError::HeaderLength(ihl));
+        }
+
+        let total_length = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
+        if total_length < header_length || total_length > buffer.len() {
+            return Err(ParseError::TotalLength { total_length, header_length, available: buffer.len() });
+        }
+
+        Ok(Ipv4Packet { buffer, header_length, total_length })
+    }
+
+    /// Length of the header with its options.
+    #[inline]
+    pub fn header_length(&self) -> usize {
+        self.header_length
+    }
+
+    #[inline]
+    pub fn total_length(&self) -> usize {
+        self.total_length
+    }
+
+    #[inline]
+    pub fn protocol(&self) -> u8 {
+        self.buffer[9]
+    }
+
+    #[inline]
+    pub fn source(&self) -> Ipv4Addr {
+        Ipv4Addr::new(self.buffer[12], self.buffer[13], self.buffer[14], self.buffer[15])
+    }
+
+    #[inline]
+    pub fn destination(&self) -> Ipv4Addr {
+        Ipv4Addr::new(self.buffer[16], self.buffer[17], self.buffer[18], self.buffer[19])
+    }
+
+    /// The fixed part of the header, with its fields in network byte order as the kernel hands them out.
+    pub fn header(&self) -> iphdr {
+        let bytes = &self.buffer[..IPV4_MIN_HEADER_LENGTH];
+        iphdr {
+            _bitfield_align_1: [],
+            _bitfield_1: __BindgenBitfieldUnit::new([bytes[0]]),
+            tos: bytes[1],
+            tot_len: u16::from_ne_bytes([bytes[2], bytes[3]]),
+            id: u16::from_ne_bytes([bytes[4], bytes[5]]),
+            frag_off: u16::from_ne_bytes([bytes[6], bytes[7]]),
+            ttl: bytes[8],
+            protocol: bytes[9],
+            check: u16::from_ne_bytes([bytes[10], bytes[11]]),
+            saddr: u32::from_ne_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            daddr: u32::from_ne_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+        }
+    }
+
+    /// Whether the header checksum matches the header.
+    #[inline]
+    pub fn is_checksum_valid(&self) -> bool {
+        Checksum::new().add(&self.buffer[..self.header_length]).is_valid()
+    }
+
+    /// The bytes between the header and the total length.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        &self.buffer[self.header_length..self.total_length]
+    }
+
+    /// Parses the payload as a TCP segment.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<TcpSegment, ParseError>` - The segment, or why the payload is not one
+    pub fn tcp(&self) -> Result<TcpSegment<'a>, ParseError> {
+        if self.protocol() != IPPROTO_TCP as u8 {
+            return Err(ParseError::NotTcp(self.protocol()));
+        }
+        TcpSegment::parse(self.payload())
+    }
+}
+
+/// A validated view of an IPv6 packet, borrowing the buffer it was read into.
+///
+/// Extension headers are not followed, a packet whose next header is not TCP is not a segment of ours.
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv6Packet<'a> {
+    buffer: &'a [u8],
+    payload_length: usize,
+}
+
+impl<'a> Ipv6Packet<'a> {
+    /// Checks the version and payload length of a packet against the bytes received.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The packet as read from the link, bytes behind the payload length are ignored
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Ipv6Packet, ParseError>` - The view, or why the packet can not be read
+    pub fn parse(buffer: &'a [u8]) -> Result<Self, ParseError> {
+        if buffer.len() < IPV6_HEADER_LENGTH {
+            return Err(ParseError::Truncated { needed: IPV6_HEADER_LENGTH, available: buffer.len() });
+        }
+
+        let version = buffer[0] >> 4;
+        if version != 6 {
+            return Err(ParseError::UnsupportedVersion(version));
+        }
+
+        let payload_length = u16::from_be_bytes([buffer[4], buffer[5]]) as usize;
+        if IPV6_HEADER_LENGTH + payload_length > buffer.len() {
+            return Err(ParseError::TotalLength {
+                total_length: IPV6_HEADER_LENGTH + payload_length,
+                header_length: IPV6_HEADER_LENGTH,
+                available: buffer.len(),
+            });
+        }
+
+        Ok(Ipv6Packet { buffer, payload_length })
+    }
+
+    #[inline]
+    pub fn payload_length(&self) -> usize {
+        self.payload_length
+    }
+
+    #[inline]
+    pub fn next_header(&self) -> u8 {
+        self.buffer[6]
+    }
+
+    #[inline]
+    pub fn source(&self) -> Ipv6Addr {
+        Ipv6Addr::from(<[u8; 16]>::try_from(&self.buffer[8..24]).unwrap())
+    }
+
+    #[inline]
+    pub fn destination(&self) -> Ipv6Addr {
+        Ipv6Addr::from(<[u8; 16]>::try_from(&self.buffer[24..40]).unwrap())
+    }
+
+    /// What this stack keeps of the header.
+    pub fn header(&self) -> Ipv6Header {
+        Ipv6Header {
+            source: self.source(),
+            destination: self.destination(),
+            payload_length: self.payload_length as u16,
+        }
+    }
+
+    /// The bytes between the header and the payload length.
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        &self.buffer[IPV6_HEADER_LENGTH..IPV6_HEADER_LENGTH + self.payload_length]
+    }
+
+    /// Parses the payload as a TCP segment.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<TcpSegment, ParseError>` - The segment, or why the payload is not one
+    pub fn tcp(&self) -> Result<TcpSegment<'a>, ParseError> {
+        if self.next_header() != IPPROTO_TCP as u8 {
+            return Err(ParseError::NotTcp(self.next_header()));
+        }
+        TcpSegment::parse(self.payload())
+    }
+}
+
+/// A validated view of a TCP segment, borrowing the buffer it was read into.
+#[derive(Debug, Clone, Copy)]
+pub struct TcpSegment<'a> {
+    buffer: &'a [u8],
+    header_length: usize,
+}
+
+impl<'a> TcpSegment<'a> {
+    /// Checks the data offset of a segment against its length.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The segment, from the first byte of its header to the last byte of its data
+    ///
+    /// # Returns
+    ///
+    /// * `Result<TcpSegment, ParseError>` - The view, or why the segment can not be read
+    pub fn parse(buffer: &'a [u8]) -> Result<Self, ParseError> {
+        if buffer.len() < TCP_MIN_HEADER_LENGTH {
+            return Err(ParseError::Truncated { needed: TCP_MIN_HEADER_LENGTH, available: buffer.len() });
+        }
+
+        let doff = buffer[12] >> 4;
+        let header_length = doff as usize * 4;
+        if header_length < TCP_MIN_HEADER_LENGTH || header_length > buffer.len() {
+            return Err(ParseError::DataOffset(doff));
+        }
+
+        Ok(TcpSegment { buffer, header_length })
+    }
+
+    /// Length of the header with its options.
+    #[inline]
+    pub fn header_length(&self) -> usize {
+        self.header_length
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The whole segment, header and data.
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buffer
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    #[inline]
+    pub fn source_port(&self) -> u16 {
+        u16::from_be_bytes([self.buffer[0], self.buffer[1]])
+    }
+
+    #[inline]
+    pub fn destination_port(&self) -> u16 {
+        u16::from_be_bytes([self.buffer[2], self.buffer[3]])
+    }
+
+    #[inline]
+    pub fn seq(&self) -> u32 {
+        u32::from_be_bytes([self.buffer[4], self.buffer[5], self.buffer[6], self.buffer[7]])
+    }
+
+    #[inline]
+    pub fn ack(&self) -> u32 {
+        u32::from_be_bytes([self.buffer[8], self.buffer[9], self.buffer[10], self.buffer[11]])
+    }
+
+    /// The flags byte: FIN, SYN, RST, PSH, ACK and URG from the lowest bit up.
+    #[inline]
+    pub fn flags(&self) -> u8 {
+        self.buffer[13]
+    }
+
+    #[inline]
+    pub fn window(&self) -> u16 {
+        u16::from_be_bytes([self.buffer[14], self.buffer[15]])
+    }
+
+    /// The fixed part of the header, with its fields in network byte order as the kernel hands them out.
+    pub fn header(&self) -> tcphdr__bindgen_ty_1__bindgen_ty_2 {
+        let bytes = &self.buffer[..TCP_MIN_HEADER_LENGTH];
+        tcphdr__bindgen_ty_1__bindgen_ty_2 {
+            source: u16::from_ne_bytes([bytes[0], bytes[1]]),
+            dest: u16::from_ne_bytes([bytes[2], bytes[3]]),
+            seq: u32::from_ne_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            ack_seq: u32::from_ne_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            _bitfield_align_1: [],
+            _bitfield_1: __BindgenBitfieldUnit::new([bytes[12], bytes[13]]),
+            window: u16::from_ne_bytes([bytes[14], bytes[15]]),
+            check: u16::from_ne_bytes([bytes[16], bytes[17]]),
+            urg_ptr: u16::from_ne_bytes([bytes[18], bytes[19]]),
+        }
+    }
+
+    /// The options area between the fixed header and the data.
+    #[inline]
+    pub fn options_bytes(&self) -> &'a [u8] {
+        &self.buffer[TCP_MIN_HEADER_LENGTH..self.header_length]
+    }
+
+    pub fn options(&self) -> Result<Vec<TcpOption>, ParseError> {
+        decode_options(self.options_bytes()).map_err(ParseError::Options)
+    }
+
+    #[inline]
+    pub fn payload(&self) -> &'a [u8] {
+        &self.buffer[self.header_length..]
+    }
+
+    /// Whether the checksum matches the segment.
+    ///
+    /// # Arguments
+    ///
+    /// * `pseudo_header` - The pseudo-header of the IP packet that carried the segment
+    pub fn is_checksum_valid(&self, pseudo_header: &[u8]) -> bool {
+        Checksum::new().add(pseudo_header).add(self.buffer).is_valid()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A TCP header from port 4000 to port 80 with the given data offset, followed by `rest`.
+    fn tcp(doff: u8, rest: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0x0f, 0xa0, 0x00, 0x50, 0, 0, 0x03, 0xe8, 0, 0, 0x07, 0xd0, doff << 4, 0x12, 0xff, 0xff, 0, 0, 0, 0];
+        segment.extend_from_slice(rest);
+        segment
+    }
+
+    /// An IPv4 packet from 10.0.0.1 to 10.0.0.2 with a correct header checksum.
+    fn ipv4(ihl: u8, protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let total_length = (IPV4_MIN_HEADER_LENGTH + payload.len()) as u16;
+        let mut packet = vec![0x40 | ihl, 0, 0, 0, 0, 0, 0x40, 0, 64, protocol, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2];
+        packet[2..4].copy_from_slice(&total_length.to_be_bytes());
+        let check = Checksum::new().add(&packet).finish();
+        packet[10..12].copy_from_slice(&check.to_be_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    /// An IPv6 packet from ::1 to ::2.
+    fn ipv6(next_header: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0, 0, 0, next_header, 64];
+        packet[4..6].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        packet.extend_from_slice(&Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2).octets());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn parses_an_ipv4_segment_with_options_and_data() {
+        let segment = tcp(6, &[2, 4, 0x05, 0xb4, b'h', b'i']);
+        let mut packet = ipv4(5, IPPROTO_TCP as u8, &segment);
+        // Ethernet padding behind the total length is ignored
+        packet.extend_from_slice(&[0; 6]);
+
+        let ip = Ipv4Packet::parse(&packet).unwrap();
+        assert!(ip.is_checksum_valid());
+        assert_eq!((ip.header_length(), ip.total_length()), (20, 46));
+        assert_eq!((ip.source(), ip.destination()), (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(ip.payload(), segment.as_slice());
+
+        let tcp = ip.tcp().unwrap();
+        assert_eq!((tcp.source_port(), tcp.destination_port()), (4000, 80));
+        assert_eq!((tcp.seq(), tcp.ack(), tcp.flags(), tcp.window()), (1000, 2000, 0x12, 0xffff));
+        assert_eq!(tcp.header_length(), 24);
+        assert_eq!(tcp.options().unwrap(), vec![TcpOption::MaximumSegmentSize(1460)]);
+        assert_eq!(tcp.payload(), b"hi");
+    }
+
+    #[test]
+    fn parses_an_ipv6_segment() {
+        let segment = tcp(5, b"data");
+        let packet = ipv6(IPPROTO_TCP as u8, &segment);
+
+        let ip = Ipv6Packet::parse(&packet).unwrap();
+        assert_eq!(ip.payload_length(), segment.len());
+        assert_eq!(ip.header().destination, Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2));
+        assert_eq!(ip.tcp().unwrap().payload(), b"data");
+    }
+
+    #[test]
+    fn rejects_truncated_headers() {
+        assert_eq!(Ipv4Packet::parse(&[0x45; 19]).unwrap_err(), ParseError::Truncated { needed: 20, available: 19 });
+        assert_eq!(Ipv6Packet::parse(&[0x60; 39]).unwrap_err(), ParseError::Truncated { needed: 40, available: 39 });
+        assert_eq!(TcpSegment::parse(&[0x50; 19]).unwrap_err(), ParseError::Truncated { needed: 20, available: 19 });
+    }
+
+    #[test]
+    fn rejects_an_unexpected_ip_version() {
+        let mut packet = ipv4(5, IPPROTO_TCP as u8, &tcp(5, &[]));
+        packet[0] = 0x65;
+        assert_eq!(Ipv4Packet::parse(&packet).unwrap_err(), ParseError::UnsupportedVersion(6));
+
+        let packet = ipv4(5, IPPROTO_TCP as u8, &tcp(5, &[]));
+        assert_eq!(Ipv6Packet::parse(&packet).unwrap_err(), ParseError::UnsupportedVersion(4));
+    }
+
+    #[test]
+    fn rejects_an_ihl_below_five_words_or_past_the_buffer() {
+        let packet = ipv4(4, IPPROTO_TCP as u8, &tcp(5, &[]));
+        assert_eq!(Ipv4Packet::parse(&packet).unwrap_err(), ParseError::HeaderLength(4));
+
+        let mut packet = ipv4(5, IPPROTO_TCP as u8, &[]);
+        packet[0] = 0x46;
+        assert_eq!(Ipv4Packet::parse(&packet).unwrap_err(), ParseError::HeaderLength(6));
+    }
+
+    #[test]
+    fn rejects_a_total_length_shorter_than_the_header_or_longer_than_the_buffer() {
+        let mut packet = ipv4(5, IPPROTO_TCP as u8, &tcp(5, &[]));
+        packet[2..4].copy_from_slice(&19u16.to_be_bytes());
+        assert_eq!(
+            Ipv4Packet::parse(&packet).unwrap_err(),
+            ParseError::TotalLength { total_length: 19, header_length: 20, available: 40 }
+        );
+
+        packet[2..4].copy_from_slice(&41u16.to_be_bytes());
+        assert_eq!(
+            Ipv4Packet::parse(&packet).unwrap_err(),
+            ParseError::TotalLength { total_length: 41, header_length: 20, available: 40 }
+        );
+
+        let mut packet = ipv6(IPPROTO_TCP as u8, &tcp(5, &[]));
+        packet[4..6].copy_from_slice(&21u16.to_be_bytes());
+        assert_eq!(
+            Ipv6Packet::parse(&packet).unwrap_err(),
+            ParseError::TotalLength { total_length: 61, header_length: 40, available: 60 }
+        );
+    }
+
+    #[test]
+    fn rejects_other_protocols() {
+        let packet = ipv4(5, 17, &[0; 8]);
+        assert_eq!(Ipv4Packet::parse(&packet).unwrap().tcp().unwrap_err(), ParseError::NotTcp(17));
+
+        let packet = ipv6(58, &[0; 8]);
+        assert_eq!(Ipv6Packet::parse(&packet).unwrap().tcp().unwrap_err(), ParseError::NotTcp(58));
+    }
+
+    #[test]
+    fn rejects_a_data_offset_below_five_words_or_past_the_end() {
+        assert_eq!(TcpSegment::parse(&tcp(4, &[])).unwrap_err(), ParseError::DataOffset(4));
+        assert_eq!(TcpSegment::parse(&tcp(6, &[1, 1, 1])).unwrap_err(), ParseError::DataOffset(6));
+        assert_eq!(TcpSegment::parse(&tcp(15, &[0; 20])).unwrap_err(), ParseError::DataOffset(15));
+
+        // The data offset is checked against the segment, not against the buffer behind it
+        let mut packet = ipv4(5, IPPROTO_TCP as u8, &tcp(6, &[]));
+        packet.extend_from_slice(&[1, 1, 1, 1]);
+        assert_eq!(Ipv4Packet::parse(&packet).unwrap().tcp().unwrap_err(), ParseError::DataOffset(6));
+    }
+
+    #[test]
+    fn reports_malformed_options() {
+        let buffer = tcp(6, &[2, 3, 0, 0]);
+        let segment = TcpSegment::parse(&buffer).unwrap();
+        assert!(matches!(segment.options(), Err(ParseError::Options(_))));
+    }
+}
@@ -0,0 +1,117 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+/// Shortest time between two window probes.
+const MIN_PERSIST_INTERVAL: Duration = Duration::from_millis(200);
+/// Longest time between two window probes, the bound of the RTO, RFC 6298 section 2.5.
+const MAX_PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many window probes in a row may go unanswered before the connection is aborted.
+pub const MAX_WINDOW_PROBES: u32 = 8;
+
+/// Outcome of a persist timer expiry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Persist {
+    /// A window probe has to be sent.
+    Probe,
+    /// `MAX_WINDOW_PROBES` probes in a row were not answered.
+    GiveUp,
+    /// The timer is not running.
+    Idle,
+}
+
+/// The persist timer of RFC 9293 section 3.8.6.1, which probes a zero window the remote advertised.
+///
+/// The first probe is sent one RTO after the window closed, every further one after twice the time of the one
+/// before. Probes are sent for as long as the remote answers them, the window staying closed is no reason to give up.
+pub struct PersistTimer {
+    deadline: Option<Instant>,
+    interval: Duration,
+    /// When the last probe was sent
+    probed: Option<Instant>,
+    unanswered: u32,
+    notify: Arc<Notify>,
+}
+
+impl Default for PersistTimer {
+    fn default() -> Self {
+        PersistTimer {
+            deadline: None,
+            interval: MIN_PERSIST_INTERVAL,
+            probed: None,
+            unanswered: 0,
+            notify: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl PersistTimer {
+    /// The notifier that wakes the timer task whenever the deadline changes.
+    #[inline]
+    pub fn notify(&self) -> Arc<Notify> {
+        self.notify.clone()
+    }
+
+    /// When the persist timer fires next, `None` if it is not running.
+    #[inline]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    #[inline]
+    pub fn is_running(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    /// Starts the timer for a window that just closed, a running timer is left alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `rto` - The current retransmission timeout, the time until the first probe
+    pub fn start(&mut self, rto: Duration) {
+        if self.is_running() {
+            return;
+        }
+
+        self.interval = rto.clamp(MIN_PERSIST_INTERVAL, MAX_PERSIST_INTERVAL);
+        self.probed = None;
+        self.unanswered = 0;
+        self.deadline = Some(Instant::now() + self.interval);
+        self.notify.notify_one();
+    }
+
+    /// Stops the timer, the window opened or there is nothing left to send.
+    pub fn stop(&mut self) {
+        if self.deadline.take().is_some() {
+            self.notify.notify_one();
+        }
+    }
+
+    /// Handles an expiry of the persist timer, and backs it off if a probe is due.
+    ///
+    /// # Arguments
+    ///
+    /// * `heard` - When the last segment from the remote arrived, a probe sent before it counts as answered
+    pub fn on_timeout(&mut self, heard: Instant) -> Persist {
+        if self.deadline.is_none() {
+            return Persist::Idle;
+        }
+
+        if self.probed.is_some_and(|probed| heard > probed) {
+            self.unanswered = 0;
+        }
+        if self.unanswered >= MAX_WINDOW_PROBES {
+            self.deadline = None;
+            return Persist::GiveUp;
+        }
+
+        let now = Instant::now();
+        self.probed = Some(now);
+        self.unanswered += 1;
+        self.interval = (self.interval * 2).min(MAX_PERSIST_INTERVAL);
+        self.deadline = Some(now + self.interval);
+        Persist::Probe
+    }
+}
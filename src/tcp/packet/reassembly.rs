@@ -0,0 +1,228 @@
+use crate::tcp::packet::tcb::{seq_le, seq_lt, TransmissionControlBlock};
+
+/// What a received segment made available to the application.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// Bytes that are now contiguous with everything delivered before
+    pub data: Vec<u8>,
+    /// Whether the remote's FIN has been reached
+    pub fin: bool,
+}
+
+/// Receive buffer that keeps out-of-order segments by sequence number until the gap before them is filled.
+///
+/// Buffered segments never overlap each other and always lie inside the receive window.
+#[derive(Debug, Default)]
+pub struct ReassemblyBuffer {
+    segments: Vec<(u32, Vec<u8>)>,
+    fin: Option<u32>,
+}
+
+impl ReassemblyBuffer {
+    /// Number of bytes waiting for a gap to be filled.
+    pub fn buffered(&self) -> usize {
+        self.segments.iter().map(|(_, data)| data.len()).sum()
+    }
+
+    /// Adds the payload of a segment to the buffer and delivers whatever became contiguous at RCV.NXT.
+    ///
+    /// # Arguments
+    ///
+    /// * `tcb` - The TCB, its RCV.NXT is advanced past the delivered bytes and the FIN
+    /// * `seq` - The sequence number of the first payload byte
+    /// * `payload` - The payload of the segment
+    /// * `fin` - Whether the segment carries a FIN
+    ///
+    /// # Returns
+    ///
+    /// * `Delivery` - The bytes, and possibly the FIN, that are now in order
+    pub fn receive(&mut self, tcb: &mut TransmissionControlBlock, seq: u32, payload: &[u8], fin: bool) -> Delivery {
+        let rcv_nxt = tcb.rcv_nxt;
+        let window_end = rcv_nxt.wrapping_add(tcb.rcv_wnd as u32);
+
+        // A FIN that was already consumed is a retransmission and must not be delivered twice
+        let fin_seq = seq.wrapping_add(payload.len() as u32);
+        let mut fin = fin && seq_le(rcv_nxt, fin_seq);
+
+        // Trim the part that was already delivered and the part beyond the window
+        let mut start = seq;
+        let mut payload = payload;
+        if seq_lt(start, rcv_nxt) {
+            let skip = (rcv_nxt.wrapping_sub(start) as usize).min(payload.len());
+            payload = &payload[skip..];
+            start = rcv_nxt;
+        }
+
+        let end = start.wrapping_add(payload.len() as u32);
+        if seq_lt(window_end, end) {
+            let keep = if seq_lt(start, window_end) { window_end.wrapping_sub(start) as usize } else { 0 };
+            payload = &payload[..keep.min(payload.len())];
+            fin = false;
+        }
+
+        if fin {
+            self.fin = Some(fin_seq);
+        }
+
+        if !payload.is_empty() {
+            self.insert(rcv_nxt, start, payload);
+        }
+
+        self.deliver(tcb)
+    }
+
+    /// Inserts `[start, start + payload.len())` without overlapping any segment already buffered.
+    fn insert(&mut self, rcv_nxt: u32, start: u32, payload: &[u8]) {
+        let mut pieces = vec![(start, payload.to_vec())];
+
+        for (seq, data) in &self.segments {
+            let (seq, end) = (*seq, seq.wrapping_add(data.len() as u32));
+            pieces = pieces.into_iter().flat_map(|(piece_seq, piece)| {
+                let piece_end = piece_seq.wrapping_add(piece.len() as u32);
+                if seq_le(piece_end, seq) || seq_le(end, piece_seq) {
+                    return vec![(piece_seq, piece)];
+                }
+
+                let mut rest = Vec::with_capacity(2);
+                if seq_lt(piece_seq, seq) {
+                    let len = seq.wrapping_sub(piece_seq) as usize;
+                    rest.push((piece_seq, piece[..len].to_vec()));
+                }
+                if seq_lt(end, piece_end) {
+                    let skip = end.wrapping_sub(piece_seq) as usize;
+                    rest.push((end, piece[skip..].to_vec()));
+                }
+                rest
+            }).collect();
+        }
+
+        self.segments.extend(pieces);
+        self.segments.sort_by_key(|(seq, _)| seq.wrapping_sub(rcv_nxt));
+    }
+
+    /// Moves every segment that starts at RCV.NXT out of the buffer.
+    fn deliver(&mut self, tcb: &mut TransmissionControlBlock) -> Delivery {
+        let mut delivery = Delivery::default();
+
+        while self.segments.first().is_some_and(|(seq, _)| *seq == tcb.rcv_nxt) {
+            let (_, data) = self.segments.remove(0);
+            tcb.rcv_nxt = tcb.rcv_nxt.wrapping_add(data.len() as u32);
+            delivery.data.extend_from_slice(&data);
+        }
+
+        if self.fin == Some(tcb.rcv_nxt) {
+            self.fin = None;
+            tcb.rcv_nxt = tcb.rcv_nxt.wrapping_add(1);
+            delivery.fin = true;
+        }
+
+        delivery
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delivered(data: &[u8], fin: bool) -> Delivery {
+        Delivery { data: data.to_vec(), fin }
+    }
+
+    #[test]
+    fn delivers_in_order_data_right_away() {
+        let mut tcb = TransmissionControlBlock::expecting(100, 1000);
+        let mut buffer = ReassemblyBuffer::default();
+
+        assert_eq!(buffer.receive(&mut tcb, 100, b"abc", false), delivered(b"abc", false));
+        assert_eq!(tcb.rcv_nxt, 103);
+        assert_eq!(buffer.buffered(), 0);
+    }
+
+    #[test]
+    fn holds_out_of_order_segments_until_the_gap_is_filled() {
+        let mut tcb = TransmissionControlBlock::expecting(100, 1000);
+        let mut buffer = ReassemblyBuffer::default();
+
+        assert_eq!(buffer.receive(&mut tcb, 106, b"ghi", false), Delivery::default());
+        assert_eq!(buffer.receive(&mut tcb, 103, b"def", false), Delivery::default());
+        assert_eq!((buffer.buffered(), tcb.rcv_nxt), (6, 100));
+
+        assert_eq!(buffer.receive(&mut tcb, 100, b"abc", false), delivered(b"abcdefghi", false));
+        assert_eq!((buffer.buffered(), tcb.rcv_nxt), (0, 109));
+    }
+
+    #[test]
+    fn trims_segments_overlapping_buffered_ones() {
+        let mut tcb = TransmissionControlBlock::expecting(100, 1000);
+        let mut buffer = ReassemblyBuffer::default();
+
+        buffer.receive(&mut tcb, 104, b"ef", false);
+        // Covers the buffered segment on both sides
+        buffer.receive(&mut tcb, 102, b"cdEFgh", false);
+        assert_eq!(buffer.buffered(), 6);
+        // Overlaps its end
+        buffer.receive(&mut tcb, 107, b"HIJ", false);
+        assert_eq!(buffer.buffered(), 8);
+
+        // What was buffered first wins
+        assert_eq!(buffer.receive(&mut tcb, 100, b"abc", false), delivered(b"abcdefghIJ", false));
+        assert_eq!(tcb.rcv_nxt, 110);
+    }
+
+    #[test]
+    fn drops_duplicates_and_already_delivered_bytes() {
+        let mut tcb = TransmissionControlBlock::expecting(100, 1000);
+        let mut buffer = ReassemblyBuffer::default();
+
+        buffer.receive(&mut tcb, 105, b"fg", false);
+        buffer.receive(&mut tcb, 105, b"fg", false);
+        assert_eq!(buffer.buffered(), 2);
+
+        buffer.receive(&mut tcb, 100, b"abc", false);
+        assert_eq!(buffer.receive(&mut tcb, 100, b"abc", false), Delivery::default());
+        assert_eq!(tcb.rcv_nxt, 103);
+
+        // A retransmission reaching past RCV.NXT delivers only what is new
+        assert_eq!(buffer.receive(&mut tcb, 101, b"bcde", false), delivered(b"defg", false));
+        assert_eq!((buffer.buffered(), tcb.rcv_nxt), (0, 107));
+    }
+
+    #[test]
+    fn delivers_a_fin_only_after_the_data_before_it() {
+        let mut tcb = TransmissionControlBlock::expecting(100, 1000);
+        let mut buffer = ReassemblyBuffer::default();
+
+        assert_eq!(buffer.receive(&mut tcb, 103, b"def", true), Delivery::default());
+        assert_eq!(tcb.rcv_nxt, 100);
+
+        assert_eq!(buffer.receive(&mut tcb, 100, b"abc", false), delivered(b"abcdef", true));
+        // The FIN takes a sequence number of its own
+        assert_eq!(tcb.rcv_nxt, 107);
+
+        // A retransmitted FIN is not delivered twice
+        assert_eq!(buffer.receive(&mut tcb, 103, b"def", true), Delivery::default());
+        assert_eq!(tcb.rcv_nxt, 107);
+    }
+
+    #[test]
+    fn trims_data_beyond_the_window_and_its_fin() {
+        let mut tcb = TransmissionControlBlock::expecting(100, 4);
+        let mut buffer = ReassemblyBuffer::default();
+
+        assert_eq!(buffer.receive(&mut tcb, 100, b"abcdef", true), delivered(b"abcd", false));
+        assert_eq!(tcb.rcv_nxt, 104);
+    }
+
+    #[test]
+    fn reassembles_across_the_sequence_number_wraparound() {
+        let mut tcb = TransmissionControlBlock::expecting(u32::MAX - 2, 1000);
+        let mut buffer = ReassemblyBuffer::default();
+
+        assert_eq!(buffer.receive(&mut tcb, 3, b"ghi", true), Delivery::default());
+        assert_eq!(buffer.receive(&mut tcb, u32::MAX, b"cdef", false), Delivery::default());
+        assert_eq!(buffer.buffered(), 7);
+
+        assert_eq!(buffer.receive(&mut tcb, u32::MAX - 2, b"abc", false), delivered(b"abcdefghi", true));
+        assert_eq!(tcb.rcv_nxt, 7);
+    }
+}
@@ -0,0 +1,89 @@
+use std::task::Waker;
+
+use bytes::BytesMut;
+
+/// In-order data that has been received but not read by the application yet.
+///
+/// The buffer is only filled once a reader is attached, connections driven from the command line print their data
+/// instead. While attached, the receive window is whatever room is left in the buffer.
+#[derive(Debug)]
+pub struct ReceiveBuffer {
+    data: BytesMut,
+    /// The size of the buffer, the largest window we advertise
+    capacity: u16,
+    attached: bool,
+    /// The remote's FIN was delivered, or the connection closed
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+impl ReceiveBuffer {
+    pub fn new(capacity: u16) -> Self {
+        ReceiveBuffer {
+            data: BytesMut::new(),
+            capacity,
+            attached: false,
+            finished: false,
+            waker: None,
+        }
+    }
+
+    /// Keeps received data for a reader from now on.
+    pub fn attach(&mut self) {
+        self.attached = true;
+    }
+
+    #[inline]
+    pub fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    /// Appends delivered data and wakes up the reader.
+    pub fn push(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+        self.wake();
+    }
+
+    /// Takes up to `size` bytes from the front of the buffer.
+    pub fn take(&mut self, size: usize) -> Vec<u8> {
+        let size = size.min(self.data.len());
+        self.data.split_to(size).to_vec()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The receive window left by the unread data.
+    pub fn window(&self) -> u16 {
+        self.capacity.saturating_sub(self.data.len().min(u16::MAX as usize) as u16)
+    }
+
+    /// Marks the end of the stream, the reader sees EOF once the buffer has drained.
+    pub fn finish(&mut self) {
+        self.finished = true;
+        self.wake();
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Remembers the task to wake up when data arrives or the stream ends.
+    pub fn register(&mut self, waker: &Waker) {
+        self.waker = Some(waker.clone());
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+use crate::tcp::packet::tcb::seq_le;
+use crate::tcp::packet::tcp_packet::TCPPacket;
+
+/// RTO used before the first RTT sample, RFC 6298 section 2.1.
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+/// Lower bound of the RTO, RFC 6298 section 2.4.
+const MIN_RTO: Duration = Duration::from_secs(1);
+/// Upper bound of the RTO, RFC 6298 section 2.5.
+const MAX_RTO: Duration = Duration::from_secs(60);
+/// Clock granularity G of RFC 6298.
+const CLOCK_GRANULARITY: Duration = Duration::from_millis(1);
+
+/// How many times the oldest segment is retransmitted before the connection is aborted.
+pub const MAX_RETRANSMISSIONS: u32 = 8;
+
+/// Smoothed round-trip time estimator from RFC 6298.
+#[derive(Debug, Clone, Copy)]
+pub struct RtoEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl Default for RtoEstimator {
+    fn default() -> Self {
+        RtoEstimator {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: INITIAL_RTO,
+        }
+    }
+}
+
+impl RtoEstimator {
+    /// The current retransmission timeout.
+    #[inline]
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    /// The smoothed round-trip time, if at least one sample has been taken.
+    #[inline]
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// Feeds a round-trip time measurement into the estimator, RFC 6298 sections 2.2 and 2.3.
+    pub fn sample(&mut self, rtt: Duration) {
+        let srtt = match self.srtt {
+            None => {
+                self.rttvar = rtt / 2;
+                rtt
+            }
+            Some(srtt) => {
+                self.rttvar = (self.rttvar * 3 + srtt.abs_diff(rtt)) / 4;
+                (srtt * 7 + rtt) / 8
+            }
+        };
+
+        self.srtt = Some(srtt);
+        self.rto = (srtt + CLOCK_GRANULARITY.max(self.rttvar * 4)).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    /// Doubles the RTO after a timeout, RFC 6298 section 5.5.
+    pub fn backoff(&mut self) {
+        self.rto = (self.rto * 2).min(MAX_RTO);
+    }
+}
+
+/// A sent segment that has not been acknowledged yet.
+struct UnackedSegment {
+    /// Sequence number just past the segment
+    end: u32,
+    packet: TCPPacket,
+    sent_at: Instant,
+    retransmitted: bool,
+}
+
+/// Outcome of a retransmission timer expiry.
+pub enum Timeout<'a> {
+    /// The oldest segment has to be sent again.
+    Retransmit(&'a mut TCPPacket),
+    /// The oldest segment has been retransmitted `MAX_RETRANSMISSIONS` times without being acknowledged.
+    GiveUp,
+    /// Nothing is outstanding.
+    Idle,
+}
+
+/// Segments that occupy sequence space and are waiting for an acknowledgement, together with the single
+/// retransmission timer of RFC 6298.
+pub struct RetransmissionQueue {
+    segments: VecDeque<UnackedSegment>,
+    estimator: RtoEstimator,
+    deadline: Option<Instant>,
+    retries: u32,
+    notify: Arc<Notify>,
+}
+
+impl Default for RetransmissionQueue {
+    fn default() -> Self {
+        RetransmissionQueue {
+            segments: VecDeque::new(),
+            estimator: RtoEstimator::default(),
+            deadline: None,
+            retries: 0,
+            notify: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl RetransmissionQueue {
+    /// The notifier that wakes the timer task whenever the deadline changes.
+    #[inline]
+    pub fn notify(&self) -> Arc<Notify> {
+        self.notify.clone()
+    }
+
+    /// When the retransmission timer fires next, `None` if it is not running.
+    #[inline]
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    #[inline]
+    pub fn estimator(&self) -> &RtoEstimator {
+        &self.estimator
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Queues a freshly sent segment and starts the timer if it is not running, RFC 6298 section 5.1.
+    ///
+    /// # Arguments
+    ///
+    /// * `end` - The sequence number just past the segment
+    /// * `packet` - The packet as it was sent
+    pub fn push(&mut self, end: u32, packet: TCPPacket) {
+        let now = Instant::now();
+        self.segments.push_back(UnackedSegment {
+            end,
+            packet,
+            sent_at: now,
+            retransmitted: false,
+        });
+
+        if self.deadline.is_none() {
+            self.restart_timer(now);
+        }
+    }
+
+    /// Removes every segment covered by `ack` and takes an RTT sample from segments that were only sent once
+    /// (Karn's algorithm).
+    ///
+    /// # Arguments
+    ///
+    /// * `ack` - The new SND.UNA
+    pub fn acknowledge(&mut self, ack: u32) {
+        let now = Instant::now();
+        let mut sample = None;
+
+        while self.segments.front().is_some_and(|segment| seq_le(segment.end, ack)) {
+            let segment = self.segments.pop_front().unwrap();
+            if !segment.retransmitted {
+                sample = Some(now - segment.sent_at);
+            }
+        }
+
+        if let Some(rtt) = sample {
+            self.estimator.sample(rtt);
+        }
+
+        // RFC 6298 sections 5.2 and 5.3
+        self.retries = 0;
+        if self.segments.is_empty() {
+            self.deadline = None;
+        } else {
+            self.restart_timer(now);
+        }
+        self.notify.notify_one();
+    }
+
+    /// Handles an expiry of the retransmission timer, RFC 6298 sections 5.4 to 5.6.
+    pub fn on_timeout(&mut self) -> Timeout<'_> {
+        if self.segments.is_empty() {
+            self.deadline = None;
+            return Timeout::Idle;
+        }
+
+        if self.retries >= MAX_RETRANSMISSIONS {
+            self.deadline = None;
+            return Timeout::GiveUp;
+        }
+
+        self.retries += 1;
+        self.estimator.backoff();
+        self.restart_timer(Instant::now());
+
+        let segment = self.segments.front_mut().unwrap();
+        segment.retransmitted = true;
+        Timeout::Retransmit(&mut segment.packet)
+    }
+
+    /// Marks the oldest segment as retransmitted and returns it, for a fast retransmit.
+    /// The timer and the RTO are left alone.
+    pub fn retransmit_front(&mut self) -> Option<&mut TCPPacket> {
+        let segment = self.segments.front_mut()?;
+        segment.retransmitted = true;
+        Some(&mut segment.packet)
+    }
+
+    /// Drops every outstanding segment and stops the timer.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.deadline = None;
+        self.notify.notify_one();
+    }
+
+    fn restart_timer(&mut self, now: Instant) {
+        self.deadline = Some(now + self.estimator.rto());
+        self.notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn packet() -> TCPPacket {
+        TCPPacket::default(IpAddr::V4(Ipv4Addr::LOCALHOST), "127.0.0.1:80", Some("data"), 4000).unwrap()
+    }
+
+    #[test]
+    fn the_first_sample_sets_srtt_and_half_of_it_as_rttvar() {
+        let mut estimator = RtoEstimator::default();
+        assert_eq!((estimator.srtt(), estimator.rto()), (None, INITIAL_RTO));
+
+        estimator.sample(Duration::from_secs(2));
+        assert_eq!(estimator.srtt(), Some(Duration::from_secs(2)));
+        assert_eq!(estimator.rttvar, Duration::from_secs(1));
+        assert_eq!(estimator.rto(), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn later_samples_are_smoothed() {
+        let mut estimator = RtoEstimator::default();
+        estimator.sample(Duration::from_secs(2));
+
+        // RTTVAR = 3/4 * 1s + 1/4 * |2s - 1s|, SRTT = 7/8 * 2s + 1/8 * 1s
+        estimator.sample(Duration::from_secs(1));
+        assert_eq!(estimator.rttvar, Duration::from_secs(1));
+        assert_eq!(estimator.srtt(), Some(Duration::from_millis(1875)));
+        assert_eq!(estimator.rto(), Duration::from_millis(5875));
+
+        // The difference is taken the other way round for a sample above SRTT
+        estimator.sample(Duration::from_secs(3));
+        assert_eq!(estimator.rttvar, Duration::from_micros(1_031_250));
+        assert_eq!(estimator.srtt(), Some(Duration::from_micros(2_015_625)));
+    }
+
+    #[test]
+    fn the_rto_stays_within_its_bounds() {
+        let mut estimator = RtoEstimator::default();
+        estimator.sample(Duration::from_millis(10));
+        assert_eq!(estimator.rto(), MIN_RTO);
+
+        estimator = RtoEstimator::default();
+        estimator.sample(Duration::from_secs(30));
+        assert_eq!(estimator.rto(), MAX_RTO);
+    }
+
+    #[test]
+    fn backoff_doubles_the_rto_up_to_its_cap() {
+        let mut estimator = RtoEstimator::default();
+        estimator.backoff();
+        assert_eq!(estimator.rto(), Duration::from_secs(2));
+        estimator.backoff();
+        assert_eq!(estimator.rto(), Duration::from_secs(4));
+
+        for _ in 0..10 {
+            estimator.backoff();
+        }
+        assert_eq!(estimator.rto(), MAX_RTO);
+    }
+
+    #[test]
+    fn retransmitted_segments_are_not_sampled() {
+        let mut queue = RetransmissionQueue::default();
+        queue.push(100, packet());
+        queue.push(200, packet());
+
+        queue.retransmit_front();
+        queue.acknowledge(100);
+        assert_eq!(queue.estimator().srtt(), None);
+
+        queue.acknowledge(200);
+        assert!(queue.estimator().srtt().is_some());
+        assert!(queue.is_empty());
+        assert_eq!(queue.deadline(), None);
+    }
+
+    #[test]
+    fn a_timeout_retransmits_backs_off_and_eventually_gives_up() {
+        let mut queue = RetransmissionQueue::default();
+        assert!(matches!(queue.on_timeout(), Timeout::Idle));
+
+        queue.push(100, packet());
+        for retry in 1..=MAX_RETRANSMISSIONS {
+            assert!(matches!(queue.on_timeout(), Timeout::Retransmit(_)));
+            assert_eq!(queue.estimator().rto(), (INITIAL_RTO * 2u32.pow(retry)).min(MAX_RTO));
+        }
+        assert!(matches!(queue.on_timeout(), Timeout::GiveUp));
+        assert_eq!(queue.deadline(), None);
+
+        // A timed out segment does not give an RTT sample either
+        queue.acknowledge(100);
+        assert_eq!(queue.estimator().srtt(), None);
+    }
+}
@@ -0,0 +1,99 @@
+use std::task::Waker;
+
+use bytes::{Bytes, BytesMut};
+
+/// How many unsent bytes a stream may queue before its writes wait.
+pub const SEND_BUFFER_LIMIT: usize = 64 * 1024;
+
+/// Data written by the application that has not been sent yet, plus a pending close.
+#[derive(Debug, Default)]
+pub struct SendBuffer {
+    pending: BytesMut,
+    fin_requested: bool,
+    /// The FIN has been handed to the connection, nothing more is sent
+    fin_sent: bool,
+    /// A writer waiting for room in the buffer
+    waker: Option<Waker>,
+}
+
+impl SendBuffer {
+    /// Appends data to the end of the stream.
+    pub fn push(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+    }
+
+    /// Takes up to `size` bytes from the front of the buffer.
+    pub fn take(&mut self, size: usize) -> Bytes {
+        let size = size.min(self.pending.len());
+        self.wake();
+        self.pending.split_to(size).freeze()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Asks for a FIN to be sent once every buffered byte is out.
+    pub fn close(&mut self) {
+        self.fin_requested = true;
+    }
+
+    /// Whether `close` was called, the buffer takes no more data from then on, also once the FIN was sent.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.fin_requested
+    }
+
+    /// Returns `true` exactly once after `close`, when the buffer has drained and the FIN may be sent.
+    pub fn take_fin(&mut self) -> bool {
+        if self.fin_requested && !self.fin_sent && self.pending.is_empty() {
+            self.fin_sent = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Remembers the task to wake up once there is room in the buffer.
+    pub fn register(&mut self, waker: &Waker) {
+        self.waker = Some(waker.clone());
+    }
+
+    /// Wakes up the waiting writer, if any.
+    pub fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_the_fin_once_after_the_data() {
+        let mut buffer = SendBuffer::default();
+        buffer.push(b"last words");
+        buffer.close();
+        assert!(!buffer.take_fin(), "the data goes first");
+
+        assert_eq!(&buffer.take(64)[..], b"last words");
+        assert!(buffer.take_fin());
+        assert!(!buffer.take_fin());
+    }
+
+    #[test]
+    fn stays_closed_once_the_fin_was_sent() {
+        let mut buffer = SendBuffer::default();
+        buffer.close();
+        assert!(buffer.take_fin());
+        assert!(buffer.is_closed());
+    }
+}
@@ -0,0 +1,319 @@
+use crate::tcp::packet::options::{DEFAULT_REMOTE_MSS, MAX_WINDOW_SCALE, TcpOption};
+
+/// The window we advertise to the remote.
+pub const DEFAULT_RECEIVE_WINDOW: u16 = 65495;
+
+/// Transmission Control Block, the send and receive sequence variables of RFC 793, section 3.2.
+/// All numbers are kept in host byte order.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TransmissionControlBlock {
+    /// Initial send sequence number
+    pub iss: u32,
+    /// Oldest unacknowledged sequence number
+    pub snd_una: u32,
+    /// Next sequence number to be sent
+    pub snd_nxt: u32,
+    /// Window advertised by the remote, already scaled
+    pub snd_wnd: u32,
+    /// Sequence number of the segment the window was last taken from
+    pub snd_wl1: u32,
+    /// Acknowledgement number of the segment the window was last taken from
+    pub snd_wl2: u32,
+    /// Largest segment the remote accepts
+    pub snd_mss: u16,
+    /// Shift applied to windows advertised by the remote
+    pub snd_wscale: u8,
+
+    /// Initial receive sequence number
+    pub irs: u32,
+    /// Next sequence number expected from the remote
+    pub rcv_nxt: u32,
+    /// Window we advertise to the remote
+    pub rcv_wnd: u16,
+}
+
+impl TransmissionControlBlock {
+    /// Creates a TCB for a connection that will send its SYN with `iss`.
+    pub fn new(iss: u32) -> Self {
+        TransmissionControlBlock {
+            iss,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_mss: DEFAULT_REMOTE_MSS,
+            rcv_wnd: DEFAULT_RECEIVE_WINDOW,
+            ..Default::default()
+        }
+    }
+
+    /// Records the remote's SYN, after which `rcv_nxt` points just past it.
+    ///
+    /// The window of the SYN itself, and of any later segment, is taken by `update_window`.
+    pub fn synchronize(&mut self, irs: u32) {
+        self.irs = irs;
+        self.rcv_nxt = irs.wrapping_add(1);
+        self.snd_wl1 = irs;
+        self.snd_wl2 = self.iss;
+    }
+
+    /// Applies the options of the remote's SYN.
+    ///
+    /// Window scaling is only in effect when both SYNs carried the option, ours always does.
+    pub fn negotiate(&mut self, options: &[TcpOption]) {
+        self.snd_wscale = 0;
+        for option in options {
+            match option {
+                TcpOption::MaximumSegmentSize(mss) => self.snd_mss = *mss,
+                TcpOption::WindowScale(shift) => self.snd_wscale = (*shift).min(MAX_WINDOW_SCALE),
+                _ => {}
+            }
+        }
+    }
+
+    /// Scales the window of a segment, windows in SYN segments are never scaled.
+    #[inline]
+    pub fn scale_window(&self, window: u16, syn: bool) -> u32 {
+        if syn { window as u32 } else { (window as u32) << self.snd_wscale }
+    }
+
+    /// Processes an acknowledgement number from the remote.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the ACK acknowledged new data
+    pub fn acknowledge(&mut self, ack: u32) -> bool {
+        if self.acknowledges_new_data(ack) {
+            self.snd_una = ack;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes the remote's window from an acceptable ACK, unless it was sent before the segment the window was last
+    /// taken from, RFC 9293 section 3.10.7.4. A reordered older segment neither shrinks nor reopens the window.
+    ///
+    /// # Arguments
+    ///
+    /// * `seq` - The sequence number of the segment
+    /// * `ack` - The acknowledgement number of the segment
+    /// * `window` - The window of the segment, already scaled
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the window was taken
+    pub fn update_window(&mut self, seq: u32, ack: u32, window: u32) -> bool {
+        if seq_lt(self.snd_wl1, seq) || (self.snd_wl1 == seq && seq_le(self.snd_wl2, ack)) {
+            self.snd_wnd = window;
+            self.snd_wl1 = seq;
+            self.snd_wl2 = ack;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the acknowledgement number satisfies SND.UNA < ack <= SND.NXT.
+    #[inline]
+    pub fn acknowledges_new_data(&self, ack: u32) -> bool {
+        seq_lt(self.snd_una, ack) && seq_le(ack, self.snd_nxt)
+    }
+
+    /// Whether the acknowledgement number satisfies ISS < ack <= SND.NXT, the test of segments received before the
+    /// handshake completed. Unlike `acknowledges_new_data` it still holds once SND.UNA moved past our SYN.
+    #[inline]
+    pub fn acknowledges_syn(&self, ack: u32) -> bool {
+        seq_lt(self.iss, ack) && seq_le(ack, self.snd_nxt)
+    }
+
+    /// Whether the remote acknowledged our SYN, so both ends are synchronized.
+    #[inline]
+    pub fn is_syn_acknowledged(&self) -> bool {
+        seq_lt(self.iss, self.snd_una)
+    }
+
+    /// Whether the acknowledgement number refers to something we never sent.
+    #[inline]
+    pub fn is_ack_too_new(&self, ack: u32) -> bool {
+        seq_lt(self.snd_nxt, ack)
+    }
+
+    /// Bytes sent but not acknowledged yet: SND.NXT - SND.UNA.
+    #[inline]
+    pub fn flight_size(&self) -> u32 {
+        self.snd_nxt.wrapping_sub(self.snd_una)
+    }
+
+    /// How many more bytes the remote's window allows us to send: SND.UNA + SND.WND - SND.NXT.
+    pub fn usable_window(&self) -> u32 {
+        let window_end = self.snd_una.wrapping_add(self.snd_wnd);
+        if seq_lt(self.snd_nxt, window_end) {
+            window_end.wrapping_sub(self.snd_nxt)
+        } else {
+            0
+        }
+    }
+
+    /// Whether everything we sent, including a FIN, has been acknowledged.
+    #[inline]
+    pub fn all_acknowledged(&self) -> bool {
+        self.snd_una == self.snd_nxt
+    }
+
+    /// The segment acceptability test of RFC 793, page 69.
+    ///
+    /// # Arguments
+    ///
+    /// * `seq` - The first sequence number of the segment
+    /// * `len` - The sequence space the segment occupies, including SYN and FIN
+    pub fn is_acceptable(&self, seq: u32, len: u32) -> bool {
+        let window = self.rcv_wnd as u32;
+        let in_window = |n: u32| seq_le(self.rcv_nxt, n) && seq_lt(n, self.rcv_nxt.wrapping_add(window));
+
+        match (len, window) {
+            (0, 0) => seq == self.rcv_nxt,
+            (0, _) => in_window(seq),
+            (_, 0) => false,
+            (_, _) => in_window(seq) || in_window(seq.wrapping_add(len - 1)),
+        }
+    }
+
+    /// A TCB expecting `rcv_nxt` next with a receive window of `window` bytes.
+    #[cfg(test)]
+    pub(crate) fn expecting(rcv_nxt: u32, window: u16) -> Self {
+        let mut tcb = Self::new(100);
+        tcb.snd_nxt = 101;
+        tcb.synchronize(rcv_nxt.wrapping_sub(1));
+        tcb.rcv_wnd = window;
+        tcb
+    }
+}
+
+/// `a < b` in sequence number space.
+#[inline]
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// `a <= b` in sequence number space.
+#[inline]
+pub fn seq_le(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A TCB synchronized with a remote whose initial sequence number is `irs`.
+    fn synchronized(iss: u32, irs: u32) -> TransmissionControlBlock {
+        let mut tcb = TransmissionControlBlock::new(iss);
+        tcb.snd_nxt = iss.wrapping_add(1);
+        tcb.synchronize(irs);
+        tcb
+    }
+
+    #[test]
+    fn takes_the_window_of_the_syn_ack() {
+        let mut tcb = synchronized(100, 5000);
+        assert!(tcb.update_window(5000, 101, 1000));
+        assert_eq!((tcb.snd_wnd, tcb.snd_wl1, tcb.snd_wl2), (1000, 5000, 101));
+    }
+
+    #[test]
+    fn ignores_the_window_of_a_reordered_older_segment() {
+        let mut tcb = synchronized(100, 5000);
+        assert!(tcb.update_window(5001, 101, 1000));
+        assert!(tcb.update_window(5101, 101, 0));
+
+        // Sent before the zero window, it arrives after it
+        assert!(!tcb.update_window(5001, 101, 1000));
+        assert_eq!(tcb.snd_wnd, 0);
+
+        // The same sequence number with an older acknowledgement is older as well
+        assert!(tcb.update_window(5101, 301, 500));
+        assert!(!tcb.update_window(5101, 201, 4000));
+        assert_eq!(tcb.snd_wnd, 500);
+    }
+
+    #[test]
+    fn takes_the_window_of_a_pure_ack_with_a_newer_acknowledgement() {
+        let mut tcb = synchronized(100, 5000);
+        assert!(tcb.update_window(5001, 101, 0));
+        // A window update repeats the sequence number and the acknowledgement
+        assert!(tcb.update_window(5001, 101, 2000));
+        assert!(tcb.update_window(5001, 601, 1500));
+        assert_eq!((tcb.snd_wnd, tcb.snd_wl2), (1500, 601));
+    }
+
+    #[test]
+    fn orders_window_updates_across_the_wraparound() {
+        let mut tcb = synchronized(100, u32::MAX - 10);
+        assert!(tcb.update_window(u32::MAX - 9, 101, 1000));
+        assert!(tcb.update_window(5, 101, 0));
+        assert!(!tcb.update_window(u32::MAX - 9, 101, 1000));
+        assert_eq!(tcb.snd_wnd, 0);
+    }
+
+    #[test]
+    fn compares_sequence_numbers_across_the_wraparound() {
+        assert!(seq_lt(1, 2));
+        assert!(!seq_lt(2, 1));
+        assert!(!seq_lt(7, 7));
+        assert!(seq_le(7, 7));
+
+        assert!(seq_lt(u32::MAX, 0));
+        assert!(seq_lt(u32::MAX - 10, 10));
+        assert!(!seq_lt(10, u32::MAX - 10));
+        assert!(seq_le(u32::MAX, 0));
+        assert!(!seq_le(0, u32::MAX));
+
+        // Half the sequence space ahead still counts as later
+        assert!(seq_lt(0, i32::MAX as u32));
+        assert!(!seq_lt(0, i32::MAX as u32 + 2));
+    }
+
+    #[test]
+    fn accepts_an_empty_segment_into_a_zero_window_only_at_rcv_nxt() {
+        let tcb = TransmissionControlBlock::expecting(1000, 0);
+        assert!(tcb.is_acceptable(1000, 0));
+        assert!(!tcb.is_acceptable(999, 0));
+        assert!(!tcb.is_acceptable(1001, 0));
+    }
+
+    #[test]
+    fn accepts_an_empty_segment_inside_an_open_window() {
+        let tcb = TransmissionControlBlock::expecting(1000, 100);
+        assert!(tcb.is_acceptable(1000, 0));
+        assert!(tcb.is_acceptable(1099, 0));
+        assert!(!tcb.is_acceptable(999, 0));
+        assert!(!tcb.is_acceptable(1100, 0));
+    }
+
+    #[test]
+    fn never_accepts_data_into_a_zero_window() {
+        let tcb = TransmissionControlBlock::expecting(1000, 0);
+        assert!(!tcb.is_acceptable(1000, 1));
+        assert!(!tcb.is_acceptable(999, 10));
+    }
+
+    #[test]
+    fn accepts_data_that_overlaps_an_open_window() {
+        let tcb = TransmissionControlBlock::expecting(1000, 100);
+        assert!(tcb.is_acceptable(1000, 100));
+        // Only the beginning or only the end inside the window is enough
+        assert!(tcb.is_acceptable(1050, 100));
+        assert!(tcb.is_acceptable(990, 11));
+        assert!(!tcb.is_acceptable(990, 10));
+        assert!(!tcb.is_acceptable(1100, 1));
+    }
+
+    #[test]
+    fn accepts_segments_in_a_window_across_the_wraparound() {
+        let tcb = TransmissionControlBlock::expecting(u32::MAX - 9, 100);
+        assert!(tcb.is_acceptable(u32::MAX, 0));
+        assert!(tcb.is_acceptable(50, 10));
+        assert!(tcb.is_acceptable(u32::MAX - 14, 10));
+        assert!(!tcb.is_acceptable(90, 1));
+        assert!(!tcb.is_acceptable(u32::MAX - 20, 10));
+    }
+}
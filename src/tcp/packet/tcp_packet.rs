@@ -1,18 +1,27 @@
-use std::convert::Infallible;
-use std::ffi::{c_void, CString};
+use std::ffi::c_void;
 use std::fmt::{Display, Formatter};
 use std::mem::size_of;
+use std::net::{AddrParseError, IpAddr};
+
+use bytes::Bytes;
 
 use crate::raw_bindings::raw_bindings::{iphdr, tcphdr};
-use crate::tcp::packet::data::PseudoHeader;
+use crate::tcp::packet::checksum::{checksum, Checksum};
+use crate::tcp::packet::ip_header::IpHeader;
+use crate::tcp::packet::options::{encode_options, TcpOption};
 use crate::tcp::util::{ChangingOrderSizes, ToAddress};
 
+#[derive(Clone)]
 pub struct TCPPacket {
-    pub(crate) ip_head: iphdr,
+    pub(crate) ip_head: IpHeader,
     pub(crate) tcp_head: tcphdr,
+    pub(crate) options: Vec<u8>,
 
-    pub(crate) data: CString,
-    pub(crate) data_vec: Vec<u8>
+    pub(crate) data: Bytes,
+    pub(crate) data_vec: Vec<u8>,
+    /// Whether the TCP checksum in the header matches the packet. `restamp` keeps it up to date, anything else that
+    /// changes the packet clears it, and the checksum is computed again when the packet is sent.
+    pub(crate) checksum_valid: bool,
 }
 
 impl Display for TCPPacket {
@@ -26,40 +35,40 @@ impl Display for TCPPacket {
     }
 }
 
-const LINE_BREAK: &[u8] = &[10];// \n
-
 impl TCPPacket {
-    pub fn default<A, T>(destination_address: A, data: Option<T>, source_port: u16) -> Result<TCPPacket, String>
+    pub fn default<A, T>(source_address: IpAddr, destination_address: A, data: Option<T>, source_port: u16) -> Result<TCPPacket, String>
     where A: ToAddress,
-          T: Into<Vec<u8>>,
+          T: Into<Bytes>,
     {
         let (port, addr) = destination_address.to_address().ok_or("Invalid address")?;
+        let addr: IpAddr = addr.parse().map_err(|e: AddrParseError| e.to_string())?;
 
-        let data = match data {
-            None => { CString::default() }
-            Some(data) => {
-                let mut data: Vec<u8> = data.try_into().map_err(|e: Infallible| e.to_string())?;
-                data.extend_from_slice(LINE_BREAK);
-                CString::new(data).map_err(|e| e.to_string())?
-            }
-        };
+        // The payload is sent exactly as given, whatever bytes it holds
+        let data = data.map_or_else(Bytes::new, Into::into);
+        let data_len = data.len();
 
-        let data_len = data.count_bytes();
+        let ip_head = IpHeader::new(source_address, addr, size_of::<tcphdr>() + data_len)?;
 
         Ok(TCPPacket {
-            ip_head: iphdr::default(data_len, "127.0.0.1", addr),
+            ip_head,
             tcp_head: tcphdr::default(source_port, port),
+            options: Vec::new(),
             data,
-            data_vec: Vec::with_capacity(size_of::<iphdr>() + size_of::<tcphdr>() + data_len)
+            data_vec: Vec::with_capacity(ip_head.len() + size_of::<tcphdr>() + data_len),
+            checksum_valid: false,
         })
     }
 
     #[inline]
     pub fn as_ptr(&mut self) -> *const c_void {
-        self.tcp_check();
-        self.calculate_data();
-        self.ip_check();
+        if !self.checksum_valid {
+            self.tcp_check();
+        }
         self.calculate_data();
+        if let IpHeader::V4(_) = self.ip_head {
+            self.ip_check();
+            self.calculate_data();
+        }
         self.data_vec.as_ptr() as *const c_void
     }
 
@@ -67,10 +76,13 @@ impl TCPPacket {
         let mut offset = 0;
         self.data_vec.resize(self.len(), 0);
 
-        unsafe {
-            let ip = &self.ip_head as *const iphdr as *const u8;
-            std::ptr::copy(ip, self.data_vec.as_mut_ptr().offset(offset), size_of::<iphdr>());
-            offset += size_of::<iphdr>() as isize;
+        // IPv6 packets start with the TCP header, the kernel adds the IP header
+        if let IpHeader::V4(ip_head) = &self.ip_head {
+            unsafe {
+                let ip = ip_head as *const iphdr as *const u8;
+                std::ptr::copy(ip, self.data_vec.as_mut_ptr().offset(offset), size_of::<iphdr>());
+                offset += size_of::<iphdr>() as isize;
+            }
         }
 
         unsafe {
@@ -80,7 +92,12 @@ impl TCPPacket {
         }
 
         unsafe {
-            std::ptr::copy(self.data.as_ptr() as *const u8, self.data_vec.as_mut_ptr().offset(offset), self.data.count_bytes());
+            std::ptr::copy(self.options.as_ptr(), self.data_vec.as_mut_ptr().offset(offset), self.options.len());
+            offset += self.options.len() as isize;
+        }
+
+        unsafe {
+            std::ptr::copy(self.data.as_ptr(), self.data_vec.as_mut_ptr().offset(offset), self.data.len());
         }
     }
 
@@ -89,87 +106,81 @@ impl TCPPacket {
     pub fn tcp_check(&mut self) {
         unsafe {
             self.tcp_head.__bindgen_anon_1.__bindgen_anon_2.check = 0;
-            self.tcp_head.__bindgen_anon_1.__bindgen_anon_2.check = self.get_tcp_check();
+            self.tcp_head.__bindgen_anon_1.__bindgen_anon_2.check = self.get_tcp_check().to_network();
         }
+        self.checksum_valid = true;
     }
 
     #[inline]
     pub fn ip_check(&mut self) {
-        self.ip_head.check = 0;
-        self.ip_head.check = Self::checksum(self.data_vec.as_ptr(), self.len());
+        if let IpHeader::V4(ip_head) = &mut self.ip_head {
+            ip_head.check = 0;
+            let header = unsafe { std::slice::from_raw_parts(ip_head as *const iphdr as *const u8, size_of::<iphdr>()) };
+            ip_head.check = checksum(header).to_network();
+        }
     }
 
     #[inline]
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
-        size_of::<iphdr>() + size_of::<tcphdr>() + self.data.count_bytes()
+        self.ip_head.len() + self.tcp_len()
     }
 
-    #[allow(dead_code)]
-    pub fn change_data<T: Into<Vec<u8>>>(&mut self, data: T) -> Result<(), String> {
-        self.data = CString::new(data).map_err(|e| e.to_string())?;
-        Ok(())
+    /// Length of the TCP segment: header, options and data.
+    #[inline]
+    pub fn tcp_len(&self) -> usize {
+        size_of::<tcphdr>() + self.options.len() + self.data.len()
     }
 
-    fn get_tcp_check(&mut self) -> u16 {
-        let pseudo_header = PseudoHeader {
-            source_address: self.ip_head.saddr,
-            dest_address: self.ip_head.daddr,
-            placeholder: 0,
-            protocol: self.ip_head.protocol,
-            tcp_length: ((size_of::<tcphdr>() + self.data.count_bytes())as u16).to_network()
-        };
-
-        let vec = unsafe {
-            let len = size_of::<PseudoHeader>() + size_of::<tcphdr>() + self.data.count_bytes();
-            let mut vec: Vec<u8> = Vec::with_capacity(len);
-            vec.resize(len, 0);
-
-            let mut offset = 0;
-
-            //第一部分：伪头
-            std::ptr::copy(
-                &pseudo_header as *const PseudoHeader as *const u8,
-                vec.as_mut_ptr().offset(offset),
-                size_of::<PseudoHeader>()
-            );
-            offset += size_of::<PseudoHeader>() as isize;
-
-            //第二部分：TCP头
-            std::ptr::copy(
-                &self.tcp_head as *const tcphdr as *const u8,
-                vec.as_mut_ptr().offset(offset),
-                size_of::<tcphdr>()
-            );
-            offset += size_of::<tcphdr>() as isize;
-
-            //第三部分：数据部
-            std::ptr::copy(self.data.as_ptr() as *const u8, vec.as_mut_ptr().offset(offset), self.data.count_bytes());
+    /// Sets the options carried by the packet, updating the data offset and the IP total length.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The options to carry, padded to a multiple of four bytes
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if the options do not fit into the header
+    pub fn set_options(&mut self, options: &[TcpOption]) -> Result<(), String> {
+        self.options = encode_options(options)?;
+        unsafe {
+            let doff = (size_of::<tcphdr>() + self.options.len()) / 4;
+            self.tcp_head.__bindgen_anon_1.__bindgen_anon_2.set_doff(doff as u16);
+        }
+        self.ip_head.set_tcp_len(self.tcp_len());
+        self.checksum_valid = false;
+        Ok(())
+    }
 
-            vec
+    /// The sequence space the packet occupies: its data plus one for each of SYN and FIN.
+    #[inline]
+    pub fn sequence_length(&self) -> u32 {
+        let flags = unsafe {
+            let tcp_head = &self.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
+            (tcp_head.syn() + tcp_head.fin()) as u32
         };
-
-        Self::checksum(vec.as_ptr(), vec.len())
+        self.data.len() as u32 + flags
     }
 
-    fn checksum(buffer: *const u8, len: usize) -> u16 {
-        let mut sum = 0u32;
-        let mut i = 0;
-
-        unsafe {
-            while i < len {
-                // 将字节组合成16位整数
-                let word = ((*buffer.add(i + 1) as u32) << 8) | (*buffer.add(i)) as u32;
-                sum = sum + word;
-                i = i + 2;
-            }
-        }
+    #[allow(dead_code)]
+    pub fn change_data<T: Into<Bytes>>(&mut self, data: T) {
+        self.data = data.into();
+        self.ip_head.set_tcp_len(self.tcp_len());
+        self.checksum_valid = false;
+    }
 
-        // 将溢出加回到低16位
-        while (sum >> 16) != 0 {
-            sum = (sum & 0xffff) + (sum >> 16);
-        }
+    /// The TCP checksum over the pseudo-header, the header with its options and the data, in host byte order.
+    fn get_tcp_check(&self) -> u16 {
+        let pseudo_header = self.ip_head.pseudo_header(self.tcp_len());
+        let tcp_head = unsafe {
+            std::slice::from_raw_parts(&self.tcp_head as *const tcphdr as *const u8, size_of::<tcphdr>())
+        };
 
-        // 取反得到校验和
-        return (!sum as u16) & 0xffff;
+        Checksum::new()
+            .add(&pseudo_header)
+            .add(tcp_head)
+            .add(&self.options)
+            .add(&self.data)
+            .finish()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,228 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+
+use crate::tcp::packet::data::{CloseReason, Controller, ReceiveData, TcpState};
+
+/// Name of the processor completing the three-way handshake.
+pub const HANDSHAKE_PROCESSOR: &str = "handshake";
+/// Name of the processor logging every received segment.
+pub const PRINTER_PROCESSOR: &str = "printer";
+/// Name of the processor acknowledging received data.
+pub const DATA_PROCESSOR: &str = "data";
+/// Name of the processor driving the wave handshake.
+pub const WAVE_PROCESSOR: &str = "wave";
+
+/// Something that happened to a connection, handed to every processor in the order it happened.
+///
+/// A connection publishes events once it is registered in a table, one accepted by a listener is registered after it
+/// answered the SYN, so its processors see it move on from SYN-RECEIVED.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// The connection moved to another state
+    StateChanged { from: TcpState, to: TcpState },
+    /// The connection reached CLOSED, it is the last event of the connection
+    Closed(CloseReason),
+}
+
+/// Responds to what a connection receives.
+///
+/// Every connection runs its processors one after another, in the order of their priority, for each segment it
+/// accepts and each event it sees. A processor holding up its hook holds up the connection's others too, so a
+/// processor with slow work to do should spawn it.
+#[async_trait]
+pub trait PacketProcessor: Send + Sync {
+    /// The states segments are handed to the processor in, checked right before it runs; empty means every state.
+    fn states(&self) -> &[TcpState] {
+        &[]
+    }
+
+    /// Handles a segment the connection accepted, after it was run through the state machine.
+    ///
+    /// # Arguments
+    ///
+    /// * `controller` - The connection
+    /// * `segment` - The segment
+    async fn on_segment(&self, controller: &Controller, segment: &ReceiveData);
+
+    /// Handles an event of the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `controller` - The connection
+    /// * `event` - The event
+    async fn on_event(&self, _controller: &Controller, _event: &ConnectionEvent) {}
+}
+
+/// The method of the connection a built-in processor runs.
+type Handler = fn(&Controller, &ReceiveData);
+
+/// A processor running a method of the connection, the processors every connection starts with.
+struct BuiltinProcessor {
+    states: &'static [TcpState],
+    handle: Handler,
+}
+
+#[async_trait]
+impl PacketProcessor for BuiltinProcessor {
+    fn states(&self) -> &[TcpState] {
+        self.states
+    }
+
+    async fn on_segment(&self, controller: &Controller, segment: &ReceiveData) {
+        (self.handle)(controller, segment)
+    }
+}
+
+/// A processor in a registry.
+#[derive(Clone)]
+struct Entry {
+    name: String,
+    priority: i32,
+    enabled: bool,
+    processor: Arc<dyn PacketProcessor>,
+}
+
+/// The processors of a connection, which can be changed while it runs.
+///
+/// Processors run from the highest priority to the lowest, those with the same priority in the order they were
+/// added. A change applies from the next segment or event on.
+#[derive(Default)]
+pub struct ProcessorRegistry {
+    entries: RwLock<Vec<Entry>>,
+}
+
+impl ProcessorRegistry {
+    /// A registry with the processors every connection starts with, highest priority first:
+    /// `PRINTER_PROCESSOR` (400), `HANDSHAKE_PROCESSOR` (300), `DATA_PROCESSOR` (200) and `WAVE_PROCESSOR` (100).
+    pub fn builtin() -> Self {
+        let registry = ProcessorRegistry::default();
+        let builtins: [(&str, i32, &'static [TcpState], Handler); 4] = [
+            (PRINTER_PROCESSOR, 400, &[], Controller::packet_printer),
+            (HANDSHAKE_PROCESSOR, 300, &[TcpState::SynSent, TcpState::SynReceived], Controller::third_handshake_listener),
+            (DATA_PROCESSOR, 200, &[TcpState::SynReceived, TcpState::Established, TcpState::FinWait1, TcpState::FinWait2], Controller::data_listener),
+            (WAVE_PROCESSOR, 100, &[
+                TcpState::Established,
+                TcpState::FinWait1,
+                TcpState::FinWait2,
+                TcpState::Closing,
+                TcpState::LastAck,
+                TcpState::TimeWait
+            ], Controller::wave_handshake_listener),
+        ];
+        for (name, priority, states, handle) in builtins {
+            registry.insert(name, priority, Arc::new(BuiltinProcessor { states, handle }));
+        }
+        registry
+    }
+
+    /// Adds a processor, enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the processor is changed by later
+    /// * `priority` - Where the processor runs, higher runs earlier
+    /// * `processor` - The processor, it may be shared by several connections
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if a processor of the name was already added
+    pub fn add(&self, name: &str, priority: i32, processor: Arc<dyn PacketProcessor>) -> Result<(), String> {
+        if self.entries.read().iter().any(|entry| entry.name == name) {
+            return Err(format!("Processor {} already exists", name));
+        }
+        self.insert(name, priority, processor);
+        Ok(())
+    }
+
+    /// Removes a processor.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Arc<dyn PacketProcessor>>` - The processor, None if there is none of the name
+    pub fn remove(&self, name: &str) -> Option<Arc<dyn PacketProcessor>> {
+        let mut entries = self.entries.write();
+        let index = entries.iter().position(|entry| entry.name == name)?;
+        Some(entries.remove(index).processor)
+    }
+
+    /// Lets a processor run again. Returns false if there is none of the name.
+    pub fn enable(&self, name: &str) -> bool {
+        self.update(name, |entry| entry.enabled = true)
+    }
+
+    /// Keeps a processor from running without removing it. Returns false if there is none of the name.
+    pub fn disable(&self, name: &str) -> bool {
+        self.update(name, |entry| entry.enabled = false)
+    }
+
+    /// Changes the priority of a processor, it runs after the others of the new priority. Returns false if there is
+    /// none of the name.
+    pub fn prioritize(&self, name: &str, priority: i32) -> bool {
+        let mut entries = self.entries.write();
+        let Some(index) = entries.iter().position(|entry| entry.name == name) else {
+            return false;
+        };
+        let mut entry = entries.remove(index);
+        entry.priority = priority;
+        Self::place(&mut entries, entry);
+        true
+    }
+
+    /// Whether a processor of the name runs, None if there is none.
+    pub fn is_enabled(&self, name: &str) -> Option<bool> {
+        self.entries.read().iter().find(|entry| entry.name == name).map(|entry| entry.enabled)
+    }
+
+    /// The names of the processors, in the order they run, disabled ones included.
+    pub fn names(&self) -> Vec<String> {
+        self.entries.read().iter().map(|entry| entry.name.clone()).collect()
+    }
+
+    /// Adds every processor of another registry, as enabled or disabled as it is there.
+    ///
+    /// # Remarks
+    ///
+    /// A processor replaces the one of the same name, so the processors every connection starts with can be
+    /// exchanged.
+    pub fn extend(&self, other: &ProcessorRegistry) {
+        let others = other.entries.read().clone();
+        let mut entries = self.entries.write();
+        for entry in others {
+            entries.retain(|existing| existing.name != entry.name);
+            Self::place(&mut entries, entry);
+        }
+    }
+
+    /// The processors that run, in the order they run.
+    pub(crate) fn enabled(&self) -> Vec<Arc<dyn PacketProcessor>> {
+        self.entries.read().iter().filter(|entry| entry.enabled).map(|entry| entry.processor.clone()).collect()
+    }
+
+    fn insert(&self, name: &str, priority: i32, processor: Arc<dyn PacketProcessor>) {
+        let entry = Entry {
+            name: name.to_string(),
+            priority,
+            enabled: true,
+            processor,
+        };
+        Self::place(&mut self.entries.write(), entry);
+    }
+
+    fn update(&self, name: &str, change: impl FnOnce(&mut Entry)) -> bool {
+        match self.entries.write().iter_mut().find(|entry| entry.name == name) {
+            Some(entry) => {
+                change(entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Puts an entry behind every entry of the same or a higher priority, which keeps the entries in running order.
+    fn place(entries: &mut Vec<Entry>, entry: Entry) {
+        let index = entries.iter().position(|existing| existing.priority < entry.priority).unwrap_or(entries.len());
+        entries.insert(index, entry);
+    }
+}
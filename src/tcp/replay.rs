@@ -0,0 +1,349 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use colored::Colorize;
+use rand::{random, Rng};
+use tokio::sync::mpsc;
+use tokio::time::{Instant, timeout_at};
+use tracing::info;
+
+use crate::tcp::capture::{ACK, CapturedSegment, FIN, flag_names, PcapWriter, PSH, RST, SYN, URG};
+use crate::tcp::link::device::Link;
+use crate::tcp::main_loop::read_segment;
+use crate::tcp::packet::data::Controller;
+use crate::tcp::packet::options::TcpOption;
+use crate::tcp::settings::Settings;
+use crate::tcp::util::ChangingOrderSizes;
+
+/// How long a read of the replay link blocks before the loop checks whether the replay is over.
+const RECEIVE_POLL: Duration = Duration::from_millis(100);
+
+/// How a live session compared with the recorded one.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// Segments of our side that were sent again
+    pub sent: usize,
+    /// Segments the peer sent in the recorded session
+    pub expected: usize,
+    /// Segments the peer sent in the live session
+    pub received: usize,
+    /// Every way the live session differed from the recorded one
+    pub differences: Vec<String>,
+}
+
+impl ReplayReport {
+    #[inline]
+    pub fn is_identical(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// The sequence numbers of a session, recorded or live, that the others are taken relative to.
+#[derive(Debug, Clone, Copy)]
+struct InitialSequenceNumbers {
+    /// Our ISN
+    iss: u32,
+    /// The peer's ISN, known once its SYN arrived
+    irs: Option<u32>,
+}
+
+impl InitialSequenceNumbers {
+    /// The sequence number of a peer segment relative to the peer's ISN.
+    fn peer_seq(&self, segment: &CapturedSegment) -> u32 {
+        self.irs.map_or(segment.seq, |irs| segment.seq.wrapping_sub(irs))
+    }
+
+    /// The acknowledgement number of a peer segment relative to our ISN.
+    fn peer_ack(&self, segment: &CapturedSegment) -> u32 {
+        segment.ack.wrapping_sub(self.iss)
+    }
+}
+
+/// Plays our side of a recorded session against a live server again and compares what the server answers.
+///
+/// # Arguments
+///
+/// * `segments` - The recorded session, as read by `read_capture`. The first SYN without ACK tells our side from
+///   the peer's, segments of other connections are ignored
+/// * `link` - Where the live session runs, a link of no connection table as its packets are read here
+/// * `target` - The live server
+/// * `settings` - Where the segments are sent from
+/// * `response_timeout` - How long to wait for the answers to each segment
+/// * `capture` - Where the packets of the live session are recorded
+///
+/// # Returns
+///
+/// * `Result<ReplayReport, String>` - The differences between the sessions, or why the replay could not run
+///
+/// # Remarks
+///
+/// Our segments are sent from a new random port with a new ISN, their sequence and acknowledgement numbers are
+/// rewritten relative to the new ISN and to the ISN the live peer chose. The peer's segments are compared in order
+/// with the ones it sent after the same segment of ours: flags, relative sequence and acknowledgement numbers,
+/// window, options and payload. Timestamp values are not compared, answers with a wrong checksum are ignored.
+/// Over a raw socket the kernel answers the peer with a RST because it knows no socket on our port, which has to
+/// be filtered out for the replay to get further than the handshake, just as for any connection of this stack.
+pub async fn replay(
+    segments: &[CapturedSegment],
+    link: Arc<dyn Link>,
+    target: SocketAddr,
+    settings: Settings,
+    response_timeout: Duration,
+    capture: Option<Arc<PcapWriter>>,
+) -> Result<ReplayReport, String> {
+    let syn = segments.iter()
+        .position(|segment| segment.has(SYN) && !segment.has(ACK))
+        .ok_or("the capture holds no SYN to replay")?;
+    let (ours, peer) = (segments[syn].source, segments[syn].destination);
+    let recorded = InitialSequenceNumbers {
+        iss: segments[syn].seq,
+        irs: segments[syn..].iter()
+            .find(|segment| segment.source == peer && segment.destination == ours && segment.has(SYN))
+            .map(|segment| segment.seq),
+    };
+
+    // Every segment of ours, with the segments the peer sent before our next one
+    let mut steps: Vec<(&CapturedSegment, Vec<&CapturedSegment>)> = Vec::new();
+    for segment in &segments[syn..] {
+        if segment.source == ours && segment.destination == peer {
+            steps.push((segment, Vec::new()));
+        } else if segment.source == peer && segment.destination == ours {
+            if let Some((_, responses)) = steps.last_mut() {
+                responses.push(segment);
+            }
+        }
+    }
+
+    // The live session runs on a link of its own, its packets are built by a connection that is never registered
+    link.set_receive_timeout(RECEIVE_POLL)?;
+    let local_port = rand::thread_rng().gen_range(49152..=u16::MAX);
+    let mut controller = Controller::new(link.clone(), local_port, &target.ip().to_string(), target.port(), settings)?;
+    controller.capture = capture.clone();
+    let local = SocketAddr::new(controller.local_address, local_port);
+
+    info!(
+        "{}",
+        format!("Replaying {} segments of {} to {} against {} from {}", steps.len(), ours, peer, target, local).truecolor(200, 35, 55)
+    );
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    tokio::task::spawn_blocking(move || {
+        while !sender.is_closed() {
+            let Some(receive_data) = read_segment(link.as_ref()) else {
+                continue;
+            };
+
+            let segment = CapturedSegment::from(&receive_data);
+            if segment.source == target && segment.destination == local && receive_data.checksum_valid {
+                if let Some(capture) = &capture {
+                    capture.record(&receive_data.ip_head, &receive_data.raw);
+                }
+                let _ = sender.send(segment);
+            }
+        }
+    });
+
+    let mut live = InitialSequenceNumbers { iss: random(), irs: None };
+    let mut report = ReplayReport {
+        expected: steps.iter().map(|(_, responses)| responses.len()).sum(),
+        ..Default::default()
+    };
+
+    for (index, (segment, expected)) in steps.iter().enumerate() {
+        let seq = live.iss.wrapping_add(segment.seq.wrapping_sub(recorded.iss));
+        let ack = match (segment.has(ACK), live.irs, recorded.irs) {
+            (false, _, _) => segment.ack,
+            (true, Some(irs), Some(recorded_irs)) => irs.wrapping_add(segment.ack.wrapping_sub(recorded_irs)),
+            (true, _, _) => {
+                report.differences.push(format!(
+                    "segment {} ({}): the peer sent no SYN, the acknowledgement number can not be rewritten",
+                    index,
+                    flag_names(segment.flags)
+                ));
+                break;
+            }
+        };
+        send_segment(&controller, segment, seq, ack)?;
+        report.sent += 1;
+
+        // Wait for as many answers as were recorded, and take whatever else has arrived by then
+        let deadline = Instant::now() + response_timeout;
+        let mut responses = Vec::new();
+        while responses.len() < expected.len() {
+            match timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(response)) => responses.push(response),
+                _ => break,
+            }
+        }
+        while let Ok(response) = receiver.try_recv() {
+            responses.push(response);
+        }
+        report.received += responses.len();
+
+        if let Some(response) = responses.iter().find(|response| response.has(SYN)) {
+            live.irs = Some(response.seq);
+        }
+
+        // Answers are paired in order, preferring one with the same flags, as RSTs of the kernel may come in between
+        let mut unmatched: Vec<Option<CapturedSegment>> = responses.into_iter().map(Some).collect();
+        for recorded_response in expected {
+            let position = unmatched.iter()
+                .position(|response| response.as_ref().is_some_and(|response| response.flags == recorded_response.flags))
+                .or_else(|| unmatched.iter().position(Option::is_some));
+
+            match position.and_then(|position| unmatched[position].take()) {
+                Some(response) => {
+                    for difference in compare(recorded_response, &recorded, &response, &live) {
+                        report.differences.push(format!("answer to segment {} ({}): {}", index, flag_names(segment.flags), difference));
+                    }
+                }
+                None => report.differences.push(format!(
+                    "answer to segment {} ({}): expected {}, nothing was received",
+                    index,
+                    flag_names(segment.flags),
+                    describe(recorded_response, &recorded)
+                )),
+            }
+        }
+        for response in unmatched.into_iter().flatten() {
+            report.differences.push(format!("answer to segment {} ({}): unexpected {}", index, flag_names(segment.flags), describe(&response, &live)));
+        }
+    }
+
+    info!(
+        "{}",
+        format!("Replay finished, {} segments sent, {} of {} answers received", report.sent, report.received, report.expected)
+            .truecolor(200, 35, 55)
+    );
+
+    Ok(report)
+}
+
+/// Sends one of our recorded segments with new sequence and acknowledgement numbers.
+fn send_segment(controller: &Controller, segment: &CapturedSegment, seq: u32, ack: u32) -> Result<(), String> {
+    let mut packet = if segment.payload.is_empty() {
+        controller.make_packet_with_none()
+    } else {
+        controller.make_packet_with_data(segment.payload.clone())
+    };
+
+    unsafe {
+        let tcp_head = &mut packet.tcp_head.__bindgen_anon_1.__bindgen_anon_2;
+        tcp_head.seq = seq.to_network();
+        tcp_head.ack_seq = ack.to_network();
+        tcp_head.window = segment.window.to_network();
+        tcp_head.set_fin(segment.has(FIN) as u16);
+        tcp_head.set_syn(segment.has(SYN) as u16);
+        tcp_head.set_rst(segment.has(RST) as u16);
+        tcp_head.set_psh(segment.has(PSH) as u16);
+        tcp_head.set_ack(segment.has(ACK) as u16);
+        tcp_head.set_urg(segment.has(URG) as u16);
+    }
+    packet.set_options(&segment.options)?;
+
+    let sent_size = controller.transmit(&mut packet);
+    if sent_size < 0 {
+        return Err(format!("Can not send segment: {}", packet));
+    }
+    info!("replayed segment send: {}, with size: {}", packet, sent_size);
+    Ok(())
+}
+
+/// Lists how a live answer differs from the recorded one.
+fn compare(
+    recorded: &CapturedSegment,
+    recorded_numbers: &InitialSequenceNumbers,
+    live: &CapturedSegment,
+    live_numbers: &InitialSequenceNumbers,
+) -> Vec<String> {
+    let mut differences = Vec::new();
+
+    if recorded.flags != live.flags {
+        differences.push(format!("flags were {}, now {}", flag_names(recorded.flags), flag_names(live.flags)));
+    }
+    // A RST answering a SYN comes before any SYN of the peer, its sequence number is compared as it is
+    let (recorded_seq, live_seq) = (recorded_numbers.peer_seq(recorded), live_numbers.peer_seq(live));
+    if recorded_seq != live_seq {
+        differences.push(format!("relative sequence number was {}, now {}", recorded_seq, live_seq));
+    }
+    if recorded.has(ACK) && live.has(ACK) {
+        let (recorded_ack, live_ack) = (recorded_numbers.peer_ack(recorded), live_numbers.peer_ack(live));
+        if recorded_ack != live_ack {
+            differences.push(format!("relative acknowledgement number was {}, now {}", recorded_ack, live_ack));
+        }
+    }
+    if recorded.window != live.window {
+        differences.push(format!("window was {}, now {}", recorded.window, live.window));
+    }
+    if comparable_options(&recorded.options) != comparable_options(&live.options) {
+        differences.push(format!("options were {:?}, now {:?}", recorded.options, live.options));
+    }
+    if recorded.payload != live.payload {
+        differences.push(format!(
+            "payload was {:?}, now {:?}",
+            String::from_utf8_lossy(&recorded.payload),
+            String::from_utf8_lossy(&live.payload)
+        ));
+    }
+
+    differences
+}
+
+/// The options without their timestamp values, which differ in every session.
+fn comparable_options(options: &[TcpOption]) -> Vec<TcpOption> {
+    options.iter()
+        .map(|option| match option {
+            TcpOption::Timestamps { .. } => TcpOption::Timestamps { value: 0, echo_reply: 0 },
+            option => option.clone(),
+        })
+        .collect()
+}
+
+/// Describes a peer segment with its relative sequence and acknowledgement numbers.
+fn describe(segment: &CapturedSegment, numbers: &InitialSequenceNumbers) -> String {
+    format!(
+        "{} seq {} ack {} with {} bytes",
+        flag_names(segment.flags),
+        numbers.peer_seq(segment),
+        numbers.peer_ack(segment),
+        segment.payload.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(seq: u32, ack: u32) -> CapturedSegment {
+        CapturedSegment {
+            timestamp: Duration::ZERO,
+            source: "10.0.0.2:7".parse().unwrap(),
+            destination: "10.0.0.1:50000".parse().unwrap(),
+            seq,
+            ack,
+            flags: ACK,
+            window: 1000,
+            options: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn compares_numbers_relative_to_the_initial_sequence_numbers() {
+        let recorded = InitialSequenceNumbers { iss: 100, irs: Some(1000) };
+        let live = InitialSequenceNumbers { iss: 7000, irs: Some(5000) };
+
+        assert!(compare(&segment(1001, 101), &recorded, &segment(5001, 7001), &live).is_empty());
+    }
+
+    #[test]
+    fn reports_relative_differences_whatever_the_absolute_numbers() {
+        let recorded = InitialSequenceNumbers { iss: 100, irs: Some(1000) };
+        let live = InitialSequenceNumbers { iss: 100, irs: Some(500) };
+
+        // The same absolute sequence number is 500 bytes further into the live session
+        let differences = compare(&segment(1001, 101), &recorded, &segment(1001, 101), &live);
+        assert_eq!(differences, vec!["relative sequence number was 1, now 501".to_string()]);
+    }
+}
@@ -0,0 +1,73 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use crate::tcp::congestion::congestion_control::CongestionAlgorithm;
+use crate::tcp::packet::ip_header::IpVersion;
+use crate::tcp::packet::tcb::DEFAULT_RECEIVE_WINDOW;
+
+/// What a connection is set up with, chosen on the command line or by the user of the library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// Our address, the loopback address of the remote's IP version when not set
+    pub source_address: Option<IpAddr>,
+    /// The largest receive window we advertise, and the size of the receive buffer
+    pub receive_window: u16,
+    /// The congestion control algorithm of the connection
+    pub congestion: CongestionAlgorithm,
+    /// When a silent connection is probed and given up, it is never probed when not set
+    pub keepalive: Option<Keepalive>,
+}
+
+/// When a connection we heard nothing from for a while is probed, and when it is given up, as in RFC 1122
+/// section 4.2.3.6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// How long the remote has to be silent before the first probe
+    pub idle: Duration,
+    /// How long to wait for the answer to a probe before the next is sent
+    pub interval: Duration,
+    /// How many probes may go unanswered before the connection is given up
+    pub count: u32,
+}
+
+impl Default for Keepalive {
+    /// The defaults of Linux: two hours of silence, then nine probes 75 seconds apart.
+    fn default() -> Self {
+        Keepalive {
+            idle: Duration::from_secs(2 * 60 * 60),
+            interval: Duration::from_secs(75),
+            count: 9,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            source_address: None,
+            receive_window: DEFAULT_RECEIVE_WINDOW,
+            congestion: CongestionAlgorithm::default(),
+            keepalive: None,
+        }
+    }
+}
+
+impl Settings {
+    /// The address packets to a remote of an IP version are sent from.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The IP version of the remote
+    ///
+    /// # Returns
+    ///
+    /// * `Result<IpAddr, String>` - The source address, or an error if it is of another IP version
+    pub fn source_address_for(&self, version: IpVersion) -> Result<IpAddr, String> {
+        match (self.source_address, version) {
+            (None, IpVersion::V4) => Ok(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            (None, IpVersion::V6) => Ok(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+            (Some(address), version) if IpVersion::of(&address) == version => Ok(address),
+            (Some(address), version) => Err(format!("source address {} is not an {:?} address", address, version)),
+        }
+    }
+}
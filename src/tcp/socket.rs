@@ -0,0 +1,78 @@
+use std::ffi::c_int;
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::raw_bindings::raw_bindings::{AF_INET, AF_INET6, IP_HDRINCL, IPPROTO_IP, IPPROTO_IPV6, IPPROTO_TCP, IPV6_RECVPKTINFO, setsockopt, SO_RCVTIMEO, SOCK_RAW, socket, SOL_SOCKET, timeval};
+use crate::tcp::packet::ip_header::IpVersion;
+
+/// Opens the raw socket every connection is driven over.
+///
+/// # Arguments
+///
+/// * `version` - The IP version of the socket
+///
+/// # Returns
+///
+/// * `Result<c_int, String>` - The socket. IPv4 sockets have IP_HDRINCL set so that we build the IP header
+///   ourselves, IPv6 sockets report the destination address of every segment with IPV6_PKTINFO instead
+///
+/// # Remarks
+///
+/// Raw sockets need root or the CAP_NET_RAW capability.
+pub fn raw_socket(version: IpVersion) -> Result<c_int, String> {
+    let (domain, level, option) = match version {
+        IpVersion::V4 => (AF_INET, IPPROTO_IP as c_int, IP_HDRINCL as c_int),
+        IpVersion::V6 => (AF_INET6, IPPROTO_IPV6 as c_int, IPV6_RECVPKTINFO as c_int),
+    };
+
+    unsafe {
+        let socket = socket(domain as c_int, SOCK_RAW, IPPROTO_TCP as c_int);
+        if socket == -1 {
+            return Err(format!("Create socket failed, error: {}", socket));
+        }
+
+        let one = 1;
+        let opt = setsockopt(socket, level, option, &one as *const i32 as *const c_void, 4);
+        if opt == -1 {
+            return Err(format!("Create socket failed, error: {}", opt));
+        }
+
+        info!("Create socket success, socket id: {}", socket);
+        info!("Create socket success, opt return: {}", opt);
+        Ok(socket)
+    }
+}
+
+/// Makes reads on a socket give up after a while, so a loop reading it can notice it is no longer needed.
+///
+/// # Arguments
+///
+/// * `socket` - The socket
+/// * `timeout` - How long a read waits for a packet
+///
+/// # Returns
+///
+/// * `Result<(), String>` - An error if the option can not be set
+pub fn set_receive_timeout(socket: c_int, timeout: Duration) -> Result<(), String> {
+    let timeval = timeval {
+        tv_sec: timeout.as_secs() as _,
+        tv_usec: timeout.subsec_micros() as _,
+    };
+
+    let opt = unsafe {
+        setsockopt(
+            socket,
+            SOL_SOCKET as c_int,
+            SO_RCVTIMEO as c_int,
+            &timeval as *const timeval as *const c_void,
+            size_of::<timeval>() as u32,
+        )
+    };
+    if opt == -1 {
+        return Err(format!("Set receive timeout failed, error: {}", opt));
+    }
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use parking_lot::Mutex;
+use rand::Rng;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::info;
+
+use crate::tcp::connection_table::ConnectionTable;
+use crate::tcp::link::raw_socket::RawSocketLink;
+use crate::tcp::main_loop::{receive_packet, send_packet};
+use crate::tcp::packet::data::{CloseReason, Controller, TcpState};
+use crate::tcp::packet::ip_header::IpVersion;
+use crate::tcp::packet::options::DEFAULT_MSS;
+use crate::tcp::packet::send_buffer::SEND_BUFFER_LIMIT;
+use crate::tcp::settings::Settings;
+use crate::tcp::util::ToAddress;
+
+/// The tables `RawTcpStream::connect` drives its connections in, one per IP version, created on first use.
+static DEFAULT_TABLES: Mutex<Vec<Arc<ConnectionTable>>> = Mutex::new(Vec::new());
+
+/// A TCP connection driven by this stack over a raw socket, used like `tokio::net::TcpStream`.
+///
+/// Reads return the in-order bytes of the remote and EOF once its FIN has been read, writes are queued and sent as
+/// the send and congestion windows allow. Dropping the stream closes the connection.
+pub struct RawTcpStream {
+    controller: Arc<Controller>,
+}
+
+impl RawTcpStream {
+    /// Opens a connection to a remote, over a raw socket shared by every stream of the process.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The remote, as `ip:port` or `[ipv6]:port`
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<RawTcpStream>` - The stream once the three-way handshake has completed
+    pub async fn connect(address: &str) -> io::Result<RawTcpStream> {
+        let (_, remote_address) = address.to_address()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid address: {}", address)))?;
+        let version = if remote_address.contains(':') { IpVersion::V6 } else { IpVersion::V4 };
+
+        let table = {
+            let mut tables = DEFAULT_TABLES.lock();
+            match tables.iter().find(|table| table.supports(version)) {
+                Some(table) => table.clone(),
+                None => {
+                    let link = RawSocketLink::open(version).map_err(io::Error::other)?;
+                    let table = Arc::new(ConnectionTable::new(Arc::new(link)));
+                    tokio::spawn(receive_packet(table.clone()));
+                    tables.push(table.clone());
+                    table
+                }
+            }
+        };
+
+        RawTcpStream::connect_with(&table, address, Settings::default()).await
+    }
+
+    /// Opens a connection to a remote in a given connection table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The table the connection is driven in, its receive loop must be running
+    /// * `address` - The remote, as `ip:port` or `[ipv6]:port`, of an IP version the table supports
+    /// * `settings` - The source address, receive window and congestion control algorithm of the connection
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<RawTcpStream>` - The stream once the three-way handshake has completed
+    pub async fn connect_with(table: &Arc<ConnectionTable>, address: &str, settings: Settings) -> io::Result<RawTcpStream> {
+        let (remote_port, remote_address) = address.to_address()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid address: {}", address)))?;
+
+        // An unused port from the dynamic range
+        let controller = loop {
+            let local_port = rand::thread_rng().gen_range(49152..=u16::MAX);
+            let controller = Controller::new(table.link.clone(), local_port, remote_address, remote_port, settings)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let version = IpVersion::of(&controller.sockaddr_to_remote.address());
+            if !table.supports(version) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{:?} is not carried by the table of {}", version, address)));
+            }
+            if table.get(&controller.key()).is_none() {
+                break controller;
+            }
+        };
+
+        controller.receive_buffer.lock().attach();
+        let controller = table.register(controller);
+        send_packet(&controller).await;
+
+        match controller.wait_synchronized().await {
+            Ok(()) => {
+                info!("Connected to {} from port {}", controller.address_to_remote, controller.local_port);
+                Ok(RawTcpStream { controller })
+            }
+            Err(CloseReason::Aborted(reason)) => Err(io::Error::new(io::ErrorKind::ConnectionRefused, reason)),
+            Err(CloseReason::Reset) => Err(io::ErrorKind::ConnectionReset.into()),
+            Err(CloseReason::KeepaliveTimeout) => Err(io::ErrorKind::TimedOut.into()),
+            Err(CloseReason::Closed | CloseReason::Refused) => Err(io::ErrorKind::ConnectionRefused.into()),
+        }
+    }
+
+    /// The connection the stream runs on.
+    #[inline]
+    pub fn controller(&self) -> &Arc<Controller> {
+        &self.controller
+    }
+
+    #[inline]
+    pub fn local_port(&self) -> u16 {
+        self.controller.local_port
+    }
+
+    /// The remote, as `ip:port`.
+    #[inline]
+    pub fn peer_address(&self) -> &str {
+        &self.controller.address_to_remote
+    }
+}
+
+impl AsyncRead for RawTcpStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let mut buffer = self.controller.receive_buffer.lock();
+
+        if !buffer.is_empty() {
+            let data = buffer.take(buf.remaining());
+            buf.put_slice(&data);
+            let window = buffer.window();
+            drop(buffer);
+
+            self.controller.update_receive_window(window);
+            return Poll::Ready(Ok(()));
+        }
+
+        if buffer.is_finished() {
+            return match self.controller.closed.borrow().clone() {
+                Some(CloseReason::Aborted(reason)) => Poll::Ready(Err(io::Error::new(io::ErrorKind::ConnectionReset, reason))),
+                Some(CloseReason::Reset) => Poll::Ready(Err(io::ErrorKind::ConnectionReset.into())),
+                Some(CloseReason::KeepaliveTimeout) => Poll::Ready(Err(io::ErrorKind::TimedOut.into())),
+                _ => Poll::Ready(Ok(())),
+            };
+        }
+
+        buffer.register(cx.waker());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for RawTcpStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.controller.current_state() {
+            TcpState::Established | TcpState::CloseWait => {}
+            _ => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+
+        let size = {
+            let mut buffer = self.controller.send_buffer.lock();
+            if buffer.is_closed() {
+                return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+            }
+            if buffer.len() >= SEND_BUFFER_LIMIT {
+                buffer.register(cx.waker());
+                return Poll::Pending;
+            }
+            buf.len().min(SEND_BUFFER_LIMIT - buffer.len())
+        };
+
+        // The connection may have closed since the checks above
+        match self.controller.write(&buf[..size]) {
+            Ok(()) => Poll::Ready(Ok(size)),
+            Err(e) => Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, e))),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Written data is handed to the connection right away, what is left waits for the window
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let state = self.controller.current_state();
+        if (state == TcpState::Established || state == TcpState::CloseWait) && !self.controller.send_buffer.lock().is_closed() {
+            self.controller.close();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for RawTcpStream {
+    fn drop(&mut self) {
+        let state = self.controller.current_state();
+        if (state == TcpState::Established || state == TcpState::CloseWait) && !self.controller.send_buffer.lock().is_closed() {
+            self.controller.close();
+        }
+    }
+}
+
+/// Controller struct implementation
+impl Controller {
+    /// Advertises the room a read made in the receive buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The receive window left by the unread data
+    ///
+    /// # Remarks
+    ///
+    /// To avoid silly window syndrome the remote is only told about the larger window once it has grown by at least
+    /// one MSS or half the buffer, RFC 1122 section 4.2.3.3.
+    pub(crate) fn update_receive_window(&self, window: u16) {
+        let threshold = DEFAULT_MSS.min(self.settings.receive_window / 2);
+        {
+            let mut tcb = self.tcb.write();
+            if window.saturating_sub(tcb.rcv_wnd) < threshold {
+                return;
+            }
+            tcb.rcv_wnd = window;
+        }
+
+        match self.current_state() {
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2 => {
+                let mut packet = self.make_packet_with_none().to_ack_packet();
+                let sent_size = self.send_packet(&mut packet);
+                tracing::info!("window update send: {}, with size: {}", packet, sent_size);
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,38 +1,74 @@
 use std::fmt::{Display, Formatter};
 
 use crate::raw_bindings::raw_bindings::{htonl, htons, ntohl, ntohs};
-use crate::tcp::packet::data::ReceiveData;
+use crate::tcp::packet::data::{CloseReason, ReceiveData, TcpState};
 
 pub trait ToAddress {
     fn to_address(&self) -> Option<(u16, &str)>;
 }
 
 impl<T: AsRef<str>> ToAddress for T {
+    /// Splits `ip:port`, IPv6 addresses are written in brackets as in `[::1]:port`.
     fn to_address(&self) -> Option<(u16, &str)> {
-        let parts: Vec<&str> = self.as_ref().split(':').collect();
-        if parts.is_empty() { return None; }
-        if parts.len() == 1 { return None; }
+        let (addr, port) = self.as_ref().rsplit_once(':')?;
 
-        let addr = parts[0];
-        let port = match parts[1].parse::<u16>() {
+        let addr = match addr.strip_prefix('[') {
+            Some(addr) => addr.strip_suffix(']')?,
+            None if addr.contains(':') => return None,
+            None => addr,
+        };
+        let port = match port.parse::<u16>() {
             Ok(p) => { p }
             Err(_) => { return None }
         };
         Some((port, addr))
     }
 }
+
 impl Display for ReceiveData {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{{\nIP head: {},\n TCP head: {},\n Data: {:?}\n]}}",
-            self.iphdr,
+            "{{\nIP head: {},\n TCP head: {},\n TCP options: {:?},\n Data: {:?}\n]}}",
+            self.ip_head,
             self.tcphdr,
+            self.options,
             self.data,
         )
     }
 }
 
+impl Display for TcpState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TcpState::Closed => "CLOSED",
+            TcpState::Listen => "LISTEN",
+            TcpState::SynSent => "SYN-SENT",
+            TcpState::SynReceived => "SYN-RECEIVED",
+            TcpState::Established => "ESTABLISHED",
+            TcpState::FinWait1 => "FIN-WAIT-1",
+            TcpState::FinWait2 => "FIN-WAIT-2",
+            TcpState::CloseWait => "CLOSE-WAIT",
+            TcpState::LastAck => "LAST-ACK",
+            TcpState::Closing => "CLOSING",
+            TcpState::TimeWait => "TIME-WAIT",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for CloseReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseReason::Closed => write!(f, "connection closed"),
+            CloseReason::Aborted(reason) => write!(f, "{}", reason),
+            CloseReason::Refused => write!(f, "connection refused"),
+            CloseReason::Reset => write!(f, "connection reset by the remote"),
+            CloseReason::KeepaliveTimeout => write!(f, "the remote answered no keepalive probe"),
+        }
+    }
+}
+
 pub trait ChangingOrderSizes<T> {
     fn to_network(self) -> T;
     fn to_host(self) -> T;
@@ -67,15 +103,3 @@ impl ChangingOrderSizes<u32> for u32{
         }
     }
 }
-
-macro_rules! spawn_listener {
-    ($controller:expr, $receiver:expr, [$($func:ident),*]) => {
-        $(
-            let receiver_inner = $receiver.clone();
-            let controller_inner = $controller.clone();
-            tokio::spawn(async move {
-                controller_inner.$func(receiver_inner).await;
-            });
-        )*
-    };
-}
\ No newline at end of file
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+use crate::tcp::packet::data::ReceiveData;
+
+/// How many segments a listener may fall behind before the receive loop waits for it.
+pub const BUS_CAPACITY: usize = 256;
+
+/// The segments published to a listener, in the order they were received.
+pub type Subscription = mpsc::Receiver<Arc<ReceiveData>>;
+
+/// Hands every segment accepted by a connection to each of its listeners.
+///
+/// Every listener has a queue of its own, so none misses a segment because another came in right behind it. A full
+/// queue holds up the receive loop until the listener caught up, which leaves it to the link to drop what can not be
+/// kept. The listeners stop once the bus is dropped and their queues are drained.
+pub struct PacketBus {
+    subscribers: Mutex<Vec<mpsc::Sender<Arc<ReceiveData>>>>,
+    capacity: usize,
+}
+
+impl PacketBus {
+    /// Creates a bus without listeners.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - How many segments each listener may fall behind
+    pub fn new(capacity: usize) -> Self {
+        PacketBus {
+            subscribers: Mutex::new(Vec::new()),
+            capacity,
+        }
+    }
+
+    /// Adds a listener, it receives every segment published from now on.
+    pub fn subscribe(&self) -> Subscription {
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+
+    /// Hands a segment to every listener, waiting for room in the queues that are full.
+    ///
+    /// # Arguments
+    ///
+    /// * `receive_data` - The segment
+    ///
+    /// # Remarks
+    ///
+    /// Waiting blocks the thread, so this must not be called from within an asynchronous task.
+    pub fn publish(&self, receive_data: ReceiveData) {
+        let receive_data = Arc::new(receive_data);
+        let subscribers = self.subscribers.lock().clone();
+
+        let mut stopped = false;
+        for subscriber in subscribers {
+            // Fails only once the listener stopped
+            stopped |= subscriber.blocking_send(receive_data.clone()).is_err();
+        }
+        if stopped {
+            self.subscribers.lock().retain(|subscriber| !subscriber.is_closed());
+        }
+    }
+}
+
+impl Default for PacketBus {
+    fn default() -> Self {
+        PacketBus::new(BUS_CAPACITY)
+    }
+}
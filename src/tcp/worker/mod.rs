@@ -1,4 +1,7 @@
-#[macro_use]
 pub(crate) mod util;
 
-pub mod receive_processor;
\ No newline at end of file
+pub mod bus;
+pub mod receive_processor;
+pub mod state_machine;
+pub mod timer;
+pub mod sender;
\ No newline at end of file
@@ -1,8 +1,8 @@
 use colored::Colorize;
 use log::info;
-use tokio::sync::watch::Receiver;
 
-use crate::tcp::packet::data::{Controller, ReceiveData, SpacilProcessor};
+use crate::tcp::packet::data::{CloseReason, Controller, ReceiveData, TcpState};
+use crate::tcp::worker::state_machine::MAXIMUM_SEGMENT_LIFETIME;
 
 /// Controller struct implementation
 impl Controller {
@@ -10,99 +10,172 @@ impl Controller {
     ///
     /// # Arguments
     ///
-    /// * `receiver` - A Receiver<Option<ReceiveData>> instance that receives the data.
+    /// * `receiver` - A segment the connection accepted.
     ///
     /// # Remarks
     ///
-    /// This function listens for the third handshake in the TCP connection process and sends a tertiary handshake packet when a secondary handshake packet is found.
-    pub async fn third_handshake_listener(&self, receiver: Receiver<Option<ReceiveData>>) {
-        processor!(self, receiver, SpacilProcessor::InitHandshake, |receiver| {
-
-            if receiver.tcphdr.syn() == 1 && receiver.tcphdr.ack() == 1 {
+    /// In SYN-SENT this function sends a tertiary handshake packet when a secondary handshake packet is found.
+    /// In SYN-RECEIVED it waits for the remote's ACK of our SYN-ACK. Either way the connection moves to ESTABLISHED
+    /// and anything written in the meantime is sent.
+    pub fn third_handshake_listener(&self, receiver: &ReceiveData) {
+        match self.current_state() {
+            TcpState::SynSent if receiver.tcphdr.syn() == 1 && receiver.tcphdr.ack() == 1 => {
                 info!("{}", "Secondary handshake packet found, tertiary handshake packet being sent......".truecolor(200, 35, 55));
-                let mut packet = self.make_packet_with_none().to_third_handshake(receiver.tcphdr.ack_seq, receiver.tcphdr.seq);
+                let mut packet = self.make_packet_with_none().to_third_handshake();
 
-                let sent_size = self.send_packet(&mut packet);
+                let sent_size = self.send_packet_with_state(&mut packet, TcpState::Established);
 
                 info!("third_handshake send: {}, with size: {}", packet, sent_size);
-                *self.spacil.write() = SpacilProcessor::None;
+                self.flush_send_buffer();
             }
-        });
+
+            TcpState::SynReceived if self.tcb.read().all_acknowledged() => {
+                info!("{}", format!("Tertiary handshake packet found, connection from {} accepted", self.address_to_remote).truecolor(200, 35, 55));
+                self.transition(TcpState::Established);
+                self.flush_send_buffer();
+            }
+
+            _ => {}
+        }
     }
 
     /// Prints the received packet.
     ///
     /// # Arguments
     ///
-    /// * `receiver` - A Receiver<Option<ReceiveData>> instance that receives the data.
+    /// * `receiver` - A segment the connection accepted.
     ///
     /// # Remarks
     ///
-    /// This function prints the received packet's size, IP header, and TCP header.
-    pub async fn packet_printer(&self, receiver: Receiver<Option<ReceiveData>>) {
-        processor!(self, receiver, SpacilProcessor::None, |receiver| {
-            let mut string = String::new();
-            string.push_str(format!("Received packet with size {}: {{\n", receiver.packet_size).as_str());
-            string.push_str(format!("  received ip head: {}\n", receiver.iphdr).as_str());
-            string.push_str(format!("  received tcp head: {}\n", receiver.tcphdr).as_str());
-            string.push_str("}");
-            tracing::info!("{}", string.truecolor(170, 170, 170));
-        })
+    /// This function prints the received packet's size, IP header, TCP header and TCP options.
+    pub fn packet_printer(&self, receiver: &ReceiveData) {
+        let mut string = String::new();
+        string.push_str(format!("Received packet with size {}: {{\n", receiver.packet_size).as_str());
+        string.push_str(format!("  received ip head: {}\n", receiver.ip_head).as_str());
+        string.push_str(format!("  received tcp head: {}\n", receiver.tcphdr).as_str());
+        if !receiver.options.is_empty() {
+            string.push_str(format!("  received tcp options: {:?}\n", receiver.options).as_str());
+        }
+        string.push('}');
+        tracing::info!("{}", string.truecolor(170, 170, 170));
     }
 
     /// Listens for data from the server.
     ///
     /// # Arguments
     ///
-    /// * `receiver` - A Receiver<Option<ReceiveData>> instance that receives the data.
+    /// * `receiver` - A segment the connection accepted.
     ///
     /// # Remarks
     ///
-    /// This function listens for data from the server and sends an acknowledgment packet when data is received.
-    pub async fn data_listener(&self, receiver: Receiver<Option<ReceiveData>>) {
-        processor!(self, receiver, SpacilProcessor::None, |receiver| {
-            if let Some(data) = &receiver.data {
-                let tmp = String::from_utf8_lossy(data);
-                info!(
-                    "Receive a string from {}: {}",
-                    "server".truecolor(250, 108, 10),
-                    tmp.replace("\r", "")
-                    .replace("\n", "")
-                    .truecolor(10, 163, 250)
-                );
-                let mut packet = self
-                                                .make_packet_with_none()
-                                                .to_data_ack_packet(receiver.tcphdr.seq, receiver.tcphdr.ack_seq, data.len() as u32);
-
-                let sent_size = self.send_packet(&mut packet);
-
-                tracing::info!("data ack packet send: {}, with size: {}", packet, sent_size);
-            }
-        })
+    /// This function prints the bytes that became contiguous in the receive stream, unless a reader is attached to the
+    /// connection, and acknowledges every segment carrying data with RCV.NXT, so out-of-order segments produce a
+    /// duplicate ACK.
+    /// Segments whose FIN was delivered are acknowledged by `wave_handshake_listener` instead.
+    pub fn data_listener(&self, receiver: &ReceiveData) {
+        if receiver.data.is_none() {
+            return;
+        }
+
+        if !receiver.delivered.data.is_empty() && !self.receive_buffer.lock().is_attached() {
+            let tmp = String::from_utf8_lossy(&receiver.delivered.data);
+            info!(
+                "Receive a string from {}: {}",
+                "server".truecolor(250, 108, 10),
+                tmp.replace("\r", "")
+                .replace("\n", "")
+                .truecolor(10, 163, 250)
+            );
+        }
+
+        if receiver.delivered.fin {
+            return;
+        }
+
+        let mut packet = self.make_packet_with_none().to_ack_packet();
+        let sent_size = self.send_packet(&mut packet);
+
+        tracing::info!("data ack packet send: {}, with size: {}", packet, sent_size);
     }
 
-    /// Listens for the final handshake in the TCP connection process.
+    /// Listens for the wave handshake in the TCP connection process.
     ///
     /// # Arguments
     ///
-    /// * `receiver` - A Receiver<Option<ReceiveData>> instance that receives the data.
+    /// * `receiver` - A segment the connection accepted.
     ///
     /// # Remarks
     ///
-    /// This function listens for the final handshake in the TCP connection process and sends a final handshake packet when a FIN-ACK handshake packet is found.
-    pub async fn wave_handshake_listener(&self, receiver: Receiver<Option<ReceiveData>>) {
-        processor!(self, receiver, SpacilProcessor::WaveHandshake, |receiver| {
-            if receiver.tcphdr.fin() == 1 && receiver.tcphdr.ack() == 1 {
+    /// This function drives both the active close (FIN-WAIT-1, FIN-WAIT-2, CLOSING, TIME-WAIT) and the passive close
+    /// (ESTABLISHED to CLOSE-WAIT to LAST-ACK), acknowledging every FIN from the remote. On a passive close of a
+    /// connection without a reader our own FIN follows once the send buffer has drained, a stream stays in CLOSE-WAIT
+    /// until it is shut down or dropped.
+    pub fn wave_handshake_listener(&self, receiver: &ReceiveData) {
+        let fin = receiver.delivered.fin;
+        let fin_acknowledged = self.tcb.read().all_acknowledged();
+        let state = self.current_state();
+
+        match state {
+            TcpState::Established if fin => {
+                info!("{}", "FIN packet found, the remote is closing the connection......".truecolor(200, 35, 55));
+                self.acknowledge_fin(TcpState::CloseWait);
+                // A stream may go on writing after the remote half-closed, it closes through shutdown or drop
+                if !self.receive_buffer.lock().is_attached() {
+                    self.close();
+                }
+            }
+
+            TcpState::FinWait1 if fin => {
                 info!("{}", "FIN-ACK handshake packet found, FIN-FINAL handshake packet being sent......".truecolor(200, 35, 55));
-                let mut packet = self.make_packet_with_none().to_fourth_handshake(receiver.tcphdr.ack_seq, receiver.tcphdr.seq);
+                let next = if fin_acknowledged { TcpState::TimeWait } else { TcpState::Closing };
+                self.acknowledge_fin(next);
+            }
+
+            TcpState::FinWait1 if fin_acknowledged => self.transition(TcpState::FinWait2),
+
+            TcpState::FinWait2 if fin => {
+                info!("{}", "FIN handshake packet found, FIN-FINAL handshake packet being sent......".truecolor(200, 35, 55));
+                self.acknowledge_fin(TcpState::TimeWait);
+            }
 
-                let sent_size = self.send_packet(&mut packet);
+            TcpState::Closing if fin_acknowledged => self.transition(TcpState::TimeWait),
 
-                tracing::info!("wave_handshake send: {}, with size: {}", packet, sent_size);
-                *self.spacil.write() = SpacilProcessor::None;
+            // The remote did not see our last ACK and retransmitted its FIN.
+            TcpState::TimeWait if receiver.tcphdr.fin() == 1 => self.acknowledge_fin(TcpState::TimeWait),
+
+            TcpState::LastAck if fin_acknowledged => {
                 info!("FIN-ACK success, bye, my dear baby~");
-                std::process::exit(0);
+                self.finish(CloseReason::Closed);
             }
+
+            _ => {}
+        }
+
+        // The reader sees EOF only now that the FIN moved the connection on
+        if fin {
+            self.receive_buffer.lock().finish();
+        }
+
+        if state != TcpState::TimeWait && self.current_state() == TcpState::TimeWait {
+            self.wait_for_close();
+        }
+    }
+
+    /// Acknowledges a FIN from the remote and moves the connection to `next`.
+    fn acknowledge_fin(&self, next: TcpState) {
+        let mut packet = self.make_packet_with_none().to_ack_packet();
+        let sent_size = self.send_packet_with_state(&mut packet, next);
+
+        tracing::info!("wave_handshake send: {}, with size: {}", packet, sent_size);
+    }
+
+    /// Keeps the connection in TIME-WAIT for two maximum segment lifetimes, then closes it.
+    fn wait_for_close(&self) {
+        let controller = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(MAXIMUM_SEGMENT_LIFETIME * 2).await;
+            info!("FIN-ACK success, bye, my dear baby~");
+            controller.finish(CloseReason::Closed);
         });
     }
-}
\ No newline at end of file
+}
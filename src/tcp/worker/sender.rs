@@ -0,0 +1,109 @@
+use colored::Colorize;
+use log::{trace, warn};
+
+use crate::tcp::packet::data::{Controller, TcpState};
+
+/// Controller struct implementation
+impl Controller {
+    /// Queues data for sending and sends as much of it as the remote's window allows.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The bytes to append to the stream
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if the connection was asked to close or can not send any more, the data is
+    ///   not queued then
+    pub fn write(&self, data: &[u8]) -> Result<(), String> {
+        {
+            let mut buffer = self.send_buffer.lock();
+            if buffer.is_closed() {
+                return Err(format!("Connection is closing, {} bytes are not sent", data.len()));
+            }
+            // Data written during the handshake goes out once it completes
+            let state = self.current_state();
+            if !matches!(state, TcpState::SynSent | TcpState::SynReceived | TcpState::Established | TcpState::CloseWait) {
+                return Err(format!("Can not write to a connection in state {}", state));
+            }
+            buffer.push(data);
+        }
+
+        self.flush_send_buffer();
+        Ok(())
+    }
+
+    /// Asks for the connection to be closed, the FIN goes out once every queued byte has been sent.
+    pub fn close(&self) {
+        let state = self.current_state();
+        if state != TcpState::Established && state != TcpState::CloseWait {
+            warn!("Can not close a connection in state {}", state);
+            return;
+        }
+
+        self.send_buffer.lock().close();
+        self.flush_send_buffer();
+    }
+
+    /// Sends queued data while the remote's window has room for it.
+    ///
+    /// # Remarks
+    ///
+    /// Each segment carries at most one MSS and never more than SND.UNA + SND.WND - SND.NXT bytes, nor more than the
+    /// congestion window leaves room for. Whatever does not fit stays queued until an ACK opens the window.
+    /// Data is only sent in ESTABLISHED and CLOSE-WAIT, so anything written during the handshake goes out once it
+    /// completes.
+    /// The persist timer runs while the remote's window is zero with nothing in flight, and stops once the window
+    /// opened or nothing is left to send.
+    pub fn flush_send_buffer(&self) {
+        let mut buffer = self.send_buffer.lock();
+
+        let window_closed = loop {
+            let next = match self.current_state() {
+                TcpState::Established => TcpState::FinWait1,
+                TcpState::CloseWait => TcpState::LastAck,
+                _ => break false,
+            };
+
+            if buffer.is_empty() {
+                if buffer.take_fin() {
+                    let mut packet = self.make_packet_with_none().to_fin_packet();
+                    let sent_size = self.send_packet_with_state(&mut packet, next);
+                    tracing::info!("fin data send: {}, with size: {}", packet, sent_size);
+                }
+                break false;
+            }
+
+            // Flow control and congestion control both have to allow the segment
+            let (window, flight_size, mss) = {
+                let tcb = self.tcb.read();
+                (tcb.usable_window(), tcb.flight_size(), tcb.snd_mss as usize)
+            };
+            let cwnd = self.congestion.lock().cwnd();
+            let usable = window.min(cwnd.saturating_sub(flight_size)) as usize;
+
+            let size = usable.min(mss).min(buffer.len());
+            if size == 0 {
+                trace!(
+                    "{}",
+                    format!("Send window is full (cwnd: {}, in flight: {}), {} bytes wait for an ACK", cwnd, flight_size, buffer.len())
+                        .truecolor(25, 160, 60)
+                );
+                // With nothing in flight no ACK is coming, only a probe finds out when the window opens
+                break window == 0 && flight_size == 0;
+            }
+
+            let mut packet = self.make_packet_with_data(buffer.take(size)).to_data_packet();
+            let sent_size = self.send_packet(&mut packet);
+            tracing::info!("input data send: {}, with size: {}", packet, sent_size);
+        };
+
+        // Still under the lock of the buffer, so a flush that saw the window closed can not outrun one that saw it open
+        if window_closed {
+            let rto = self.retransmission.lock().estimator().rto();
+            self.persist.lock().start(rto);
+        } else {
+            self.persist.lock().stop();
+        }
+    }
+}
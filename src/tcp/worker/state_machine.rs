@@ -0,0 +1,357 @@
+use std::time::Duration;
+
+use colored::Colorize;
+use log::{error, info, trace};
+
+use crate::tcp::congestion::congestion_control::AckOutcome;
+use crate::tcp::packet::data::{CloseReason, Controller, ReceiveData, TcpState};
+use crate::tcp::packet::tcb::{seq_le, TransmissionControlBlock};
+use crate::tcp::processor::ConnectionEvent;
+use crate::tcp::util::ChangingOrderSizes;
+
+/// Maximum segment lifetime, TIME-WAIT lasts twice this long.
+/// RFC 793 suggests two minutes, which is far too long for a test client.
+pub const MAXIMUM_SEGMENT_LIFETIME: Duration = Duration::from_secs(2);
+
+/// What the receive loop should do with an inbound segment after it has been checked against the connection state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentCheck {
+    /// The segment is valid in the current state and is handed to the listeners.
+    Accept,
+    /// The segment is a duplicate or otherwise unacceptable, an ACK is sent back and the segment is dropped.
+    Acknowledge,
+    /// The segment makes no sense in the current state and is silently dropped.
+    Drop,
+    /// The segment acknowledges something we never sent, it is answered with a RST and dropped.
+    Reset,
+    /// The segment is a RST the connection has to accept, the connection is torn down.
+    Abort,
+}
+
+/// Controller struct implementation
+impl Controller {
+    /// Returns the current connection state.
+    #[inline]
+    pub fn current_state(&self) -> TcpState {
+        *self.state.read()
+    }
+
+    /// Moves the connection to a new state.
+    ///
+    /// # Arguments
+    ///
+    /// * `next` - The state to move to
+    pub fn transition(&self, next: TcpState) {
+        let mut state = self.state.write();
+        if *state != next {
+            info!("{}", format!("Connection state: {} -> {}", *state, next).truecolor(220, 180, 40));
+            let from = std::mem::replace(&mut *state, next);
+            self.state_changed.notify_waiters();
+            // Sent while the state is locked, so the processors see the transitions in the order they happened
+            let _ = self.events.send(ConnectionEvent::StateChanged { from, to: next });
+        }
+    }
+
+    /// Moves the connection to CLOSED, drops every outstanding segment and wakes up everyone in `wait_closed`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the connection closed
+    pub fn finish(&self, reason: CloseReason) {
+        self.transition(TcpState::Closed);
+        let first = self.closed.send_if_modified(|closed| {
+            if closed.is_some() {
+                return false;
+            }
+            *closed = Some(reason.clone());
+            true
+        });
+        // The timers see the connection closed once clearing them wakes them up
+        self.retransmission.lock().clear();
+        self.persist.lock().stop();
+        if first {
+            let _ = self.events.send(ConnectionEvent::Closed(reason));
+        }
+
+        // Readers see EOF or the error, writers find the connection closed
+        self.receive_buffer.lock().finish();
+        self.send_buffer.lock().wake();
+    }
+
+    /// Aborts the connection: every outstanding segment is dropped and the connection is closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `reason` - Why the connection is aborted
+    pub fn abort(&self, reason: &str) {
+        error!("{}", format!("Connection to {} aborted: {}", self.address_to_remote, reason).red());
+        self.finish(CloseReason::Aborted(reason.to_string()));
+    }
+
+    /// Waits until the connection reaches CLOSED.
+    ///
+    /// # Returns
+    ///
+    /// * `CloseReason` - Why the connection closed
+    pub async fn wait_closed(&self) -> CloseReason {
+        let mut receiver = self.closed.subscribe();
+        let reason = receiver.wait_for(|closed| closed.is_some()).await.unwrap();
+        reason.clone().unwrap()
+    }
+
+    /// Waits until the three-way handshake has completed.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), CloseReason>` - Ok once the connection is synchronized, or why it closed before
+    pub async fn wait_synchronized(&self) -> Result<(), CloseReason> {
+        loop {
+            let notified = self.state_changed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            match self.current_state() {
+                TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived => {}
+                _ => return Ok(()),
+            }
+            if let Some(reason) = self.closed.borrow().clone() {
+                return Err(reason);
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Checks an inbound segment against the current connection state.
+    ///
+    /// # Arguments
+    ///
+    /// * `receive` - The segment to check
+    ///
+    /// # Returns
+    ///
+    /// * `SegmentCheck` - The action the receive loop should take
+    pub fn check_segment(&self, receive: &ReceiveData) -> SegmentCheck {
+        let head = &receive.tcphdr;
+        let state = self.current_state();
+        let tcb = self.tcb.read();
+
+        let check = match state {
+            // A RST is never answered, with a RST or otherwise
+            _ if head.rst() == 1 => Self::check_reset(state, &tcb, receive),
+
+            // A connection that is not open yet, or no longer, resets whatever reaches it
+            TcpState::Closed => SegmentCheck::Reset,
+
+            // SYNs for a listening port are answered by the listener before a connection exists
+            TcpState::Listen => SegmentCheck::Drop,
+
+            // Segments behind the SYN-ACK may arrive before the processors moved the connection on
+            TcpState::SynSent if tcb.is_syn_acknowledged() => Self::check_synchronized(&tcb, receive),
+
+            TcpState::SynSent => {
+                let ack = head.ack_seq.to_host();
+                if head.ack() == 1 && !tcb.acknowledges_syn(ack) {
+                    SegmentCheck::Reset
+                } else if head.syn() == 1 && head.ack() == 1 && tcb.acknowledges_new_data(ack) {
+                    SegmentCheck::Accept
+                } else {
+                    SegmentCheck::Drop
+                }
+            }
+
+            TcpState::SynReceived => {
+                let ack = head.ack_seq.to_host();
+                if !tcb.is_acceptable(head.seq.to_host(), receive.sequence_length()) || head.syn() == 1 {
+                    // A retransmitted SYN, our SYN-ACK is retransmitted by the timer
+                    SegmentCheck::Drop
+                } else if head.ack() == 0 {
+                    SegmentCheck::Drop
+                } else if tcb.acknowledges_syn(ack) {
+                    // Segments behind the ACK of our SYN-ACK may arrive before the processors moved the connection on
+                    SegmentCheck::Accept
+                } else {
+                    SegmentCheck::Reset
+                }
+            }
+
+            TcpState::Established |
+            TcpState::FinWait1 |
+            TcpState::FinWait2 |
+            TcpState::CloseWait |
+            TcpState::LastAck |
+            TcpState::Closing |
+            TcpState::TimeWait => Self::check_synchronized(&tcb, receive),
+        };
+
+        if check != SegmentCheck::Accept {
+            trace!("{}", format!("Segment rejected in state {}: {:?}", state, check).truecolor(25, 160, 60));
+        }
+
+        check
+    }
+
+    /// Checks an inbound segment of a synchronized connection, RFC 9293 section 3.10.7.4.
+    ///
+    /// # Arguments
+    ///
+    /// * `tcb` - The TCB of the connection
+    /// * `receive` - The segment, which is not a RST
+    ///
+    /// # Returns
+    ///
+    /// * `SegmentCheck` - `Accept`, `Acknowledge` for a segment outside the window, or `Drop`
+    fn check_synchronized(tcb: &TransmissionControlBlock, receive: &ReceiveData) -> SegmentCheck {
+        let head = &receive.tcphdr;
+
+        if !tcb.is_acceptable(head.seq.to_host(), receive.sequence_length()) {
+            // Old duplicates, such as a retransmitted SYN-ACK after our third handshake got lost
+            SegmentCheck::Acknowledge
+        } else if head.syn() == 1 {
+            SegmentCheck::Acknowledge
+        } else if head.ack() == 0 {
+            SegmentCheck::Drop
+        } else if tcb.is_ack_too_new(head.ack_seq.to_host()) {
+            SegmentCheck::Acknowledge
+        } else {
+            SegmentCheck::Accept
+        }
+    }
+
+    /// Checks an inbound RST against the current connection state, as RFC 9293 section 3.10.7 and RFC 5961 section 3
+    /// ask.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The current connection state
+    /// * `tcb` - The TCB of the connection
+    /// * `receive` - The RST
+    ///
+    /// # Returns
+    ///
+    /// * `SegmentCheck` - `Abort` if the RST tears the connection down, `Acknowledge` for a challenge ACK, or `Drop`
+    fn check_reset(state: TcpState, tcb: &TransmissionControlBlock, receive: &ReceiveData) -> SegmentCheck {
+        let head = &receive.tcphdr;
+        let seq = head.seq.to_host();
+
+        match state {
+            TcpState::Closed | TcpState::Listen => SegmentCheck::Drop,
+
+            // Only the answer to our SYN can refuse it, its sequence number is not known yet
+            TcpState::SynSent if !tcb.is_syn_acknowledged() => {
+                if head.ack() == 1 && tcb.acknowledges_syn(head.ack_seq.to_host()) {
+                    SegmentCheck::Abort
+                } else {
+                    SegmentCheck::Drop
+                }
+            }
+
+            TcpState::SynReceived => {
+                if tcb.is_acceptable(seq, 0) {
+                    SegmentCheck::Abort
+                } else {
+                    SegmentCheck::Drop
+                }
+            }
+
+            // A blind RST would have to guess RCV.NXT exactly, one elsewhere in the window gets a challenge ACK
+            // that a remote which really lost the connection answers with a RST of the right sequence number
+            _ => {
+                if seq == tcb.rcv_nxt {
+                    SegmentCheck::Abort
+                } else if tcb.is_acceptable(seq, 0) {
+                    SegmentCheck::Acknowledge
+                } else {
+                    SegmentCheck::Drop
+                }
+            }
+        }
+    }
+
+    /// Tears the connection down after the remote reset it.
+    ///
+    /// # Remarks
+    ///
+    /// A RST answering our SYN refuses the connection. One in CLOSING, LAST-ACK or TIME-WAIT only ends a close that was
+    /// under way, as RFC 9293 section 3.10.7.4 asks, any other is reported as a reset.
+    pub fn reset_by_remote(&self) {
+        let reason = match self.current_state() {
+            TcpState::SynSent if !self.tcb.read().is_syn_acknowledged() => CloseReason::Refused,
+            TcpState::Closing | TcpState::LastAck | TcpState::TimeWait => CloseReason::Closed,
+            _ => CloseReason::Reset,
+        };
+        if reason != CloseReason::Closed {
+            error!("{}", format!("Connection to {}: {}", self.address_to_remote, reason).red());
+        }
+        self.finish(reason);
+    }
+
+    /// Updates the TCB from a segment that passed `check_segment`.
+    ///
+    /// The payload and FIN go through the reassembly buffer, whatever became contiguous is stored in
+    /// `receive.delivered` for the listeners.
+    ///
+    /// # Arguments
+    ///
+    /// * `receive` - The accepted segment
+    pub fn update_tcb(&self, receive: &mut ReceiveData) {
+        let head = receive.tcphdr;
+        let mut tcb = self.tcb.write();
+
+        if head.syn() == 1 {
+            tcb.synchronize(head.seq.to_host());
+            tcb.negotiate(&receive.options);
+            self.congestion.lock().set_mss(tcb.snd_mss as u32);
+        }
+
+        // The payload of a SYN starts right after it
+        let seq = head.seq.to_host().wrapping_add(head.syn() as u32);
+        let payload = receive.data.as_deref().unwrap_or_default();
+        receive.delivered = self.reassembly.lock().receive(&mut tcb, seq, payload, head.fin() == 1);
+
+        // An attached reader gets the data, the window shrinks until it is read. It sees the FIN once the wave
+        // processor acknowledged it, so a stream shut down at EOF closes from CLOSE-WAIT
+        let mut buffer = self.receive_buffer.lock();
+        if buffer.is_attached() {
+            buffer.push(&receive.delivered.data);
+            tcb.rcv_wnd = buffer.window();
+        }
+        drop(buffer);
+
+        if head.ack() == 1 {
+            let ack = head.ack_seq.to_host();
+            let window = tcb.scale_window(head.window.to_host(), head.syn() == 1);
+            let (snd_una, flight_size) = (tcb.snd_una, tcb.flight_size());
+
+            // RFC 5681 section 2: a duplicate ACK carries nothing new while data is outstanding
+            let duplicate = ack == snd_una &&
+                flight_size > 0 &&
+                receive.data.is_none() &&
+                head.syn() == 0 &&
+                head.fin() == 0 &&
+                window == tcb.snd_wnd;
+
+            let acked = if tcb.acknowledge(ack) {
+                self.retransmission.lock().acknowledge(tcb.snd_una);
+                Some(ack.wrapping_sub(snd_una))
+            } else if duplicate {
+                Some(0)
+            } else {
+                None
+            };
+            // An ACK older than SND.UNA carries an outdated window as well
+            if seq_le(snd_una, ack) {
+                tcb.update_window(head.seq.to_host(), ack, window);
+            }
+
+            // The handshake itself is not subject to congestion control
+            if let (Some(acked), 0) = (acked, head.syn()) {
+                let rtt = self.retransmission.lock().estimator().srtt();
+                let outcome = self.congestion.lock().on_ack(acked, ack, tcb.snd_nxt, flight_size, rtt);
+                if outcome == AckOutcome::Retransmit {
+                    self.retransmit_oldest(&tcb);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::time::Instant;
+
+use colored::Colorize;
+use log::{trace, warn};
+use tokio::time;
+
+use crate::tcp::packet::data::{CloseReason, Controller, TcpState};
+use crate::tcp::packet::persist::{MAX_WINDOW_PROBES, Persist};
+use crate::tcp::packet::retransmission::{MAX_RETRANSMISSIONS, Timeout};
+use crate::tcp::packet::tcb::TransmissionControlBlock;
+use crate::tcp::settings::Keepalive;
+
+/// Controller struct implementation
+impl Controller {
+    /// Drives the retransmission timer of the connection.
+    ///
+    /// # Remarks
+    ///
+    /// This function sleeps until the deadline of the retransmission queue, retransmits the oldest unacknowledged
+    /// segment when it passes and aborts the connection once `MAX_RETRANSMISSIONS` retransmissions went unanswered.
+    /// Every timeout is reported to the congestion control as a loss.
+    /// It is woken up whenever the queue restarts or stops the timer, and returns once the connection has closed.
+    pub async fn retransmission_timer(&self) {
+        let notify = self.retransmission.lock().notify();
+        let mut closed = self.closed.subscribe();
+
+        loop {
+            // Nothing may be outstanding when the connection closes, so closing has to wake the task up as well
+            let deadline = self.retransmission.lock().deadline();
+            let expired = async {
+                match deadline {
+                    Some(deadline) => time::sleep_until(deadline.into()).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                _ = expired => {}
+                _ = notify.notified() => continue,
+                _ = closed.wait_for(|closed| closed.is_some()) => break,
+            }
+
+            // The segment is sent again with the latest acknowledgement number and window
+            let (rcv_nxt, rcv_wnd, flight_size) = {
+                let tcb = self.tcb.read();
+                (tcb.rcv_nxt, tcb.rcv_wnd, tcb.flight_size())
+            };
+
+            let mut queue = self.retransmission.lock();
+            let rto = queue.estimator().rto();
+            match queue.on_timeout() {
+                Timeout::Retransmit(packet) => {
+                    packet.restamp(rcv_nxt, rcv_wnd);
+
+                    let sent_size = self.transmit(packet);
+                    warn!(
+                        "{}",
+                        format!("Retransmission timeout after {:?}, segment sent again: {}, with size: {}", rto, packet, sent_size)
+                            .truecolor(230, 120, 30)
+                    );
+
+                    drop(queue);
+                    self.congestion.lock().on_timeout(flight_size);
+                }
+                Timeout::GiveUp => {
+                    drop(queue);
+                    self.abort(&format!("no acknowledgement after {} retransmissions", MAX_RETRANSMISSIONS));
+                }
+                Timeout::Idle => {}
+            }
+        }
+    }
+
+    /// Drives the persist timer of the connection.
+    ///
+    /// # Remarks
+    ///
+    /// This function sleeps until the deadline of the persist timer and probes the remote's zero window when it
+    /// passes, the answer tells whether the window opened. The connection is aborted once `MAX_WINDOW_PROBES` probes
+    /// in a row went unanswered.
+    /// It is woken up whenever the timer is started or stopped, and returns once the connection has closed.
+    pub async fn persist_timer(&self) {
+        let notify = self.persist.lock().notify();
+        let mut closed = self.closed.subscribe();
+
+        loop {
+            // The timer is rarely running, so the connection closing has to wake the task up as well
+            let deadline = self.persist.lock().deadline();
+            let expired = async {
+                match deadline {
+                    Some(deadline) => time::sleep_until(deadline.into()).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                _ = expired => {}
+                _ = notify.notified() => continue,
+                _ = closed.wait_for(|closed| closed.is_some()) => break,
+            }
+
+            let heard = *self.last_received.lock();
+            let outcome = self.persist.lock().on_timeout(heard);
+            match outcome {
+                Persist::Probe => {
+                    trace!("{}", format!("Window of {} is still closed, probing it", self.address_to_remote).truecolor(25, 160, 60));
+                    self.send_window_probe();
+                }
+                Persist::GiveUp => self.abort(&format!("no answer to {} window probes", MAX_WINDOW_PROBES)),
+                Persist::Idle => {}
+            }
+        }
+    }
+
+    /// Drives the keepalive timer of the connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `keepalive` - When the connection is probed and given up
+    ///
+    /// # Remarks
+    ///
+    /// Once the remote was silent for `keepalive.idle`, a probe is sent every `keepalive.interval` until anything
+    /// arrives from the remote, which starts the idle time again. After `keepalive.count` unanswered probes the
+    /// connection is closed with `CloseReason::KeepaliveTimeout`.
+    /// Only synchronized connections with nothing in flight are probed, the retransmission timer gives up on the
+    /// others. This function returns once the connection has closed.
+    pub async fn keepalive_timer(&self, keepalive: Keepalive) {
+        let mut closed = self.closed.subscribe();
+        // When the remote was last heard from, or when the idle time was last started again
+        let mut heard = *self.last_received.lock();
+        let mut probed = heard;
+        let mut unanswered = 0;
+
+        loop {
+            let deadline = if unanswered == 0 { heard + keepalive.idle } else { probed + keepalive.interval };
+            tokio::select! {
+                _ = time::sleep_until(deadline.into()) => {}
+                _ = closed.wait_for(|closed| closed.is_some()) => break,
+            }
+
+            let last_received = *self.last_received.lock();
+            if last_received > heard {
+                heard = last_received;
+                unanswered = 0;
+                continue;
+            }
+
+            if unanswered == keepalive.count {
+                warn!(
+                    "{}",
+                    format!("{} answered none of {} keepalive probes, connection given up", self.address_to_remote, unanswered)
+                        .truecolor(230, 120, 30)
+                );
+                self.finish(CloseReason::KeepaliveTimeout);
+                break;
+            }
+
+            let probing = matches!(self.current_state(), TcpState::Established | TcpState::CloseWait | TcpState::FinWait2);
+            if !probing || self.tcb.read().flight_size() > 0 {
+                heard = Instant::now();
+                unanswered = 0;
+                continue;
+            }
+
+            self.send_keepalive();
+            probed = Instant::now();
+            unanswered += 1;
+        }
+    }
+
+    /// Sends the oldest unacknowledged segment again right away, for a fast retransmit.
+    ///
+    /// # Arguments
+    ///
+    /// * `tcb` - The TCB, used to refresh the acknowledgement number and window of the segment
+    pub fn retransmit_oldest(&self, tcb: &TransmissionControlBlock) {
+        let mut queue = self.retransmission.lock();
+        if let Some(packet) = queue.retransmit_front() {
+            packet.restamp(tcb.rcv_nxt, tcb.rcv_wnd);
+
+            let sent_size = self.transmit(packet);
+            warn!(
+                "{}",
+                format!("Fast retransmit, segment sent again: {}, with size: {}", packet, sent_size).truecolor(230, 120, 30)
+            );
+        }
+    }
+}
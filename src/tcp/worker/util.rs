@@ -1,37 +1,66 @@
-use std::ops::Deref;
-use std::time::Duration;
+use colored::Colorize;
+use log::warn;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
 
-use tokio::sync::watch::Receiver;
-use tokio::time;
+use crate::tcp::packet::data::{Controller, ReceiveData};
+use crate::tcp::processor::ConnectionEvent;
+use crate::tcp::worker::bus::Subscription;
 
-use crate::tcp::packet::data::{Controller, ReceiveData, SpacilProcessor};
-
-macro_rules! processor {
-    ($self:ident, $receiver:expr, $spacil:expr, $f:expr) => {
-        $self.process_receiver($receiver, $spacil, $f).await
-    };
-}
+/// How many events a connection may be ahead of its processors, far more than it can have in a row.
+pub(crate) const EVENT_CAPACITY: usize = 64;
 
 impl Controller {
-    pub(crate) async fn process_receiver<F>(&self, mut receiver: Receiver<Option<ReceiveData>>, spacil: SpacilProcessor, process: F)
-        where
-            F: Fn(&ReceiveData) -> (),
-    {
-        let mut intv = time::interval(Duration::from_millis(10));
-
+    /// Runs the processors of the connection over every segment it accepts and every event it sees.
+    ///
+    /// # Arguments
+    ///
+    /// * `segments` - The segments the connection accepted, in the order they were received
+    /// * `events` - The events of the connection, subscribed before the first could happen
+    ///
+    /// # Remarks
+    ///
+    /// Events are handed over before the next segment, so the processors of a segment see the transitions the
+    /// segments before it caused. The function returns once the bus of the connection was dropped, after the
+    /// processors saw the remaining events.
+    pub(crate) async fn run_processors(&self, mut segments: Subscription, mut events: broadcast::Receiver<ConnectionEvent>) {
         loop {
-            if let Some(r) = receiver.borrow_and_update().deref() {
-                if spacil != SpacilProcessor::None && spacil != *self.spacil.read().deref() {
-                    continue;
-                }
-
-                process(r);
+            tokio::select! {
+                biased;
+                event = events.recv() => match event {
+                    Ok(event) => self.process_event(&event).await,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("{}", format!("Processors of {} missed {} events", self.address_to_remote, missed).truecolor(230, 120, 30));
+                    }
+                    // The controller holds the sender, this can not happen while it runs
+                    Err(RecvError::Closed) => break,
+                },
+                segment = segments.recv() => match segment {
+                    Some(segment) => self.process_segment(&segment).await,
+                    None => break,
+                },
             }
+        }
+
+        while let Ok(event) = events.try_recv() {
+            self.process_event(&event).await;
+        }
+    }
 
-            if receiver.changed().await.is_err() {
-                break;
+    /// Hands a segment to every enabled processor meant for the current state.
+    async fn process_segment(&self, segment: &ReceiveData) {
+        for processor in self.processors.enabled() {
+            let states = processor.states();
+            if states.is_empty() || states.contains(&self.current_state()) {
+                processor.on_segment(self, segment).await;
             }
-            intv.tick().await;
         }
     }
-}
\ No newline at end of file
+
+    /// Hands an event to every enabled processor.
+    async fn process_event(&self, event: &ConnectionEvent) {
+        for processor in self.processors.enabled() {
+            processor.on_event(self, event).await;
+        }
+    }
+}
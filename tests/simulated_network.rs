@@ -0,0 +1,545 @@
+//! Connections between two tables of this stack over a simulated network, no root or peer needed.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Notify;
+use tokio::time::{sleep, timeout};
+
+use tcp_test::RawTcpStream;
+use tcp_test::tcp::capture::{CapturedSegment, PcapWriter, read_capture};
+use tcp_test::tcp::connection_table::{ConnectionTable, UnknownSegmentPolicy};
+use tcp_test::tcp::link::device::Link;
+use tcp_test::tcp::link::simulated::{SimulatedLink, SimulationSettings, SimulationStatistics};
+use tcp_test::tcp::main_loop::{read_segment, receive_packet};
+use tcp_test::tcp::packet::data::{CloseReason, Controller, ReceiveData, RemoteSockaddr, TcpState};
+use tcp_test::tcp::packet::ip_header::IpHeader;
+use tcp_test::tcp::replay::replay;
+use tcp_test::tcp::processor::{ConnectionEvent, PacketProcessor, DATA_PROCESSOR, HANDSHAKE_PROCESSOR, PRINTER_PROCESSOR, WAVE_PROCESSOR};
+use tcp_test::tcp::settings::{Keepalive, Settings};
+
+const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+const SERVER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+const PORT: u16 = 7;
+
+/// What every test is given up to, a stuck connection fails it rather than hanging.
+const TEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The stream of bytes the server sends, with no period a misplaced segment could hide in.
+fn payload(length: usize) -> Vec<u8> {
+    (0..length).map(|index| (index % 251) as u8 ^ (index / 251) as u8).collect()
+}
+
+/// Closes a channel when a test ends, however it ends, so the runtime is not left waiting for the receive loops.
+struct CloseOnDrop(Arc<SimulatedLink>);
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// A client table and a server table listening on `PORT`, over a simulated channel, with their receive loops running.
+struct Network {
+    client: IpAddr,
+    server: IpAddr,
+    client_link: Arc<SimulatedLink>,
+    server_link: Arc<SimulatedLink>,
+    client_table: Arc<ConnectionTable>,
+    server_table: Arc<ConnectionTable>,
+    _channel: CloseOnDrop,
+}
+
+impl Network {
+    /// An IPv4 network whose server drops the segments of no connection.
+    fn new(channel: SimulationSettings) -> Self {
+        Network::between(channel, CLIENT, SERVER, UnknownSegmentPolicy::Drop)
+    }
+
+    /// A network between two addresses, whose server treats the segments of no connection as `unknown_segments` says.
+    fn between(channel: SimulationSettings, client: IpAddr, server: IpAddr, unknown_segments: UnknownSegmentPolicy) -> Self {
+        Network::build(channel, client, server, unknown_segments, None)
+    }
+
+    /// An IPv4 network whose client records every packet it sends and receives.
+    fn capturing(capture: Arc<PcapWriter>) -> Self {
+        Network::build(SimulationSettings::default(), CLIENT, SERVER, UnknownSegmentPolicy::Drop, Some(capture))
+    }
+
+    fn build(
+        channel: SimulationSettings,
+        client: IpAddr,
+        server: IpAddr,
+        unknown_segments: UnknownSegmentPolicy,
+        capture: Option<Arc<PcapWriter>>,
+    ) -> Self {
+        let (client_link, server_link) = SimulatedLink::pair(channel);
+        let client_table = ConnectionTable::new(client_link.clone());
+        let client_table = Arc::new(match capture {
+            Some(capture) => client_table.with_capture(capture),
+            None => client_table,
+        });
+        let server_table = Arc::new(ConnectionTable::new(server_link.clone()).with_unknown_segment_policy(unknown_segments));
+        server_table.listen(PORT, Settings { source_address: Some(server), ..Default::default() });
+        tokio::spawn(receive_packet(client_table.clone()));
+        tokio::spawn(receive_packet(server_table.clone()));
+
+        Network {
+            client,
+            server,
+            _channel: CloseOnDrop(client_link.clone()),
+            client_link,
+            server_link,
+            client_table,
+            server_table,
+        }
+    }
+
+    /// The address of the client's connections to the server.
+    fn address(&self, port: u16) -> String {
+        SocketAddr::new(self.server, port).to_string()
+    }
+
+    /// Opens a connection from the client to the server.
+    ///
+    /// # Returns
+    ///
+    /// * `(RawTcpStream, Arc<Controller>)` - The client's stream and the connection the server accepted, once both
+    ///   ends completed the handshake
+    async fn connect(&self, settings: Settings) -> (RawTcpStream, Arc<Controller>) {
+        let settings = Settings { source_address: Some(self.client), ..settings };
+        let stream = RawTcpStream::connect_with(&self.client_table, &self.address(PORT), settings).await.expect("the handshake completes");
+
+        // The connection is in the server table once its SYN arrived, the handshake may still be completing
+        let accepted = loop {
+            if let Some(controller) = self.server_table.controllers().pop() {
+                break controller;
+            }
+            sleep(Duration::from_millis(1)).await;
+        };
+        accepted.wait_synchronized().await.expect("the server completes the handshake");
+
+        (stream, accepted)
+    }
+}
+
+/// Records what the processors of a connection are handed.
+#[derive(Default)]
+struct Recorder {
+    segments: Mutex<u64>,
+    events: Mutex<Vec<ConnectionEvent>>,
+    /// The bytes that became contiguous in the receive stream
+    data: Mutex<Vec<u8>>,
+    /// Notified after every segment and event
+    changed: Notify,
+}
+
+impl Recorder {
+    /// Waits until a condition holds, it is checked again after every segment and event.
+    async fn wait_for(&self, condition: impl Fn(&Recorder) -> bool) {
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            if condition(self) {
+                return;
+            }
+            changed.await;
+        }
+    }
+
+    fn segments(&self) -> u64 {
+        *self.segments.lock()
+    }
+}
+
+#[async_trait]
+impl PacketProcessor for Recorder {
+    async fn on_segment(&self, _controller: &Controller, segment: &ReceiveData) {
+        *self.segments.lock() += 1;
+        self.data.lock().extend_from_slice(&segment.delivered().data);
+        self.changed.notify_waiters();
+    }
+
+    async fn on_event(&self, _controller: &Controller, event: &ConnectionEvent) {
+        self.events.lock().push(event.clone());
+        self.changed.notify_waiters();
+    }
+}
+
+/// Has the server send `data` to a client over a simulated channel and close, and returns what the client read with
+/// what the channel did in each direction.
+///
+/// The client closes its side once it read the server's FIN, and the transfer is over when both ends reached CLOSED.
+async fn transfer(settings: SimulationSettings, client: IpAddr, server: IpAddr, data: &[u8]) -> (Vec<u8>, SimulationStatistics, SimulationStatistics) {
+    let network = Network::between(settings, client, server, UnknownSegmentPolicy::Drop);
+
+    let received = timeout(TEST_TIMEOUT, async {
+        let (mut stream, accepted) = network.connect(Settings::default()).await;
+        accepted.write(data).expect("the server can send");
+        accepted.close();
+
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.expect("the stream ends with the server's FIN");
+        stream.shutdown().await.expect("the client closes its side");
+        assert_eq!(stream.controller().wait_closed().await, CloseReason::Closed);
+        assert_eq!(accepted.wait_closed().await, CloseReason::Closed);
+        received
+    }).await.expect("the transfer finishes in time");
+
+    (received, network.server_link.statistics(), network.client_link.statistics())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transfers_data_over_a_perfect_channel() {
+    let data = payload(200_000);
+    let (received, server, client) = transfer(SimulationSettings::default(), CLIENT, SERVER, &data).await;
+
+    assert_eq!(received.len(), data.len());
+    assert!(received == data, "the data arrives unchanged");
+    assert_eq!(server.lost + client.lost, 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn transfers_data_over_ipv6() {
+    let data = payload(50_000);
+    let client = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+    let server = IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2));
+    let (received, _, _) = transfer(SimulationSettings::default(), client, server, &data).await;
+
+    assert!(received == data, "the data arrives unchanged");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recovers_from_loss_reordering_duplication_and_corruption() {
+    let settings = SimulationSettings {
+        loss: 0.05,
+        reorder: 0.05,
+        duplicate: 0.05,
+        corrupt: 0.02,
+        latency: Duration::from_millis(1),
+        jitter: Duration::from_millis(2),
+        seed: 7,
+        ..Default::default()
+    };
+    let data = payload(100_000);
+    let (received, server, client) = transfer(settings, CLIENT, SERVER, &data).await;
+
+    assert!(received == data, "the data arrives unchanged");
+    // The channel really did get in the way
+    assert!(server.lost > 0 && server.reordered > 0 && server.duplicated > 0, "{:?}", server);
+    assert!(server.corrupted + client.corrupted > 0, "{:?} {:?}", server, client);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn closes_simultaneously() {
+    let network = Network::new(SimulationSettings::default());
+
+    timeout(TEST_TIMEOUT, async {
+        let (stream, accepted) = network.connect(Settings::default()).await;
+
+        // Both FINs are on their way before either end saw the other's, each is followed closely by an ACK
+        let client = stream.controller().clone();
+        accepted.close();
+        client.close();
+
+        assert_eq!(client.wait_closed().await, CloseReason::Closed);
+        assert_eq!(accepted.wait_closed().await, CloseReason::Closed);
+    }).await.expect("both ends close in time");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keeps_writing_after_the_remote_half_closed() {
+    let network = Network::new(SimulationSettings::default());
+    let recorder = Arc::new(Recorder::default());
+    network.server_table.processors.add("recorder", 0, recorder.clone()).unwrap();
+
+    let data = payload(30_000);
+    timeout(TEST_TIMEOUT, async {
+        let (mut stream, accepted) = network.connect(Settings::default()).await;
+
+        // The server is done sending, the client reads its FIN and answers for a while longer
+        accepted.close();
+        assert!(accepted.write(b"too late").is_err(), "nothing is sent after the FIN");
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.expect("the stream ends with the server's FIN");
+        assert!(received.is_empty());
+        assert_eq!(stream.controller().current_state(), TcpState::CloseWait);
+
+        stream.write_all(&data).await.expect("a half-closed stream can still be written to");
+        stream.shutdown().await.expect("the client closes its side");
+        let late = stream.write_all(b"too late").await;
+        assert_eq!(late.err().map(|e| e.kind()), Some(std::io::ErrorKind::BrokenPipe));
+        assert_eq!(stream.controller().wait_closed().await, CloseReason::Closed);
+        assert_eq!(accepted.wait_closed().await, CloseReason::Closed);
+    }).await.expect("both ends close in time");
+
+    assert!(*recorder.data.lock() == data, "the server receives everything written after its FIN");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_connections_to_ports_nobody_listens_on() {
+    let network = Network::between(SimulationSettings::default(), CLIENT, SERVER, UnknownSegmentPolicy::Reset);
+
+    let settings = Settings { source_address: Some(CLIENT), ..Default::default() };
+    let connected = timeout(TEST_TIMEOUT, RawTcpStream::connect_with(&network.client_table, &network.address(PORT + 1), settings))
+        .await
+        .expect("the RST arrives before the SYN would be given up");
+
+    assert_eq!(connected.err().map(|e| e.kind()), Some(std::io::ErrorKind::ConnectionRefused));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resets_connections_the_remote_forgot() {
+    let network = Network::between(SimulationSettings::default(), CLIENT, SERVER, UnknownSegmentPolicy::Reset);
+
+    timeout(TEST_TIMEOUT, async {
+        let (mut stream, accepted) = network.connect(Settings::default()).await;
+
+        // The server drops the connection without telling the client, which learns of it with its next segment. The
+        // segment is answered with a RST whether or not the closed connection has left the table yet
+        accepted.abort("forgotten");
+        stream.write_all(b"anyone there?").await.expect("the data is queued");
+
+        let mut received = Vec::new();
+        let read = stream.read_to_end(&mut received).await;
+        assert_eq!(read.err().map(|e| e.kind()), Some(std::io::ErrorKind::ConnectionReset));
+        assert_eq!(stream.controller().wait_closed().await, CloseReason::Reset);
+    }).await.expect("the client learns of the reset in time");
+}
+
+/// Keepalive settings that probe within a test's patience.
+const KEEPALIVE: Keepalive = Keepalive {
+    idle: Duration::from_millis(200),
+    interval: Duration::from_millis(100),
+    count: 3,
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keepalive_keeps_a_quiet_connection_alive() {
+    let network = Network::new(SimulationSettings::default());
+    let recorder = Arc::new(Recorder::default());
+    network.client_table.processors.add("recorder", 0, recorder.clone()).unwrap();
+
+    timeout(TEST_TIMEOUT, async {
+        let (stream, _accepted) = network.connect(Settings { keepalive: Some(KEEPALIVE), ..Default::default() }).await;
+        let (probes, answers) = (network.client_link.statistics().sent, recorder.segments());
+
+        // Nothing but the answers to probes arrives on a quiet connection, each of them starting the idle time again
+        recorder.wait_for(|recorder| recorder.segments() >= answers + 3).await;
+
+        assert_eq!(stream.controller().current_state(), TcpState::Established);
+        assert!(network.client_link.statistics().sent >= probes + 3, "{:?}", network.client_link.statistics());
+    }).await.expect("three probes are answered in time");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keepalive_gives_up_on_a_silent_remote() {
+    let network = Network::new(SimulationSettings::default());
+
+    timeout(TEST_TIMEOUT, async {
+        let (stream, _accepted) = network.connect(Settings { keepalive: Some(KEEPALIVE), ..Default::default() }).await;
+
+        // The server is still there, but nothing it sends arrives any more
+        network.server_link.set_settings(SimulationSettings { loss: 1.0, ..Default::default() });
+        let (silent, probes) = (Instant::now(), network.client_link.statistics().sent);
+        let reason = stream.controller().wait_closed().await;
+
+        assert_eq!(reason, CloseReason::KeepaliveTimeout);
+        // The connection is given up one interval after the last of its probes, never earlier
+        assert!(silent.elapsed() >= KEEPALIVE.idle + KEEPALIVE.interval * KEEPALIVE.count);
+        assert_eq!(network.client_link.statistics().sent - probes, KEEPALIVE.count as u64);
+    }).await.expect("the client gives up in time");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn probes_a_zero_window_until_it_opens() {
+    const WINDOW: u16 = 2000;
+    let network = Network::new(SimulationSettings::default());
+    let (client_recorder, server_recorder) = (Arc::new(Recorder::default()), Arc::new(Recorder::default()));
+    network.client_table.processors.add("recorder", 0, client_recorder.clone()).unwrap();
+    network.server_table.processors.add("recorder", 0, server_recorder.clone()).unwrap();
+
+    let data = payload(10_000);
+    timeout(TEST_TIMEOUT, async {
+        let (mut stream, accepted) = network.connect(Settings { receive_window: WINDOW, ..Default::default() }).await;
+        accepted.write(&data).expect("the server can send");
+        accepted.close();
+
+        // The client reads nothing, so its window fills up and the server has to wait with nothing in flight
+        server_recorder.wait_for(|_| accepted.persist.lock().is_running()).await;
+        // The client acknowledged every segment it received, no late ACK can tell the server about the read below
+        client_recorder.wait_for(|recorder| recorder.segments() == network.server_link.statistics().sent).await;
+
+        // The window update the read makes is lost, only a probe finds out the window opened
+        network.client_link.set_settings(SimulationSettings { loss: 1.0, ..Default::default() });
+        let mut received = vec![0; WINDOW as usize];
+        stream.read_exact(&mut received).await.expect("the first window of data was received");
+        network.client_link.set_settings(SimulationSettings::default());
+        assert!(network.client_link.statistics().lost > 0, "{:?}", network.client_link.statistics());
+
+        stream.read_to_end(&mut received).await.expect("the stream ends with the server's FIN");
+        assert!(received == data, "the data arrives unchanged");
+    }).await.expect("the transfer finishes in time");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn processors_see_every_segment_and_event() {
+    let network = Network::new(SimulationSettings::default());
+    let recorder = Arc::new(Recorder::default());
+    network.client_table.processors.add("recorder", 0, recorder.clone()).unwrap();
+
+    let data = payload(20_000);
+    let received = timeout(TEST_TIMEOUT, async {
+        let (mut stream, accepted) = network.connect(Settings::default()).await;
+        let processors = &stream.controller().processors;
+        assert_eq!(processors.names(), [PRINTER_PROCESSOR, HANDSHAKE_PROCESSOR, DATA_PROCESSOR, WAVE_PROCESSOR, "recorder"]);
+        assert!(processors.disable(PRINTER_PROCESSOR));
+        assert!(processors.prioritize("recorder", 1_000));
+        assert_eq!(processors.names()[0], "recorder");
+
+        accepted.write(&data).expect("the server can send");
+        accepted.close();
+
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.expect("the stream ends with the server's FIN");
+        // The stream stays in CLOSE-WAIT until it is shut down
+        assert_eq!(stream.controller().current_state(), TcpState::CloseWait);
+        stream.shutdown().await.expect("the client closes its side");
+        assert_eq!(stream.controller().wait_closed().await, CloseReason::Closed);
+
+        // The last events are published as the connection closes, right before its processors stop
+        recorder.wait_for(|recorder| recorder.events.lock().last() == Some(&ConnectionEvent::Closed(CloseReason::Closed))).await;
+        received
+    }).await.expect("the transfer finishes in time");
+    assert!(received == data, "the data arrives unchanged");
+
+    let states: Vec<TcpState> = recorder.events.lock().iter().filter_map(|event| match event {
+        ConnectionEvent::StateChanged { to, .. } => Some(*to),
+        ConnectionEvent::Closed(_) => None,
+    }).collect();
+    assert_eq!(states, [TcpState::SynSent, TcpState::Established, TcpState::CloseWait, TcpState::LastAck, TcpState::Closed]);
+    // At least the SYN-ACK, every data segment and the FIN
+    assert!(recorder.segments() as usize > data.len() / 1460, "{} segments", recorder.segments());
+}
+
+#[test]
+fn same_seed_makes_the_same_decisions() {
+    let settings = SimulationSettings {
+        loss: 0.2,
+        reorder: 0.2,
+        duplicate: 0.2,
+        corrupt: 0.2,
+        reorder_delay: Duration::from_millis(50),
+        seed: 42,
+        ..Default::default()
+    };
+    let ip_head = IpHeader::new(CLIENT, SERVER, 20).unwrap();
+    let remote = RemoteSockaddr::new(&SERVER.to_string(), PORT).unwrap();
+
+    let run = || {
+        let (sender, receiver) = SimulatedLink::pair(settings);
+        receiver.set_receive_timeout(Duration::from_millis(100)).unwrap();
+        for index in 0..200u8 {
+            sender.send(&ip_head, &[index; 40], &remote);
+        }
+
+        let mut delivered = Vec::new();
+        while let Some((packet, _)) = receiver.receive() {
+            delivered.push(packet);
+        }
+        (sender.statistics(), delivered)
+    };
+
+    let (statistics, delivered) = run();
+    assert_eq!(statistics.sent, 200);
+    assert_eq!(delivered.len() as u64, statistics.sent - statistics.lost + statistics.duplicated);
+    assert_eq!(run(), (statistics, delivered));
+}
+
+/// Waits until the remote acknowledged everything a connection sent.
+async fn wait_acknowledged(controller: &Controller) {
+    while !controller.tcb.read().all_acknowledged() {
+        sleep(Duration::from_millis(1)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_a_recorded_session_identically() {
+    let path = std::env::temp_dir().join(format!("simulated-network-replay-{}.pcap", std::process::id()));
+
+    // Each segment of the client is answered before the next one is sent, so the answers of the recording are
+    // grouped as they will be in the replay
+    let recording = Network::capturing(Arc::new(PcapWriter::create(&path).expect("the capture can be created")));
+    timeout(TEST_TIMEOUT, async {
+        let (mut stream, accepted) = recording.connect(Settings::default()).await;
+        stream.write_all(b"hello").await.expect("the data is queued");
+        wait_acknowledged(stream.controller()).await;
+
+        // Nobody reads on the server, which closes as soon as the client did
+        stream.shutdown().await.expect("the client closes its side");
+        assert_eq!(accepted.wait_closed().await, CloseReason::Closed);
+    }).await.expect("the recorded session finishes in time");
+    drop(recording);
+
+    let segments = read_capture(&path).expect("the capture can be read");
+    std::fs::remove_file(&path).ok();
+    let (sent, answered): (Vec<_>, Vec<_>) = segments.iter().partition(|segment| segment.source.ip() == CLIENT);
+    assert!(segments.iter().any(|segment| segment.payload == b"hello"), "{:?}", segments);
+
+    // The same server again, with the replay in place of the client
+    let (link, server_link) = SimulatedLink::pair(SimulationSettings::default());
+    let _channel = CloseOnDrop(link.clone());
+    let server_table = Arc::new(ConnectionTable::new(server_link));
+    server_table.listen(PORT, Settings { source_address: Some(SERVER), ..Default::default() });
+    tokio::spawn(receive_packet(server_table.clone()));
+
+    let settings = Settings { source_address: Some(CLIENT), ..Default::default() };
+    let report = timeout(TEST_TIMEOUT, replay(&segments, link, SocketAddr::new(SERVER, PORT), settings, Duration::from_secs(5), None))
+        .await
+        .expect("the replay finishes in time")
+        .expect("the replay runs");
+
+    assert!(report.is_identical(), "{:?}", report.differences);
+    assert_eq!((report.sent, report.expected, report.received), (sent.len(), answered.len(), answered.len()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn accepts_data_right_behind_the_syn_ack() {
+    let (client_link, peer_link) = SimulatedLink::pair(SimulationSettings::default());
+    let _channel = CloseOnDrop(client_link.clone());
+    let client_table = Arc::new(ConnectionTable::new(client_link));
+    tokio::spawn(receive_packet(client_table.clone()));
+
+    // The peer answers the SYN like a listener, and sends its data and FIN right behind the SYN-ACK before the client
+    // had a chance to move to ESTABLISHED. It never retransmits, so nothing it sent may be dropped
+    let peer = tokio::task::spawn_blocking(move || {
+        let mut syn = read_segment(peer_link.as_ref()).expect("the client's SYN arrives");
+        let remote_port = CapturedSegment::from(&syn).source.port();
+        let settings = Settings { source_address: Some(SERVER), ..Default::default() };
+        let peer = Controller::new(peer_link, PORT, &CLIENT.to_string(), remote_port, settings).unwrap();
+
+        peer.transition(TcpState::Listen);
+        peer.update_tcb(&mut syn);
+        peer.send_packet_with_state(&mut peer.make_packet_with_none().to_second_handshake(true), TcpState::SynReceived);
+        peer.send_packet(&mut peer.make_packet_with_data(&b"hello"[..]).to_data_packet());
+        peer.send_packet(&mut peer.make_packet_with_none().to_fin_packet());
+    });
+
+    let received = timeout(TEST_TIMEOUT, async {
+        let settings = Settings { source_address: Some(CLIENT), ..Default::default() };
+        let mut stream = RawTcpStream::connect_with(&client_table, &SocketAddr::new(SERVER, PORT).to_string(), settings)
+            .await
+            .expect("the handshake completes");
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.expect("the stream ends with the peer's FIN");
+        received
+    }).await.expect("nothing behind the SYN-ACK is dropped");
+
+    peer.await.unwrap();
+    assert_eq!(received, b"hello");
+}